@@ -0,0 +1,77 @@
+//! A bot that keeps its bat level with the ball, using the bot API of a
+//! running server:
+//!
+//!     cargo run --example tracking_bot -- [HOST:PORT]
+//!
+//! It registers at `POST /bot/register`, then observes the board at
+//! `GET /bot/observe` and moves towards the ball with `POST /bot/act` until
+//! it's stopped or the server goes away.
+
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+/// How far the ball may be off the bat's center before the bot moves.
+const DEAD_ZONE: i64 = 40;
+
+fn main() {
+    let address = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "[::1]:3000".to_string());
+    let registration = request(&address, "POST", "/bot/register", None, "");
+    let token = registration["token"].as_str().expect("registered").to_string();
+    let seat = &registration["seat"];
+    let side = seat["side"].as_str().expect("seat has a side").to_string();
+    let bat = seat["bat"].as_u64().expect("seat has a bat") as usize - 1;
+    println!("Playing {side} bat {}", bat + 1);
+    loop {
+        let view = request(&address, "GET", "/bot/observe", Some(&token), "");
+        if view["phase"] != "running" {
+            continue;
+        }
+        let ball_y = view["ball"][1].as_i64().expect("ball has a position");
+        let (position, height) = (&view[side.as_str()][bat][0], &view[side.as_str()][bat][1]);
+        let center = position.as_i64().expect("bat has a position")
+            + height.as_i64().expect("bat has a height") / 2;
+        let direction = if ball_y < center - DEAD_ZONE {
+            "up"
+        } else if ball_y > center + DEAD_ZONE {
+            "down"
+        } else {
+            continue;
+        };
+        request(
+            &address,
+            "POST",
+            "/bot/act",
+            Some(&token),
+            &format!("direction={direction}"),
+        );
+    }
+}
+
+/// Send a form request and parse the JSON response, exiting on errors.
+fn request(address: &str, method: &str, path: &str, token: Option<&str>, form: &str) -> Value {
+    let mut stream = TcpStream::connect(address).unwrap_or_else(|err| {
+        eprintln!("can't connect to {address}: {err}");
+        std::process::exit(1)
+    });
+    let authorization = token.map_or(String::new(), |token| {
+        format!("Authorization: Bearer {token}\r\n")
+    });
+    write!(
+        stream,
+        "{method} {path} HTTP/1.1\r\nHost: {address}\r\nConnection: close\r\n{authorization}\
+         Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{form}",
+        form.len()
+    )
+    .expect("request is sent");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("response is read");
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    if !head.starts_with("HTTP/1.1 200") {
+        eprintln!("{method} {path} failed: {} {body}", head.lines().next().unwrap_or(""));
+        std::process::exit(1)
+    }
+    serde_json::from_str(body).expect("response is JSON")
+}
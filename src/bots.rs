@@ -0,0 +1,89 @@
+use crate::Side;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Bots get a new observation at most this often, about once per tick.
+const OBSERVE_INTERVAL: Duration = Duration::from_millis(32);
+
+/// A bat, as taken over by a bot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub struct Seat {
+    pub side: Side,
+    /// Which of the side's stacked bats, from 1.
+    pub bat: usize,
+}
+
+struct Bot {
+    seat: Seat,
+    observed_at: Option<Instant>,
+    /// The tick the bot last moved its bat in.
+    moved_at: Option<u64>,
+}
+
+/// External programs controlling bats, by the token they registered with.
+#[derive(Clone, Default)]
+pub struct BotSeats {
+    bots: Arc<Mutex<HashMap<String, Bot>>>,
+}
+
+impl BotSeats {
+    /// Bind a new token to the first of `seats` no other bot holds, or `None`
+    /// if they are all taken.
+    pub fn register(&self, seats: impl IntoIterator<Item = Seat>) -> Option<(String, Seat)> {
+        let mut bots = self.bots.lock().unwrap();
+        let seat = seats
+            .into_iter()
+            .find(|seat| bots.values().all(|bot| bot.seat != *seat))?;
+        let token = crate::random_token();
+        bots.insert(
+            token.clone(),
+            Bot {
+                seat,
+                observed_at: None,
+                moved_at: None,
+            },
+        );
+        Some((token, seat))
+    }
+
+    /// Free the token's seat, returning `false` if it wasn't registered.
+    pub fn release(&self, token: &str) -> bool {
+        self.bots.lock().unwrap().remove(token).is_some()
+    }
+
+    pub fn seat(&self, token: &str) -> Option<Seat> {
+        self.bots.lock().unwrap().get(token).map(|bot| bot.seat)
+    }
+
+    /// The seats taken by bots, ordered by side and bat.
+    pub fn seats(&self) -> Vec<Seat> {
+        let mut seats: Vec<Seat> = self.bots.lock().unwrap().values().map(|bot| bot.seat).collect();
+        seats.sort_by_key(|seat| (seat.side == Side::Right, seat.bat));
+        seats
+    }
+
+    /// How long the bot has to wait for its next observation.
+    pub fn observe(&self, token: &str) -> Option<Duration> {
+        let mut bots = self.bots.lock().unwrap();
+        let bot = bots.get_mut(token)?;
+        let now = Instant::now();
+        let wait = bot
+            .observed_at
+            .map_or(Duration::ZERO, |at| OBSERVE_INTERVAL.saturating_sub(now - at));
+        bot.observed_at = Some(now + wait);
+        Some(wait)
+    }
+
+    /// Claim the bot's one move of `tick`, returning `false` if it already moved.
+    pub fn claim_move(&self, token: &str, tick: u64) -> Option<bool> {
+        let mut bots = self.bots.lock().unwrap();
+        let bot = bots.get_mut(token)?;
+        if bot.moved_at == Some(tick) {
+            return Some(false);
+        }
+        bot.moved_at = Some(tick);
+        Some(true)
+    }
+}
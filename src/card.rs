@@ -0,0 +1,400 @@
+use crate::config::TICK_HZ;
+use crate::history::{FinishedGame, KEPT_GAMES};
+use crate::rules::Scoring;
+use crate::Side;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Size of a card in pixels, about what chat apps show of a link preview.
+const WIDTH: usize = 800;
+const HEIGHT: usize = 420;
+
+/// The colors of the board, indexed by every pixel with 2 bits.
+const PALETTE: [[u8; 3]; 4] = [
+    [0x33, 0x33, 0x33],
+    [0xee, 0xee, 0xee],
+    [0x99, 0x99, 0x99],
+    [0x44, 0xcc, 0x44],
+];
+const BACKGROUND: u8 = 0;
+const FOREGROUND: u8 = 1;
+const DIM: u8 = 2;
+const ACCENT: u8 = 3;
+
+/// Glyphs are 5 by 7 pixels, with a column of space after each.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+
+/// The rows of `c`'s glyph from the top, the leftmost pixel in the highest of
+/// the 5 bits. Lowercase letters are drawn as capitals, characters the font
+/// lacks as a question mark.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        ' ' => [0; GLYPH_HEIGHT],
+        ':' => [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        '+' => [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+        '_' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+        '.' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+        ',' => [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000],
+        '!' => [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100],
+        '\'' => [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000],
+        '/' => [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000],
+        '(' => [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+        ')' => [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+        '#' => [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+        '&' => [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101],
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100],
+    }
+}
+
+/// Palette indices of every pixel, row by row.
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Self {
+            pixels: vec![BACKGROUND; WIDTH * HEIGHT],
+        }
+    }
+
+    /// Paint the rectangle at `x`, `y`, cut off at the edges.
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u8) {
+        for row in y.min(HEIGHT)..(y + height).min(HEIGHT) {
+            let start = row * WIDTH;
+            self.pixels[start + x.min(WIDTH)..start + (x + width).min(WIDTH)].fill(color);
+        }
+    }
+
+    /// Write `text` centered on `center` at `y`, every pixel of the font
+    /// `scale` pixels wide, leaving out the characters that don't fit.
+    fn centered(&mut self, text: &str, center: usize, y: usize, scale: usize, color: u8) {
+        let room = 2 * center.min(WIDTH - center);
+        let fitting = (room / (ADVANCE * scale)).saturating_sub(1);
+        let text: Vec<char> = text.chars().take(fitting).collect();
+        let width = (text.len() * ADVANCE).saturating_sub(1) * scale;
+        let mut x = center - width / 2;
+        for c in text {
+            for (row, bits) in glyph(c).into_iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        let (px, py) = (x + column * scale, y + row * scale);
+                        self.fill(px, py, scale, scale, color);
+                    }
+                }
+            }
+            x += ADVANCE * scale;
+        }
+    }
+}
+
+/// Draw the result of `game` under the instance's `title`.
+fn draw(game: &FinishedGame, title: &str) -> Canvas {
+    let mut canvas = Canvas::new();
+    // A bat on each side and the ball, like the board.
+    canvas.fill(20, 160, 10, 100, FOREGROUND);
+    canvas.fill(WIDTH - 30, 160, 10, 100, FOREGROUND);
+    canvas.fill(WIDTH - 90, 100, 10, 10, FOREGROUND);
+    let middle = WIDTH / 2;
+    canvas.centered(title, middle, 30, 3, DIM);
+    let sides = [(game.left, "Left", middle - 160), (game.right, "Right", middle + 160)];
+    for (score, name, center) in sides {
+        canvas.centered(&score.to_string(), center, 80, 14, FOREGROUND);
+        canvas.centered(name, center, 190, 3, DIM);
+    }
+    canvas.centered(":", middle, 80, 14, FOREGROUND);
+    let outcome = match game.winner {
+        Some(Side::Left) => "Left side wins",
+        Some(Side::Right) => "Right side wins",
+        None => "Game over",
+    };
+    canvas.centered(outcome, middle, 240, 5, ACCENT);
+    let scoring = match game.scoring {
+        Scoring::Classic => "Classic",
+        Scoring::Survival => "Survival",
+    };
+    let seconds = game.timeline.last().map_or(0, |point| point.tick) / u64::from(TICK_HZ);
+    let details = format!("{scoring} - {}:{:02}", seconds / 60, seconds % 60);
+    canvas.centered(&details, middle, 310, 3, DIM);
+    if game.demo {
+        canvas.centered("Demo data", middle, 360, 3, DIM);
+    }
+    canvas
+}
+
+/// The card of `game`, read from `dir` if it was drawn before, and written
+/// there otherwise by way of a temporary file, so that no half written card is
+/// ever read. Failing to write it is only logged.
+pub fn cached(dir: &Path, game: &FinishedGame, title: &str) -> Vec<u8> {
+    let path = dir.join(format!("{}.png", game.id));
+    if let Ok(png) = std::fs::read(&path) {
+        return png;
+    }
+    let png = encode(&draw(game, title).pixels);
+    // Of this request alone, in case the card is asked for twice at once.
+    let temporary = dir.join(format!("{}.{}.tmp", game.id, crate::random_token()));
+    let written = std::fs::create_dir_all(dir)
+        .and_then(|()| std::fs::write(&temporary, &png))
+        .and_then(|()| std::fs::rename(&temporary, &path));
+    match written {
+        Ok(()) => prune(dir),
+        Err(err) => {
+            let _ = std::fs::remove_file(&temporary);
+            eprintln!("can't write card {}: {err}", path.display());
+        }
+    }
+    png
+}
+
+/// Delete the oldest cards beyond one for each game the history keeps,
+/// ignoring errors.
+fn prune(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut cards: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let path = entry.path();
+            if path.extension()? != "png" {
+                return None;
+            }
+            Some((entry.metadata().ok()?.modified().ok()?, path))
+        })
+        .collect();
+    cards.sort();
+    let excess = cards.len().saturating_sub(KEPT_GAMES);
+    for (_, path) in &cards[..excess] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// `pixels` as a PNG with the palette. The image data is stored rather than
+/// compressed, which stays under 100 KB at 2 bits per pixel.
+fn encode(pixels: &[u8]) -> Vec<u8> {
+    let mut image = Vec::with_capacity((WIDTH / 4 + 1) * HEIGHT);
+    for row in pixels.chunks(WIDTH) {
+        // Each row starts with its filter, none.
+        image.push(0);
+        // Four pixels to a byte, the leftmost in the highest bits.
+        image.extend(row.chunks(4).map(|four| four.iter().fold(0, |byte, &px| byte << 2 | px)));
+    }
+    let mut header = Vec::new();
+    header.extend((WIDTH as u32).to_be_bytes());
+    header.extend((HEIGHT as u32).to_be_bytes());
+    // Bit depth 2, indexed color, default compression, filters and no interlacing.
+    header.extend([2, 3, 0, 0, 0]);
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"PLTE", PALETTE.as_flattened());
+    chunk(&mut png, b"IDAT", &zlib_stored(&image));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks = data.chunks(u16::MAX.into());
+    let count = blocks.len();
+    for (index, block) in blocks.enumerate() {
+        stream.push(u8::from(index + 1 == count));
+        let length = block.len() as u16;
+        stream.extend(length.to_le_bytes());
+        stream.extend((!length).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { crc >> 1 ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65_521;
+        b = (b + a) % 65_521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn game(id: &str) -> FinishedGame {
+        FinishedGame {
+            id: id.to_string(),
+            winner: Some(Side::Left),
+            left: 11,
+            right: 7,
+            timeline: Vec::new(),
+            scoring: Scoring::Classic,
+            demo: false,
+            assisted: Vec::new(),
+        }
+    }
+
+    /// The chunks of `png` by kind, checking each one's CRC.
+    fn chunks(png: &[u8]) -> Vec<([u8; 4], &[u8])> {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (rest[4..8].try_into().unwrap(), &rest[8..8 + length]);
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + length]), "CRC of {:?}", kind);
+            chunks.push((kind, data));
+            rest = &rest[12 + length..];
+        }
+        chunks
+    }
+
+    /// The image data of a zlib stream of stored blocks, checking its Adler-32.
+    fn unstore(stream: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut rest = &stream[2..];
+        loop {
+            let length = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(!length, u16::from_le_bytes([rest[3], rest[4]]));
+            data.extend(&rest[5..5 + usize::from(length)]);
+            let last = rest[0] == 1;
+            rest = &rest[5 + usize::from(length)..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    fn scratch_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("rspong-cards-{}", crate::random_token()))
+    }
+
+    #[test]
+    fn checksums_match_the_standard_check_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn cards_are_800_by_420_with_four_colors() {
+        let pixels = draw(&game("abc"), "Pong").pixels;
+        let png = encode(&pixels);
+        let chunks = chunks(&png);
+        let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"PLTE", b"IDAT", b"IEND"]);
+        let header = chunks[0].1;
+        assert_eq!(u32::from_be_bytes(header[..4].try_into().unwrap()), 800);
+        assert_eq!(u32::from_be_bytes(header[4..8].try_into().unwrap()), 420);
+        assert_eq!(&header[8..], [2, 3, 0, 0, 0]);
+        assert_eq!(chunks[1].1.len(), 4 * 3);
+        let image = unstore(chunks[2].1);
+        assert_eq!(image.len(), (WIDTH / 4 + 1) * HEIGHT);
+        let unpacked: Vec<u8> = image
+            .chunks(WIDTH / 4 + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0, "rows aren't filtered");
+                row[1..].iter().flat_map(|byte| [6, 4, 2, 0].map(|shift| byte >> shift & 3))
+            })
+            .collect();
+        assert!(unpacked == pixels, "the pixels don't survive encoding");
+        assert!(png.len() < 100_000);
+    }
+
+    #[test]
+    fn a_cached_card_is_read_back_rather_than_drawn() {
+        let dir = scratch_dir();
+        let drawn = cached(&dir, &game("abc"), "Pong");
+        assert_eq!(std::fs::read(dir.join("abc.png")).unwrap(), drawn);
+        std::fs::write(dir.join("abc.png"), b"kept").unwrap();
+        assert_eq!(cached(&dir, &game("abc"), "Pong"), b"kept");
+        let names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["abc.png"], "no temporary file is left behind");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_cards_of_the_kept_games_stay() {
+        let dir = scratch_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let long_ago = SystemTime::now() - Duration::from_secs(3600);
+        for index in 0..KEPT_GAMES + 5 {
+            let path = dir.join(format!("old{index:02}.png"));
+            std::fs::write(&path, b"card").unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(long_ago + Duration::from_secs(index as u64)).unwrap();
+        }
+        cached(&dir, &game("new"), "Pong");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), KEPT_GAMES);
+        assert!(dir.join("new.png").exists());
+        assert!(!dir.join("old05.png").exists());
+        assert!(dir.join("old06.png").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use crate::splitmix64;
+use axum::Form;
+use serde::Deserialize;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Where faults are injected.
+#[derive(Clone, Copy)]
+pub enum Point {
+    /// Updates the render task receives.
+    Render,
+    /// Events sent to the subscribers, held up in the render task only.
+    Broadcast,
+    /// Input handlers about to lock the game.
+    Lock,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Fault {
+    delay: Duration,
+    /// Percent of the items dropped.
+    drop_percent: u64,
+}
+
+#[derive(Default)]
+struct Faults {
+    random: u64,
+    render: Fault,
+    broadcast: Fault,
+    lock: Fault,
+}
+
+static FAULTS: LazyLock<Mutex<Faults>> = LazyLock::new(Mutex::default);
+
+/// The faults `POST /__chaos` injects from now on, all off unless given.
+#[derive(Deserialize)]
+pub struct ChaosForm {
+    /// Starts the draws deciding which items are dropped, for repeatable runs.
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    render_delay_ms: u64,
+    #[serde(default)]
+    render_drop_percent: u64,
+    #[serde(default)]
+    broadcast_delay_ms: u64,
+    #[serde(default)]
+    broadcast_drop_percent: u64,
+    #[serde(default)]
+    lock_delay_ms: u64,
+}
+
+/// Replace the faults injected by the ones of the form.
+pub async fn configure(Form(form): Form<ChaosForm>) {
+    let fault = |delay_ms, drop_percent| Fault {
+        delay: Duration::from_millis(delay_ms),
+        drop_percent,
+    };
+    *FAULTS.lock().unwrap() = Faults {
+        random: form.seed,
+        render: fault(form.render_delay_ms, form.render_drop_percent),
+        broadcast: fault(form.broadcast_delay_ms, form.broadcast_drop_percent),
+        lock: fault(form.lock_delay_ms, 0),
+    };
+}
+
+/// Hold up an input handler about to lock the game.
+pub async fn before_lock() {
+    sleep(delay(Point::Lock)).await;
+}
+
+/// Hold up the render task about to send events to the subscribers. Events
+/// of the handlers aren't held up, as they hold the game while sending.
+pub async fn before_broadcast() {
+    sleep(delay(Point::Broadcast)).await;
+}
+
+impl Faults {
+    fn at(&self, point: Point) -> Fault {
+        match point {
+            Point::Render => self.render,
+            Point::Broadcast => self.broadcast,
+            Point::Lock => self.lock,
+        }
+    }
+}
+
+/// How long to hold up the items at `point`.
+pub fn delay(point: Point) -> Duration {
+    FAULTS.lock().unwrap().at(point).delay
+}
+
+/// Whether to drop the item at `point`.
+pub fn dropped(point: Point) -> bool {
+    let mut faults = FAULTS.lock().unwrap();
+    let drop_percent = faults.at(point).drop_percent;
+    drop_percent > 0 && splitmix64(&mut faults.random) % 100 < drop_percent
+}
@@ -0,0 +1,169 @@
+use crate::{PointSample, Side, BAT_HEIGHT};
+use std::fmt::Write;
+
+const WIDTH: f64 = 300.;
+/// Room left of the plots for their labels.
+const LABEL_WIDTH: f64 = 50.;
+const PANEL_HEIGHT: f64 = 40.;
+const PANEL_GAP: f64 = 12.;
+const LEFT_COLOR: &str = "#eee";
+const RIGHT_COLOR: &str = "#aaa";
+
+/// Maps ticks and values into one panel of the chart.
+struct Panel {
+    top: f64,
+    ticks: u64,
+    max: f64,
+}
+
+impl Panel {
+    fn new(index: u8, ticks: u64, max: f64) -> Self {
+        Self {
+            top: PANEL_GAP / 2. + f64::from(index) * (PANEL_HEIGHT + PANEL_GAP),
+            // A game over on its first tick still gets a plot of some width.
+            ticks: ticks.max(1),
+            max: if max > 0. { max } else { 1. },
+        }
+    }
+
+    fn x(&self, tick: u64) -> f64 {
+        LABEL_WIDTH + tick.min(self.ticks) as f64 / self.ticks as f64 * (WIDTH - LABEL_WIDTH)
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        self.top + PANEL_HEIGHT - value.clamp(0., self.max) / self.max * PANEL_HEIGHT
+    }
+}
+
+/// The `points` attribute of a polyline through `points`.
+fn polyline_points(points: &[(f64, f64)]) -> String {
+    let points: Vec<String> = points.iter().map(|(x, y)| format!("{x:.1},{y:.1}")).collect();
+    points.join(" ")
+}
+
+/// The corners of a line holding `start` from tick 0 and changing to each of
+/// `changes` at its tick, up to `ticks`.
+fn steps(panel: &Panel, start: f64, changes: &[(u64, f64)], ticks: u64) -> Vec<(f64, f64)> {
+    let mut points = vec![(panel.x(0), panel.y(start))];
+    let mut value = start;
+    for &(tick, next) in changes {
+        points.push((panel.x(tick), panel.y(value)));
+        points.push((panel.x(tick), panel.y(next)));
+        value = next;
+    }
+    points.push((panel.x(ticks), panel.y(value)));
+    points
+}
+
+/// Draw the score of both sides, the length of every point's rally and the
+/// height of the bats over a game as an inline SVG. The game ended with the
+/// last of its `samples`.
+pub fn timeline(samples: &[PointSample]) -> String {
+    let ticks = samples.last().map_or(0, |sample| sample.tick);
+    let score = |side: Side| {
+        let scored = samples.iter().filter(|sample| sample.scorer == Some(side));
+        scored
+            .enumerate()
+            .map(|(index, sample)| (sample.tick, (index + 1) as f64))
+            .collect::<Vec<_>>()
+    };
+    let scores = [score(Side::Left), score(Side::Right)];
+    let heights: [Vec<(u64, f64)>; 2] = [
+        samples.iter().map(|sample| (sample.tick, f64::from(sample.heights.0))).collect(),
+        samples.iter().map(|sample| (sample.tick, f64::from(sample.heights.1))).collect(),
+    ];
+    let max_score = scores.iter().map(Vec::len).max().unwrap_or(0);
+    let max_rally = samples.iter().map(|sample| sample.rally).max().unwrap_or(0);
+    let height = 3. * (PANEL_HEIGHT + PANEL_GAP);
+    let mut svg = format!(
+        "<svg class=\"timeline\" viewBox=\"0 0 {WIDTH} {height}\" \
+         xmlns=\"http://www.w3.org/2000/svg\" font-size=\"9\" fill=\"{LEFT_COLOR}\">"
+    );
+    let panels = [
+        (Panel::new(0, ticks, max_score as f64), format!("Score {max_score}")),
+        (Panel::new(1, ticks, max_rally.into()), format!("Rally {max_rally}")),
+        (Panel::new(2, ticks, BAT_HEIGHT.into()), "Bats".to_string()),
+    ];
+    for (panel, label) in &panels {
+        let baseline = panel.y(0.);
+        write!(
+            svg,
+            "<text x=\"0\" y=\"{:.1}\">{label}</text>\
+             <line x1=\"{LABEL_WIDTH}\" y1=\"{baseline:.1}\" x2=\"{WIDTH}\" y2=\"{baseline:.1}\" \
+             stroke=\"#666\" />",
+            panel.top + PANEL_HEIGHT / 2.
+        )
+        .expect("writing to a string");
+    }
+    let [score_panel, rally_panel, height_panel] = panels.map(|(panel, _)| panel);
+    for (changes, color) in scores.iter().zip([LEFT_COLOR, RIGHT_COLOR]) {
+        write_polyline(&mut svg, &steps(&score_panel, 0., changes, ticks), color);
+    }
+    for sample in samples {
+        let (x, y) = (rally_panel.x(sample.tick), rally_panel.y(sample.rally.into()));
+        write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{y:.1}\" width=\"2\" height=\"{:.1}\" />",
+            x - 1.,
+            rally_panel.y(0.) - y
+        )
+        .expect("writing to a string");
+    }
+    for (changes, color) in heights.iter().zip([LEFT_COLOR, RIGHT_COLOR]) {
+        let start = f64::from(BAT_HEIGHT);
+        write_polyline(&mut svg, &steps(&height_panel, start, changes, ticks), color);
+    }
+    if samples.is_empty() {
+        write!(
+            svg,
+            "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"middle\">No points</text>",
+            (LABEL_WIDTH + WIDTH) / 2.,
+            score_panel.top + PANEL_HEIGHT / 2.
+        )
+        .expect("writing to a string");
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn write_polyline(svg: &mut String, points: &[(f64, f64)], color: &str) {
+    write!(
+        svg,
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" />",
+        polyline_points(points)
+    )
+    .expect("writing to a string");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_hold_each_value_until_it_changes() {
+        let panel = Panel::new(0, 100, 2.);
+        let points = steps(&panel, 0., &[(50, 1.), (75, 2.)], 100);
+        assert_eq!(
+            polyline_points(&points),
+            "50.0,46.0 175.0,46.0 175.0,26.0 237.5,26.0 237.5,6.0 300.0,6.0"
+        );
+        // The bats panel sits two panels further down.
+        assert_eq!(Panel::new(2, 100, 200.).y(100.), 6. + 2. * 52. + 20.);
+    }
+
+    #[test]
+    fn games_without_points_still_get_a_chart() {
+        let panel = Panel::new(0, 0, 0.);
+        assert_eq!(polyline_points(&steps(&panel, 0., &[], 0)), "50.0,46.0 50.0,46.0");
+        let empty = timeline(&[]);
+        assert!(empty.contains("No points"), "{empty}");
+        let one_point = timeline(&[PointSample {
+            tick: 0,
+            scorer: Some(Side::Left),
+            rally: 0,
+            heights: (200, 200),
+        }]);
+        assert!(!one_point.contains("No points") && !one_point.contains("NaN"), "{one_point}");
+        assert!(one_point.contains("<text x=\"0\" y=\"26.0\">Score 1</text>"), "{one_point}");
+    }
+}
@@ -0,0 +1,91 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+/// How far the clock was moved ahead of the real time, in milliseconds.
+static AHEAD_MS: AtomicU64 = AtomicU64::new(0);
+/// Wakes up the sleepers whenever the clock is moved.
+static MOVED: LazyLock<Notify> = LazyLock::new(Notify::new);
+/// The system's wall time when the server started, which `now_ms` goes on
+/// from by the monotonic clock.
+static STARTED: LazyLock<(Instant, SystemTime)> =
+    LazyLock::new(|| (Instant::now(), SystemTime::now()));
+/// Differences from the system clock up to this are its drift, larger ones
+/// are jumps, such as an NTP correction on a board without a real-time clock.
+pub const JUMP_MS: i64 = 1000;
+
+fn ahead() -> Duration {
+    Duration::from_millis(AHEAD_MS.load(Ordering::Relaxed))
+}
+
+/// The time countdowns, timeouts and grace periods go by. It only differs
+/// from the real time once `POST /__test/advance-time` of the `test-hooks`
+/// feature moved it; the game loop paces its steps by the real time.
+pub fn now() -> Instant {
+    Instant::now() + ahead()
+}
+
+/// `now` in milliseconds since the Unix epoch. It's the system's wall time
+/// at startup moved on by the monotonic clock, so that schedules, latencies
+/// and timestamps stay consistent when the system clock jumps.
+pub fn now_ms() -> f64 {
+    let (instant, wall) = *STARTED;
+    epoch_ms(wall + instant.elapsed() + ahead())
+}
+
+fn epoch_ms(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH).expect("clock is after the epoch").as_secs_f64() * 1000.
+}
+
+/// How far the system clock is ahead of `now_ms`, not counting moves of the
+/// `test-hooks` feature. Negative if it's behind.
+pub fn skew_ms() -> i64 {
+    let (instant, wall) = *STARTED;
+    (epoch_ms(SystemTime::now()) - epoch_ms(wall + instant.elapsed())) as i64
+}
+
+/// `skew_ms` if the system clock jumped away from `now_ms` since startup.
+pub fn jumped_ms() -> Option<i64> {
+    Some(skew_ms()).filter(|skew| skew.abs() >= JUMP_MS)
+}
+
+/// `ms` since the Unix epoch by the system clock, by `now_ms` instead.
+pub fn from_system_ms(ms: u64) -> u64 {
+    ms.saturating_add_signed(-skew_ms())
+}
+
+/// Like `tokio::time::sleep`, but ends early once the clock is moved past
+/// its end.
+pub async fn sleep(duration: Duration) {
+    let until = now() + duration;
+    loop {
+        let moved = MOVED.notified();
+        let remaining = until.saturating_duration_since(now());
+        if remaining.is_zero() {
+            return;
+        }
+        tokio::select! {
+            () = tokio::time::sleep(remaining) => {}
+            () = moved => {}
+        }
+    }
+}
+
+/// Like `tokio::time::timeout`, `None` if `future` didn't finish within
+/// `duration` by this clock.
+pub async fn timeout<T>(duration: Duration, future: impl Future<Output = T>) -> Option<T> {
+    tokio::select! {
+        output = future => Some(output),
+        () = sleep(duration) => None,
+    }
+}
+
+/// Move the clock ahead by `by`, ending the sleeps it takes past their end.
+#[cfg(feature = "test-hooks")]
+pub fn advance(by: Duration) {
+    let by = u64::try_from(by.as_millis()).expect("advanced by less than u64::MAX ms");
+    AHEAD_MS.fetch_add(by, Ordering::Relaxed);
+    MOVED.notify_waiters();
+}
@@ -0,0 +1,124 @@
+use crate::Side;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The presses of held keys, merged into fewer moves with `--coalesce-keys`.
+#[derive(Clone, Default)]
+pub struct KeyBursts {
+    /// The left side's burst, then the right one's.
+    sides: Arc<Mutex<[Burst; 2]>>,
+}
+
+#[derive(Default)]
+struct Burst {
+    key: String,
+    /// When the last move of `key` was applied.
+    moved_at: Option<Instant>,
+    /// Presses of `key` since then, waiting to be applied as one move.
+    pending: u16,
+}
+
+/// What to do about a press.
+pub struct Press {
+    /// Keys with their number of presses to move by right away, in order.
+    pub moves: Vec<(String, u16)>,
+    /// When to `flush` the presses merged into a later move, if this one
+    /// was the first of them.
+    pub flush_at: Option<Instant>,
+}
+
+fn index(side: Side) -> usize {
+    match side {
+        Side::Left => 0,
+        Side::Right => 1,
+    }
+}
+
+impl KeyBursts {
+    /// A press of `key`, one of `side`'s, at `now`. It's merged into a later
+    /// move if the last move of the side was of the same key within `window`.
+    /// Otherwise it moves right away, after what's still merged of another key.
+    pub fn press(&self, side: Side, key: &str, now: Instant, window: Duration) -> Press {
+        let mut sides = self.sides.lock().unwrap();
+        let burst = &mut sides[index(side)];
+        let moved_at = burst.moved_at.filter(|at| burst.key == key && now - *at < window);
+        if let Some(moved_at) = moved_at {
+            burst.pending = burst.pending.saturating_add(1);
+            return Press {
+                moves: Vec::new(),
+                flush_at: (burst.pending == 1).then_some(moved_at + window),
+            };
+        }
+        let mut moves = Vec::new();
+        if burst.pending > 0 {
+            moves.push((mem::take(&mut burst.key), burst.pending));
+        }
+        *burst = Burst {
+            key: key.to_string(),
+            moved_at: Some(now),
+            pending: 0,
+        };
+        moves.push((key.to_string(), 1));
+        Press {
+            moves,
+            flush_at: None,
+        }
+    }
+
+    /// The presses of `side` merged since its last move, as one move at `now`.
+    pub fn flush(&self, side: Side, now: Instant) -> Option<(String, u16)> {
+        let mut sides = self.sides.lock().unwrap();
+        let burst = &mut sides[index(side)];
+        if burst.pending == 0 {
+            return None;
+        }
+        burst.moved_at = Some(now);
+        Some((burst.key.clone(), mem::take(&mut burst.pending)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    fn moves(press: &Press) -> Vec<(&str, u16)> {
+        press.moves.iter().map(|(key, presses)| (key.as_str(), *presses)).collect()
+    }
+
+    #[test]
+    fn presses_within_the_window_are_merged_into_one_move() {
+        let bursts = KeyBursts::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let first = bursts.press(Side::Left, "w", at(0), WINDOW);
+        assert_eq!((moves(&first), first.flush_at), (vec![("w", 1)], None));
+        let second = bursts.press(Side::Left, "w", at(10), WINDOW);
+        assert_eq!((moves(&second), second.flush_at), (vec![], Some(at(100))));
+        let third = bursts.press(Side::Left, "w", at(20), WINDOW);
+        assert_eq!((moves(&third), third.flush_at), (vec![], None));
+        assert_eq!(bursts.flush(Side::Left, at(100)), Some(("w".to_string(), 2)));
+        assert_eq!(bursts.flush(Side::Left, at(100)), None);
+        // The flushed move starts the next window.
+        let later = bursts.press(Side::Left, "w", at(150), WINDOW);
+        assert_eq!(later.flush_at, Some(at(200)));
+        let after_the_window = bursts.press(Side::Left, "w", at(300), WINDOW);
+        assert_eq!(moves(&after_the_window), [("w", 1), ("w", 1)]);
+    }
+
+    #[test]
+    fn another_key_applies_what_was_merged_first() {
+        let bursts = KeyBursts::default();
+        let now = Instant::now();
+        bursts.press(Side::Left, "w", now, WINDOW);
+        bursts.press(Side::Left, "w", now, WINDOW);
+        bursts.press(Side::Left, "w", now, WINDOW);
+        let right = bursts.press(Side::Right, "o", now, WINDOW);
+        assert_eq!(moves(&right), [("o", 1)], "each side has a burst of its own");
+        let down = bursts.press(Side::Left, "s", now, WINDOW);
+        assert_eq!(moves(&down), [("w", 2), ("s", 1)]);
+        assert_eq!(bursts.flush(Side::Left, now), None);
+    }
+}
@@ -0,0 +1,538 @@
+use crate::hooks::WebhookUrl;
+use crate::rules::{Bindings, Forgiveness, Rules, Stamina};
+use crate::{BAT_HEIGHT, FIELD_SIZE};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How a stopped game gets started.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StartMode {
+    /// Any input, including a click on the board, starts the game.
+    FirstInput,
+    /// Only the "p" key or `POST /start` start the game.
+    Explicit,
+    /// A new game starts once both sides said they're ready with `POST /ready`,
+    /// and nothing else starts it. Pausing and resuming work as usual.
+    Ready,
+}
+
+impl std::str::FromStr for StartMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first-input" => Ok(Self::FirstInput),
+            "explicit" => Ok(Self::Explicit),
+            "ready" => Ok(Self::Ready),
+            _ => Err(format!(
+                "invalid start mode {s:?}, expected \"first-input\", \"explicit\" or \"ready\""
+            )),
+        }
+    }
+}
+
+/// How clients give the `x` and `y` of a click.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClickCoordinates {
+    /// Fractions of the board's width and height, from 0 to 1.
+    Normalized,
+    /// Pixels on a board of the given size.
+    Pixels { width: f32, height: f32 },
+}
+
+impl ClickCoordinates {
+    /// Convert a click to fractions of the board's width and height.
+    pub fn normalize(self, x: f32, y: f32) -> (f32, f32) {
+        match self {
+            Self::Normalized => (x, y),
+            Self::Pixels { width, height } => (x / width, y / height),
+        }
+    }
+}
+
+impl std::str::FromStr for ClickCoordinates {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid click coordinates {s:?}, expected \"normalized\" or WIDTHxHEIGHT in pixels"
+            )
+        };
+        if s == "normalized" {
+            return Ok(Self::Normalized);
+        }
+        let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+        let (width, height): (f32, f32) = (
+            width.parse().map_err(|_| invalid())?,
+            height.parse().map_err(|_| invalid())?,
+        );
+        if !(width > 0. && height > 0.) {
+            return Err(invalid());
+        }
+        Ok(Self::Pixels { width, height })
+    }
+}
+
+/// Who may register a bot at `POST /bot/register`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BotRegistration {
+    Open,
+    /// Only requests with the `--admin-token`.
+    Admin,
+}
+
+impl std::str::FromStr for BotRegistration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!(
+                "invalid bot registration {s:?}, expected \"open\" or \"admin\""
+            )),
+        }
+    }
+}
+
+/// An output of `--renderer` besides the pages, each built in with a feature
+/// of the same name plus `-renderer`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RendererKind {
+    /// Every update as a JSON line on stderr.
+    #[cfg(feature = "log-renderer")]
+    Log,
+    /// The board drawn with characters on stdout.
+    #[cfg(feature = "ascii-renderer")]
+    Ascii,
+}
+
+impl std::str::FromStr for RendererKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "log-renderer")]
+            "log" => Ok(Self::Log),
+            #[cfg(feature = "ascii-renderer")]
+            "ascii" => Ok(Self::Ascii),
+            // Unreachable if both are built in.
+            #[allow(unreachable_patterns)]
+            "log" | "ascii" => Err(format!(
+                "the {s} renderer isn't built in, build with --features {s}-renderer"
+            )),
+            _ => Err(format!("invalid renderer {s:?}, expected \"log\" or \"ascii\"")),
+        }
+    }
+}
+
+/// What the binary was asked to do.
+pub enum Command {
+    Serve(Box<Config>),
+    /// Re-simulate a recording exported from `/debug/input-log`.
+    ReplayFile { path: String, trace: bool },
+    /// Report every problem serving with these arguments would run into.
+    Check(Vec<String>),
+}
+
+impl Command {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter().peekable();
+        if args.next_if(|arg| arg == "check").is_some() {
+            return Ok(Command::Check(args.collect()));
+        }
+        if args.next_if(|arg| arg == "replay-file").is_none() {
+            return Config::from_args(args).map(|config| Command::Serve(Box::new(config)));
+        }
+        let mut path = None;
+        let mut trace = false;
+        for arg in args {
+            match arg.as_str() {
+                "--trace" => trace = true,
+                _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+        let path = path.ok_or("usage: rspong replay-file <path> [--trace]")?;
+        Ok(Command::ReplayFile { path, trace })
+    }
+}
+
+/// Ticks per second, the rate the ball moves its velocity at.
+pub const TICK_HZ: u32 = 30;
+/// The length of a tick, rounded to whole milliseconds.
+const TICK: Duration = Duration::from_millis(32);
+/// Physics steps per tick at most.
+const MAX_SUBSTEPS: u32 = 8;
+
+/// Time between two physics steps with `substeps` steps per tick.
+pub fn step_interval(substeps: u8) -> Duration {
+    TICK / substeps.max(1) as u32
+}
+
+/// Server configuration, read from the command line once at startup.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub start_mode: StartMode,
+    /// How long a finished game stays on the board before it's reset by
+    /// itself, `None` to wait for the next input.
+    pub auto_reset: Option<Duration>,
+    /// Address and port to serve on.
+    pub listen: String,
+    pub click_coordinates: ClickCoordinates,
+    /// Presses of a held key this close after its last move are merged into
+    /// one later move, `None` to apply every press.
+    pub coalesce_keys: Option<Duration>,
+    /// Events buffered per SSE subscriber before it starts lagging.
+    pub channel_capacity: usize,
+    /// Ticks between `correct` events with exact positions, 0 for none.
+    pub correction_interval: u64,
+    /// How often `/compat` reloads for browsers without scripts or
+    /// server-sent events, `None` to not serve it.
+    pub compat_refresh: Option<Duration>,
+    /// Decimals of the percentages placing the ball in fragments, 1 for
+    /// exact positions. Moves that don't change them aren't sent.
+    pub coordinate_precision: u8,
+    /// Physics steps per second, a multiple of `TICK_HZ`.
+    pub physics_hz: u32,
+    /// Ball updates sent per second, dividing `physics_hz`.
+    pub broadcast_hz: u32,
+    /// Bat and ball updates sent per tick, further ones wait for the tick's
+    /// last state. `None` sends every update.
+    pub max_moves_per_tick: Option<u32>,
+    /// SSE connections served at once, further ones get a 503.
+    pub max_connections: usize,
+    /// Rooms open at once, `POST /rooms` gets a 503 beyond that.
+    pub max_rooms: usize,
+    /// Bytes per second the room's SSE connections may send together before
+    /// spectators get fewer ball updates, see `outbox::Quality`.
+    pub bandwidth_budget: Option<u64>,
+    /// Only the page in control may send inputs, see `/controller/claim`.
+    pub single_controller: bool,
+    /// The first two pages each play one side, see `sides`.
+    pub claim_sides: bool,
+    /// Stacked paddles on each side, for co-op play.
+    pub paddles: usize,
+    pub rules: Rules,
+    /// The key sets the bats start with, see `POST /keys`.
+    pub keys: Bindings,
+    /// Bearer token for the `/admin/...` endpoints, which are off without it.
+    pub admin_token: Option<String>,
+    pub bot_registration: BotRegistration,
+    /// Unix datagram socket to send points and other game events to.
+    pub event_socket: Option<String>,
+    pub webhook: Option<WebhookUrl>,
+    /// Where to keep the all-time records, which are only in memory without it.
+    pub records: Option<String>,
+    /// Let games whose inputs looked scripted set records, see `regularity`.
+    pub assisted_records: bool,
+    /// Where to write the state of a game that broke an invariant.
+    pub crash_dump_dir: Option<String>,
+    /// Where to keep the drawn result cards, see `card`.
+    pub card_dir: String,
+    /// Where else to draw the game besides the pages, see `renderers`.
+    pub renderers: Vec<RendererKind>,
+    /// Recordings to play on the board while nobody plays, see `exhibition`.
+    pub exhibition: Vec<String>,
+    /// Start with games of scripted players in the history and exhibitions,
+    /// see `demo`.
+    pub demo: bool,
+    /// Show classic scores as bars filling up towards the score limit.
+    pub score_bars: bool,
+    /// List the connected players on the scoreboard rather than count them.
+    pub player_names: bool,
+    pub branding: Branding,
+    /// Serve the `/debug/...` endpoints.
+    pub debug: bool,
+    /// Exit if a template fails to render at startup, instead of logging it.
+    pub strict_templates: bool,
+    /// Exit if startup finds any of the problems `rspong check` reports.
+    pub strict_startup: bool,
+}
+
+/// How the pages present the deployment.
+#[derive(Clone, Debug, Serialize)]
+pub struct Branding {
+    pub title: String,
+    pub ribbon_visible: bool,
+    /// Where the "Fork me" ribbon links to.
+    pub ribbon_url: String,
+    /// Icon file served instead of the built-in one.
+    pub favicon: Option<String>,
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Self {
+            title: "Hyperpong".to_string(),
+            ribbon_visible: true,
+            ribbon_url: "https://github.com/zvyn/rspong/".to_string(),
+            favicon: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            start_mode: StartMode::FirstInput,
+            auto_reset: None,
+            listen: "[::1]:3000".to_string(),
+            click_coordinates: ClickCoordinates::Normalized,
+            coalesce_keys: None,
+            channel_capacity: 50,
+            correction_interval: 30,
+            compat_refresh: Some(Duration::from_secs(2)),
+            coordinate_precision: 1,
+            physics_hz: TICK_HZ,
+            broadcast_hz: TICK_HZ,
+            max_moves_per_tick: None,
+            max_connections: 1000,
+            max_rooms: 20,
+            bandwidth_budget: None,
+            single_controller: false,
+            claim_sides: false,
+            paddles: 1,
+            rules: Rules::default(),
+            keys: Bindings::default(),
+            admin_token: None,
+            bot_registration: BotRegistration::Open,
+            event_socket: None,
+            webhook: None,
+            records: None,
+            assisted_records: false,
+            crash_dump_dir: None,
+            card_dir: std::env::temp_dir().join("rspong-cards").display().to_string(),
+            renderers: Vec::new(),
+            exhibition: Vec::new(),
+            demo: false,
+            score_bars: false,
+            player_names: false,
+            branding: Branding::default(),
+            debug: false,
+            strict_templates: false,
+            strict_startup: false,
+        }
+    }
+}
+
+impl Config {
+    /// Time between two physics steps of the game loop.
+    pub fn step_interval(&self) -> Duration {
+        step_interval(self.rules.substeps)
+    }
+
+    /// Parse `--flag value` or `--flag=value` style arguments (without the program name).
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {flag}"))
+            };
+            match flag.as_str() {
+                "--listen" => config.listen = value()?,
+                "--start-mode" => config.start_mode = value()?.parse()?,
+                "--auto-reset" => {
+                    config.auto_reset = Some(Duration::from_secs(parse_number(&flag, &value()?)?))
+                }
+                "--click-coordinates" => config.click_coordinates = value()?.parse()?,
+                "--coalesce-keys" => {
+                    let window: u64 = parse_number(&flag, &value()?)?;
+                    config.coalesce_keys = (window > 0).then(|| Duration::from_millis(window));
+                }
+                "--channel-capacity" => {
+                    config.channel_capacity = parse_number(&flag, &value()?)?;
+                    if config.channel_capacity == 0 {
+                        return Err(format!("{flag} must be at least 1"));
+                    }
+                }
+                "--correction-interval" => {
+                    config.correction_interval = parse_number(&flag, &value()?)?
+                }
+                "--compat-refresh" => {
+                    let refresh = parse_number(&flag, &value()?)?;
+                    config.compat_refresh = (refresh > 0).then(|| Duration::from_secs(refresh));
+                }
+                "--coordinate-precision" => {
+                    config.coordinate_precision = parse_number(&flag, &value()?)?;
+                    if config.coordinate_precision > 1 {
+                        return Err(format!("{flag} must be 0 or 1"));
+                    }
+                }
+                "--max-ball-step" => {
+                    let max_step: u16 = parse_number(&flag, &value()?)?;
+                    config.rules.max_ball_step = (max_step > 0).then_some(max_step);
+                }
+                "--physics-hz" => config.physics_hz = parse_number(&flag, &value()?)?,
+                "--broadcast-hz" => config.broadcast_hz = parse_number(&flag, &value()?)?,
+                "--max-moves-per-tick" => {
+                    let limit = parse_number(&flag, &value()?)?;
+                    // A tick's last state may take one update of each bat and the ball.
+                    if limit < 3 {
+                        return Err(format!("{flag} must be at least 3"));
+                    }
+                    config.max_moves_per_tick = Some(limit);
+                }
+                "--max-connections" => {
+                    config.max_connections = parse_number(&flag, &value()?)?
+                }
+                "--max-rooms" => config.max_rooms = parse_number(&flag, &value()?)?,
+                "--bandwidth-budget" => {
+                    config.bandwidth_budget = Some(parse_number(&flag, &value()?)?)
+                }
+                "--single-controller" => config.single_controller = true,
+                "--claim-sides" => config.claim_sides = true,
+                "--paddles" => {
+                    config.paddles = parse_number(&flag, &value()?)?;
+                    if !(1..=2).contains(&config.paddles) {
+                        return Err(format!("{flag} must be 1 or 2"));
+                    }
+                }
+                "--scoring" => config.rules.scoring = value()?.parse()?,
+                "--score-limit" => config.rules.score_limit = parse_number(&flag, &value()?)?,
+                "--win-by" => {
+                    config.rules.win_by = parse_number(&flag, &value()?)?;
+                    if config.rules.win_by == 0 {
+                        return Err(format!("{flag} must be at least 1"));
+                    }
+                }
+                "--sudden-death" => config.rules.sudden_death = true,
+                "--competitive" => config.rules.competitive = true,
+                "--assist-range" => config.rules.assist_range = parse_number(&flag, &value()?)?,
+                "--ai-reaction" => config.rules.ai_reaction = parse_number(&flag, &value()?)?,
+                "--bat-shape" => config.rules.bat_shape = value()?.parse()?,
+                "--bat-edges" => config.rules.bat_edges = value()?.parse()?,
+                "--bat-start" => {
+                    let start = parse_number(&flag, &value()?)?;
+                    if start > FIELD_SIZE - BAT_HEIGHT {
+                        return Err(format!("{flag} must be at most {}", FIELD_SIZE - BAT_HEIGHT));
+                    }
+                    config.rules.bat_start = Some(start);
+                }
+                "--stamina-drain" => {
+                    config.rules.stamina.get_or_insert_with(Stamina::default).drain =
+                        parse_number(&flag, &value()?)?
+                }
+                "--stamina-regen" => {
+                    config.rules.stamina.get_or_insert_with(Stamina::default).regen =
+                        parse_number(&flag, &value()?)?
+                }
+                "--stamina-click-cost" => {
+                    config.rules.stamina.get_or_insert_with(Stamina::default).click_cost =
+                        parse_number(&flag, &value()?)?
+                }
+                "--forgiveness" => {
+                    config.rules.forgiveness.get_or_insert_with(Forgiveness::default).distance =
+                        parse_number(&flag, &value()?)?
+                }
+                "--forgiveness-saves" => {
+                    config.rules.forgiveness.get_or_insert_with(Forgiveness::default).saves =
+                        parse_number(&flag, &value()?)?
+                }
+                "--left-keys" => config.keys.left = value()?.parse()?,
+                "--right-keys" => config.keys.right = value()?.parse()?,
+                "--same-tick-moves" => config.rules.same_tick_moves = value()?.parse()?,
+                "--marathon-after" => {
+                    config.rules.marathon_after = Some(parse_number(&flag, &value()?)?)
+                }
+                "--admin-token" => config.admin_token = Some(value()?),
+                "--bot-registration" => config.bot_registration = value()?.parse()?,
+                "--event-socket" => config.event_socket = Some(value()?),
+                "--webhook" => config.webhook = Some(value()?.parse()?),
+                "--records" => config.records = Some(value()?),
+                "--assisted-records" => config.assisted_records = true,
+                "--crash-dump-dir" => config.crash_dump_dir = Some(value()?),
+                "--card-dir" => config.card_dir = value()?,
+                "--renderer" => config.renderers.push(value()?.parse()?),
+                "--exhibition" => config.exhibition.push(value()?),
+                "--demo" => config.demo = true,
+                "--score-bars" => config.score_bars = true,
+                "--player-names" => config.player_names = true,
+                "--title" => config.branding.title = value()?,
+                "--hide-ribbon" => config.branding.ribbon_visible = false,
+                "--ribbon-url" => config.branding.ribbon_url = value()?,
+                "--favicon" => config.branding.favicon = Some(value()?),
+                "--debug" => config.debug = true,
+                "--strict-templates" => config.strict_templates = true,
+                "--strict-startup" => config.strict_startup = true,
+                _ => return Err(format!("unknown argument {flag}")),
+            }
+        }
+        let substeps = config.physics_hz / TICK_HZ;
+        if config.physics_hz % TICK_HZ != 0 || !(1..=MAX_SUBSTEPS).contains(&substeps) {
+            return Err(format!(
+                "--physics-hz must be a multiple of {TICK_HZ} up to {}",
+                TICK_HZ * MAX_SUBSTEPS
+            ));
+        }
+        if config.broadcast_hz == 0 || config.physics_hz % config.broadcast_hz != 0 {
+            return Err("--broadcast-hz must divide --physics-hz".to_string());
+        }
+        if config.keys.overlap(config.paddles) {
+            return Err("--left-keys and --right-keys share keys".to_string());
+        }
+        if config.rules.bat_start.is_some() && config.paddles > 1 {
+            return Err("--bat-start only applies to a single paddle per side".to_string());
+        }
+        config.rules.substeps = substeps as u8;
+        Ok(config)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {value:?} for {flag}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn click_coordinates_are_normalized_or_pixels() {
+        assert_eq!("normalized".parse(), Ok(ClickCoordinates::Normalized));
+        let full_hd: ClickCoordinates = "1920x1080".parse().unwrap();
+        assert_eq!(full_hd, ClickCoordinates::Pixels { width: 1920., height: 1080. });
+        assert_eq!(full_hd.normalize(384., 108.), (0.2, 0.1));
+        assert_eq!(ClickCoordinates::Normalized.normalize(0.2, 0.1), (0.2, 0.1));
+        for invalid in ["pixels", "1920", "1920x", "0x1080", "-1x1", "NaNx1"] {
+            assert!(invalid.parse::<ClickCoordinates>().is_err(), "{invalid:?} parsed");
+        }
+    }
+
+    #[test]
+    fn physics_rates_split_ticks_into_substeps() {
+        let config = |args: &str| Config::from_args(args.split_whitespace().map(String::from));
+        assert_eq!(config("").unwrap().rules.substeps, 1);
+        let config_120 = config("--physics-hz 120 --broadcast-hz 30").unwrap();
+        assert_eq!(config_120.rules.substeps, 4);
+        assert_eq!(config_120.step_interval(), TICK / 4);
+        for invalid in ["--physics-hz 100", "--physics-hz 0", "--broadcast-hz 0"] {
+            assert!(config(invalid).is_err(), "{invalid} was accepted");
+        }
+        assert!(config("--physics-hz 120 --broadcast-hz 50").is_err());
+        assert!(config(&format!("--physics-hz {}", TICK_HZ * (MAX_SUBSTEPS + 1))).is_err());
+    }
+
+    #[test]
+    fn bat_start_is_on_the_board_and_for_a_lone_paddle() {
+        let config = |args: &str| Config::from_args(args.split_whitespace().map(String::from));
+        assert_eq!(config("").unwrap().rules.bat_start, None);
+        assert_eq!(config("--bat-start 0").unwrap().rules.bat_start, Some(0));
+        assert_eq!(config("--bat-start 800").unwrap().rules.bat_start, Some(800));
+        assert!(config("--bat-start 801").is_err());
+        assert!(config("--bat-start 100 --paddles 2").is_err());
+    }
+}
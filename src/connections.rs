@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Server-wide cap on concurrently open SSE connections.
+#[derive(Clone)]
+pub struct ConnectionLimit {
+    open: Arc<AtomicUsize>,
+    max: usize,
+}
+
+/// Counts as an open connection until dropped together with its stream.
+pub struct ConnectionGuard {
+    open: Arc<AtomicUsize>,
+}
+
+impl ConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        Self {
+            open: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    /// Reserve a connection, or `None` if the cap is reached.
+    pub fn acquire(&self) -> Option<ConnectionGuard> {
+        self.open
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |open| {
+                (open < self.max).then_some(open + 1)
+            })
+            .ok()
+            .map(|_| ConnectionGuard {
+                open: self.open.clone(),
+            })
+    }
+
+    pub fn open(&self) -> usize {
+        self.open.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_beyond_the_cap_are_refused_until_one_closes() {
+        let limit = ConnectionLimit::new(2);
+        let first = limit.acquire().unwrap();
+        let _second = limit.acquire().unwrap();
+        assert!(limit.acquire().is_none());
+        assert_eq!(limit.open(), 2);
+        drop(first);
+        assert_eq!(limit.open(), 1);
+        assert!(limit.acquire().is_some());
+    }
+
+    #[test]
+    fn clones_share_the_count() {
+        let limit = ConnectionLimit::new(1);
+        let _guard = limit.clone().acquire().unwrap();
+        assert!(limit.acquire().is_none());
+    }
+}
@@ -0,0 +1,41 @@
+use crate::clock;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Control is free to claim again after the controlling page sent no input
+/// for this long, e.g. because it was closed without releasing it.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The one page whose inputs count with `--single-controller`, by the token
+/// it was rendered with.
+#[derive(Clone, Default)]
+pub struct Controller {
+    holder: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl Controller {
+    /// Take control for `token` unless another page holds it, returning
+    /// whether `token` is in control now.
+    pub fn claim(&self, token: &str) -> bool {
+        let mut holder = self.holder.lock().unwrap();
+        let now = clock::now();
+        match &*holder {
+            Some((held, last_input)) if held != token && now - *last_input < IDLE_TIMEOUT => false,
+            _ => {
+                *holder = Some((token.to_string(), now));
+                true
+            }
+        }
+    }
+
+    /// Give up control, returning `false` if `token` wasn't in control.
+    pub fn release(&self, token: &str) -> bool {
+        let mut holder = self.holder.lock().unwrap();
+        if holder.as_ref().is_some_and(|(held, _)| held == token) {
+            *holder = None;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config::StartMode;
+use crate::exhibition::Exhibit;
+use crate::history::FinishedGame;
+use crate::hooks::{EventWatcher, GameEvent};
+use crate::modes::Mode;
+use crate::recording::Recording;
+use crate::{splitmix64, AppState, GameState, Input, Side};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+/// Games the scripted players play for the history of `--demo`.
+const GAMES: u64 = 8;
+/// The last of them kept as exhibitions.
+const EXHIBITS: usize = 3;
+/// Games still going after this many steps are left out.
+const MAX_STEPS: u64 = 200_000;
+/// How far off the scripted players aim at most, in field units. A bat is 200
+/// high, so aiming more than about 100 off misses.
+const MAX_AIM_ERROR: i64 = 130;
+
+/// A scripted player of one side, following the ball with a bat.
+struct Player {
+    side: Side,
+    random: u64,
+    /// Where the bat's center aims relative to the ball in the current rally.
+    aim: i64,
+    /// Whether the ball came towards the side at the last look.
+    incoming: bool,
+    /// Points played by the last look, as a serve to the conceder may come
+    /// towards the side again.
+    points: u16,
+}
+
+impl Player {
+    fn new(side: Side, seed: u64) -> Self {
+        Self {
+            side,
+            random: seed,
+            aim: 0,
+            incoming: false,
+            points: 0,
+        }
+    }
+
+    /// The key to press this tick, if any. The bat goes for the ball while it
+    /// comes towards the side, off by an error picked anew for every return
+    /// and serve.
+    fn key(&mut self, game: &GameState) -> Option<String> {
+        let incoming = match self.side {
+            Side::Left => game.ball.velocity.0 < 0,
+            Side::Right => game.ball.velocity.0 > 0,
+        };
+        let points = game.left.score + game.right.score;
+        if incoming && (!self.incoming || points != self.points) {
+            let span = 2 * MAX_AIM_ERROR as u64 + 1;
+            self.aim = (splitmix64(&mut self.random) % span) as i64 - MAX_AIM_ERROR;
+        }
+        self.incoming = incoming;
+        self.points = points;
+        let bat = &game.team(self.side).bats[0];
+        let center = i64::from(bat.position) + i64::from(bat.height / 2);
+        let target = if incoming {
+            i64::from(game.ball.position.1) + self.aim
+        } else {
+            i64::from(crate::FIELD_SIZE / 2)
+        };
+        if target < center - 25 {
+            Some(bat.up_key.clone())
+        } else if target > center + 25 {
+            Some(bat.down_key.clone())
+        } else {
+            None
+        }
+    }
+}
+
+/// Play a classic game between two scripted players like the game loop
+/// would, sending its events to `events`. `None` if it didn't end.
+fn play(state: &AppState, events: &broadcast::Sender<GameEvent>, seed: u64) -> Option<GameState> {
+    let classic = Mode::find("classic").expect("classic is a mode");
+    let mut game = GameState::new(state.config.paddles, classic.rules(&state.config.rules));
+    let mut watcher = EventWatcher::new(events.clone(), &game);
+    let mut players = [
+        Player::new(Side::Left, 2 * seed),
+        Player::new(Side::Right, 2 * seed + 1),
+    ];
+    game.apply_input(Input::Start, StartMode::Explicit);
+    watcher.observe(&game);
+    for _ in 0..MAX_STEPS {
+        if game.phase().is_over() {
+            return Some(game);
+        }
+        if game.substep == 0 {
+            for player in &mut players {
+                if let Some(key) = player.key(&game) {
+                    game.apply_input(Input::Key { key }, StartMode::Explicit);
+                }
+            }
+        }
+        game.step();
+        watcher.observe(&game);
+    }
+    None
+}
+
+/// Fill the history with the games of two scripted players, marked as demo
+/// data, and return the last of them to be shown as exhibitions.
+pub fn prefill(state: &AppState) -> Vec<Exhibit> {
+    let (events, mut seen) = broadcast::channel(16);
+    let mut exhibits = Vec::new();
+    for seed in 0..GAMES {
+        let Some(game) = play(state, &events, seed) else {
+            continue;
+        };
+        // Only the game over matters, which is the last event of a game.
+        loop {
+            match seen.try_recv() {
+                Ok(event) => {
+                    if let Some(finished) = FinishedGame::ended_by(event) {
+                        state.history.push(FinishedGame {
+                            demo: true,
+                            ..finished
+                        });
+                    }
+                }
+                Err(TryRecvError::Lagged(_)) => {}
+                Err(_) => break,
+            }
+        }
+        exhibits.push(Exhibit::Demo {
+            title: format!("demo game {}", seed + 1),
+            recording: Box::new(Recording::new(&game, StartMode::Explicit)),
+        });
+    }
+    exhibits.split_off(exhibits.len().saturating_sub(EXHIBITS))
+}
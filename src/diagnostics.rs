@@ -0,0 +1,138 @@
+use crate::clock;
+use axum::response::sse::Event;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// How long a session waits for its page to connect before it's dropped.
+const UNSUBSCRIBED_TTL: Duration = Duration::from_secs(60);
+
+struct Session {
+    /// `None` until the session's page connects to `/game-sse`.
+    stream: Option<mpsc::Sender<Event>>,
+    opened: Instant,
+}
+
+/// Clients that asked to see how the server handles their inputs, by session.
+#[derive(Clone, Default)]
+pub struct InputEcho {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+/// Ends its session when dropped together with the session's event stream.
+pub struct EchoGuard {
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    id: String,
+    stream: mpsc::Sender<Event>,
+}
+
+impl InputEcho {
+    /// Start a session, returning the id its page sends along with the inputs.
+    pub fn open(&self) -> String {
+        let now = clock::now();
+        let id = crate::random_token();
+        let mut sessions = self.sessions.lock().unwrap();
+        expire(&mut sessions, now);
+        sessions.insert(
+            id.clone(),
+            Session {
+                stream: None,
+                opened: now,
+            },
+        );
+        id
+    }
+
+    /// The events for the session's event stream and the guard to keep along
+    /// with it, or `None` if the session wasn't opened.
+    pub fn subscribe(&self, id: &str) -> Option<(mpsc::Receiver<Event>, EchoGuard)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(id)?;
+        let (tx, rx) = mpsc::channel(16);
+        session.stream = Some(tx.clone());
+        let guard = EchoGuard {
+            sessions: self.sessions.clone(),
+            id: id.to_owned(),
+            stream: tx,
+        };
+        Some((rx, guard))
+    }
+
+    /// Echo to the session only, dropping the event if it can't keep up.
+    pub fn send(&self, id: &str, event: Event) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(Session { stream: Some(stream), .. }) = sessions.get(id) {
+            if let Err(mpsc::error::TrySendError::Closed(_)) = stream.try_send(event) {
+                sessions.remove(id);
+            }
+        }
+    }
+}
+
+/// Drop the sessions whose page never connected within `UNSUBSCRIBED_TTL`.
+fn expire(sessions: &mut HashMap<String, Session>, now: Instant) {
+    sessions.retain(|_, session| {
+        session.stream.is_some() || now.duration_since(session.opened) < UNSUBSCRIBED_TTL
+    });
+}
+
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        // A later subscription of the same session replaced this stream.
+        let replaced = sessions.get(&self.id).is_some_and(|session| {
+            !session
+                .stream
+                .as_ref()
+                .is_some_and(|stream| stream.same_channel(&self.stream))
+        });
+        if !replaced {
+            sessions.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl InputEcho {
+        fn len(&self) -> usize {
+            self.sessions.lock().unwrap().len()
+        }
+    }
+
+    #[test]
+    fn dropping_the_stream_ends_the_session() {
+        let echo = InputEcho::default();
+        let id = echo.open();
+        let (rx, guard) = echo.subscribe(&id).unwrap();
+        assert_eq!(echo.len(), 1);
+        drop((rx, guard));
+        assert_eq!(echo.len(), 0);
+        assert!(echo.subscribe(&id).is_none());
+    }
+
+    #[test]
+    fn a_replaced_stream_keeps_the_session() {
+        let echo = InputEcho::default();
+        let id = echo.open();
+        let first = echo.subscribe(&id).unwrap();
+        let _second = echo.subscribe(&id).unwrap();
+        drop(first);
+        assert_eq!(echo.len(), 1);
+    }
+
+    #[test]
+    fn unsubscribed_sessions_expire() {
+        let echo = InputEcho::default();
+        let waiting = echo.open();
+        let connected = echo.open();
+        let _stream = echo.subscribe(&connected).unwrap();
+        let later = clock::now() + UNSUBSCRIBED_TTL;
+        expire(&mut echo.sessions.lock().unwrap(), later);
+        assert!(echo.subscribe(&waiting).is_none());
+        assert_eq!(echo.len(), 1);
+    }
+}
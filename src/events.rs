@@ -0,0 +1,117 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// An event as broadcast to the SSE subscribers, numbered for catching up.
+#[derive(Clone, Serialize)]
+pub struct LoggedEvent {
+    pub id: u64,
+    pub event: &'static str,
+    pub data: String,
+}
+
+/// Ring buffer of the most recently broadcast events.
+pub struct EventLog {
+    events: VecDeque<LoggedEvent>,
+    capacity: usize,
+    last_id: u64,
+}
+
+impl EventLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+            last_id: 0,
+        }
+    }
+
+    pub fn push(&mut self, event: &'static str, data: String) -> u64 {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.last_id += 1;
+        self.events.push_back(LoggedEvent {
+            id: self.last_id,
+            event,
+            data,
+        });
+        self.last_id
+    }
+
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// The ID the next pushed event gets.
+    pub fn push_id(&self) -> u64 {
+        self.last_id + 1
+    }
+
+    /// The data of the most recent buffered `event`.
+    pub fn last_data(&self, event: &str) -> Option<&str> {
+        self.events
+            .iter()
+            .rev()
+            .find(|logged| logged.event == event)
+            .map(|logged| logged.data.as_str())
+    }
+
+    /// All the events still buffered, oldest first.
+    pub fn recent(&self) -> Vec<LoggedEvent> {
+        self.events.iter().cloned().collect()
+    }
+
+    /// The events after `id`, or `None` if some of them were already dropped or `id` is
+    /// ahead of the log, as after a server restart.
+    pub fn since(&self, id: u64) -> Option<Vec<LoggedEvent>> {
+        let oldest = self.events.front().map_or(self.last_id + 1, |event| event.id);
+        if id > self.last_id || (id + 1 < oldest && id < self.last_id) {
+            return None;
+        }
+        Some(
+            self.events
+                .iter()
+                .filter(|event| event.id > id)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_with(count: usize, capacity: usize) -> EventLog {
+        let mut log = EventLog::new(capacity);
+        for _ in 0..count {
+            log.push("ball", String::new());
+        }
+        log
+    }
+
+    fn ids(events: Vec<LoggedEvent>) -> Vec<u64> {
+        events.into_iter().map(|event| event.id).collect()
+    }
+
+    #[test]
+    fn since_returns_the_missed_events() {
+        let log = log_with(5, 10);
+        assert_eq!(log.since(2).map(ids), Some(vec![3, 4, 5]));
+        assert_eq!(log.since(5).map(ids), Some(vec![]));
+    }
+
+    #[test]
+    fn since_an_id_older_than_the_buffer_needs_a_keyframe() {
+        let log = log_with(10, 4);
+        assert_eq!(log.since(5).map(ids), None);
+        assert_eq!(log.since(6).map(ids), Some(vec![7, 8, 9, 10]));
+    }
+
+    #[test]
+    fn since_an_id_ahead_of_the_log_needs_a_keyframe() {
+        assert_eq!(log_with(3, 10).since(7).map(ids), None);
+        assert_eq!(log_with(0, 10).since(1).map(ids), None);
+        assert_eq!(log_with(0, 10).since(0).map(ids), Some(vec![]));
+    }
+}
@@ -0,0 +1,173 @@
+use crate::clock;
+use crate::recording::{Playback, Recording};
+use crate::replay::Replay;
+use crate::{
+    ball_fragment, bat_fragment, broadcast, config, render_all, scoreboard_fragment, show_banner,
+    AppState, GameState, Phase, Side,
+};
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+/// How long the board has to sit unused before the exhibition starts.
+const IDLE_AFTER: Duration = Duration::from_secs(30);
+/// How often an unused board is checked on.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the title card is up before a recording plays, in seconds.
+const TITLE_SECONDS: u32 = 3;
+
+/// Takes turns showing exhibitions and loaded replays on the board.
+#[derive(Default)]
+pub struct Stage {
+    showing: Mutex<()>,
+    /// Bumped by every loaded replay, which ends whatever the board shows.
+    loaded: AtomicU64,
+}
+
+/// Something to play on an unused board.
+pub enum Exhibit {
+    /// A recording of `--exhibition`, read anew every time it's played.
+    File(String),
+    /// A game of the scripted players of `--demo`, until somebody plays.
+    Demo { title: String, recording: Box<Recording> },
+}
+
+/// What tells an unused board from one that's been played on since: the
+/// game, the number of inputs it got and the replays loaded.
+type Idle = (String, usize, u64);
+
+/// Play `exhibits` on the board in turn, each after a title card, once
+/// nobody played for `IDLE_AFTER` while somebody watched. Recordings that
+/// can't be played are skipped with a warning, the games of `--demo` once
+/// its data is gone. Any input to the real game ends the exhibition and
+/// brings the real board back.
+pub async fn run(state: AppState, mut exhibits: Vec<Exhibit>) {
+    let mut next = 0;
+    loop {
+        let idle = wait_until_idle(&state).await;
+        if !state.demo.load(Ordering::Relaxed) {
+            exhibits.retain(|exhibit| matches!(exhibit, Exhibit::File(_)));
+        }
+        if exhibits.is_empty() {
+            return;
+        }
+        let showing = state.stage.showing.lock().await;
+        let mut failed = 0;
+        while failed < exhibits.len() && idle_since(&state).await.as_ref() == Some(&idle) {
+            let exhibit = &exhibits[next % exhibits.len()];
+            next += 1;
+            match play(&state, exhibit, &idle).await {
+                Ok(()) => failed = 0,
+                Err(err) => {
+                    eprintln!("warning: skipping exhibition {}: {err}", exhibit.title());
+                    failed += 1;
+                }
+            }
+        }
+        drop(showing);
+        render_all(&state).await;
+    }
+}
+
+/// Play `replay` on the board in real time, ending whatever it showed
+/// before. Any input to the real game ends it like an exhibition.
+pub async fn show_replay(state: AppState, replay: Replay) {
+    state.stage.loaded.fetch_add(1, Ordering::Relaxed);
+    let showing = state.stage.showing.lock().await;
+    let Some(idle) = idle_since(&state).await else {
+        return;
+    };
+    let mut board = replay.board();
+    for frame in &replay.frames {
+        if idle_since(&state).await.as_ref() != Some(&idle) {
+            break;
+        }
+        frame.apply(&mut board);
+        render(&state, &board);
+        sleep(config::step_interval(1)).await;
+    }
+    drop(showing);
+    render_all(&state).await;
+}
+
+/// `None` while the game isn't waiting to be started or nobody's watching.
+async fn idle_since(state: &AppState) -> Option<Idle> {
+    let game = state.game.read().await;
+    let watched = state.update_tx.receiver_count() > 0;
+    let loaded = state.stage.loaded.load(Ordering::Relaxed);
+    (game.phase() == Phase::Waiting && watched)
+        .then(|| (game.id.clone(), game.input_log.len(), loaded))
+}
+
+async fn wait_until_idle(state: &AppState) -> Idle {
+    let mut unchanged: Option<(Instant, Idle)> = None;
+    loop {
+        let idle = idle_since(state).await;
+        match (&unchanged, idle) {
+            (Some((since, seen)), Some(idle)) if *seen == idle => {
+                if clock::now() - *since >= IDLE_AFTER {
+                    return idle;
+                }
+            }
+            (_, idle) => unchanged = idle.map(|idle| (clock::now(), idle)),
+        }
+        clock::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+impl Exhibit {
+    /// What the title card shows, the file name of a recording without its
+    /// extension.
+    fn title(&self) -> Cow<'_, str> {
+        match self {
+            Self::File(path) => Path::new(path)
+                .file_stem()
+                .map_or(path.into(), |stem| stem.to_string_lossy()),
+            Self::Demo { title, .. } => title.into(),
+        }
+    }
+}
+
+/// Show the title card of `exhibit` and play it in real time, stopping early
+/// once the board isn't `idle` anymore.
+async fn play(state: &AppState, exhibit: &Exhibit, idle: &Idle) -> Result<(), String> {
+    let read;
+    let recording = match exhibit {
+        Exhibit::File(path) => {
+            let path = path.clone();
+            read = tokio::task::spawn_blocking(move || Recording::read(&path))
+                .await
+                .map_err(|err| err.to_string())??;
+            &read
+        }
+        Exhibit::Demo { recording, .. } => recording,
+    };
+    let title = exhibit.title();
+    show_banner(state, &format!("Exhibition: {title}"), Some(TITLE_SECONDS));
+    clock::sleep(Duration::from_secs(TITLE_SECONDS.into())).await;
+    let interval = config::step_interval(recording.settings.rules.substeps);
+    let mut playback = Playback::new(recording);
+    while playback.step()? {
+        if idle_since(state).await.as_ref() != Some(idle) {
+            return Ok(());
+        }
+        if playback.game().substep == 0 {
+            render(state, playback.game());
+        }
+        sleep(interval).await;
+    }
+    Ok(())
+}
+
+/// Draw a game that isn't the real one on the board, leaving out what didn't
+/// change.
+fn render(state: &AppState, game: &GameState) {
+    broadcast(state, "ball", ball_fragment(state, game));
+    for side in Side::ALL {
+        broadcast(state, side.bat_event(), bat_fragment(state, game, side));
+    }
+    broadcast(state, "scoreboard", scoreboard_fragment(state, game));
+}
@@ -0,0 +1,148 @@
+use crate::clock;
+use crate::config::StartMode;
+use crate::events::LoggedEvent;
+use crate::recording::RecordedSettings;
+use crate::{GameState, LoggedInput};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Dumps kept in the crash dump directory, older ones are deleted.
+const KEPT_DUMPS: usize = 20;
+/// Inputs kept from the end of the input log.
+const INPUT_TAIL: usize = 200;
+
+/// Everything known about a game at the moment it broke an invariant.
+#[derive(Serialize)]
+pub struct CrashDump {
+    /// Unix time in milliseconds.
+    pub written_at: u64,
+    /// The room of the game, `None` for the one on `/`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// How far the system clock was ahead of `written_at` in milliseconds,
+    /// if it jumped since the server started.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_skew_ms: Option<i64>,
+    pub violations: Vec<String>,
+    pub state: GameState,
+    pub settings: RecordedSettings,
+    /// The last inputs applied, oldest first.
+    pub inputs: Vec<LoggedInput>,
+    /// The last events broadcast to clients, oldest first.
+    pub events: Vec<LoggedEvent>,
+}
+
+impl CrashDump {
+    /// Snapshot `game`, cheap enough to do while holding the game lock.
+    pub fn new(
+        game: &GameState,
+        violations: Vec<String>,
+        events: Vec<LoggedEvent>,
+        start_mode: StartMode,
+        room: Option<String>,
+        written_at: u64,
+    ) -> Self {
+        let tail = game.input_log.len().saturating_sub(INPUT_TAIL);
+        Self {
+            written_at,
+            room,
+            clock_skew_ms: clock::jumped_ms(),
+            violations,
+            state: game.clone(),
+            settings: RecordedSettings {
+                paddles: game.left.bats.len(),
+                rules: game.rules,
+                start_mode,
+            },
+            inputs: game.input_log[tail..].to_vec(),
+            events,
+        }
+    }
+
+    /// Write the dump to `dir` as `crash-<written_at>.json`, or
+    /// `crash-<room>-<written_at>.json` for a room's game, and delete all but
+    /// the newest dumps there. Blocks, so run it with `spawn_blocking`.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("can't create {}: {err}", dir.display()))?;
+        let name = match &self.room {
+            Some(room) => format!("crash-{room}-{}.json", self.written_at),
+            None => format!("crash-{}.json", self.written_at),
+        };
+        let path = dir.join(name);
+        let json = serde_json::to_vec_pretty(self).expect("crash dump serializes");
+        std::fs::write(&path, json)
+            .map_err(|err| format!("can't write {}: {err}", path.display()))?;
+        prune(dir);
+        Ok(path)
+    }
+}
+
+/// Delete the oldest dumps beyond `KEPT_DUMPS`, ignoring errors.
+fn prune(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut dumps: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let written_at = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("crash-")?
+                .strip_suffix(".json")?
+                .rsplit('-')
+                .next()?
+                .parse()
+                .ok()?;
+            Some((written_at, path))
+        })
+        .collect();
+    dumps.sort();
+    let excess = dumps.len().saturating_sub(KEPT_DUMPS);
+    for (_, path) in &dumps[..excess] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(room: Option<&str>, written_at: u64) -> CrashDump {
+        let game = GameState::default();
+        let violations = vec!["the ball left the board".to_string()];
+        let room = room.map(String::from);
+        CrashDump::new(&game, violations, Vec::new(), StartMode::FirstInput, room, written_at)
+    }
+
+    fn scratch_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("rspong-dumps-{}", crate::random_token()))
+    }
+
+    #[test]
+    fn dumps_of_rooms_at_the_same_time_are_kept_apart() {
+        let dir = scratch_dir();
+        let paths = [None, Some("a1"), Some("b2")].map(|room| dump(room, 1000).write(&dir));
+        let paths = paths.map(Result::unwrap);
+        assert!(paths[0].ends_with("crash-1000.json"));
+        assert!(paths[1].ends_with("crash-a1-1000.json"));
+        assert!(paths.iter().all(|path| path.exists()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_newest_dumps_of_all_rooms_are_kept() {
+        let dir = scratch_dir();
+        for written_at in 0..KEPT_DUMPS as u64 {
+            dump(Some("a1"), written_at * 2).write(&dir).unwrap();
+        }
+        dump(None, 1).write(&dir).unwrap();
+        dump(Some("b2"), 100).write(&dir).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), KEPT_DUMPS);
+        assert!(!dir.join("crash-a1-0.json").exists());
+        assert!(!dir.join("crash-1.json").exists());
+        assert!(dir.join("crash-a1-2.json").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
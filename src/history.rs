@@ -0,0 +1,241 @@
+use crate::hooks::GameEvent;
+use crate::rules::Scoring;
+use crate::{PointSample, Side};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Finished games kept, older ones are dropped.
+pub const KEPT_GAMES: usize = 50;
+
+/// How a finished game ended for one side.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameResult {
+    Win,
+    Loss,
+    /// Survival games end without a winner.
+    None,
+}
+
+/// A finished game from one side's point of view.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct SideResult {
+    pub result: GameResult,
+    pub score: u16,
+    pub opponent_score: u16,
+    /// Made up by `--demo`, gone once somebody plays.
+    pub demo: bool,
+    /// The inputs of a side looked scripted, see `regularity`.
+    pub assisted: bool,
+}
+
+/// A finished game as `/result/:id` shows it.
+#[derive(Clone, Serialize)]
+pub struct FinishedGame {
+    pub id: String,
+    pub winner: Option<Side>,
+    pub left: u16,
+    pub right: u16,
+    pub timeline: Vec<PointSample>,
+    pub scoring: Scoring,
+    /// Played by the scripted players of `--demo`.
+    pub demo: bool,
+    /// The sides whose inputs looked scripted, see `regularity`.
+    pub assisted: Vec<Side>,
+}
+
+impl FinishedGame {
+    /// The game that ended with `event`, if it's a `GameOver`.
+    pub fn ended_by(event: GameEvent) -> Option<Self> {
+        match event {
+            GameEvent::GameOver {
+                winner,
+                left,
+                right,
+                game_id,
+                timeline,
+                scoring,
+                assisted,
+                ..
+            } => Some(Self {
+                id: game_id,
+                winner,
+                left,
+                right,
+                timeline,
+                scoring,
+                demo: false,
+                assisted,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The last `KEPT_GAMES` finished games, for `/history` and `/result/:id`.
+#[derive(Clone, Default)]
+pub struct History {
+    games: Arc<Mutex<VecDeque<FinishedGame>>>,
+}
+
+impl History {
+    pub fn push(&self, game: FinishedGame) {
+        let mut games = self.games.lock().unwrap();
+        if games.len() == KEPT_GAMES {
+            games.pop_front();
+        }
+        games.push_back(game);
+    }
+
+    /// The kept game with `id`, if it's still kept.
+    pub fn game(&self, id: &str) -> Option<FinishedGame> {
+        let games = self.games.lock().unwrap();
+        games.iter().find(|game| game.id == id).cloned()
+    }
+
+    /// The kept games as `side` saw them, oldest first.
+    pub fn of(&self, side: Side) -> Vec<SideResult> {
+        let games = self.games.lock().unwrap();
+        games
+            .iter()
+            .map(|game| {
+                let (score, opponent_score) = match side {
+                    Side::Left => (game.left, game.right),
+                    Side::Right => (game.right, game.left),
+                };
+                let result = match game.winner {
+                    Some(winner) if winner == side => GameResult::Win,
+                    Some(_) => GameResult::Loss,
+                    None => GameResult::None,
+                };
+                SideResult {
+                    result,
+                    score,
+                    opponent_score,
+                    demo: game.demo,
+                    assisted: !game.assisted.is_empty(),
+                }
+            })
+            .collect()
+    }
+
+    /// Take back the flag of the game with `id` that a side's inputs looked
+    /// scripted, returning `false` if it isn't kept.
+    pub fn clear_assisted(&self, id: &str) -> bool {
+        let mut games = self.games.lock().unwrap();
+        let Some(game) = games.iter_mut().find(|game| game.id == id) else {
+            return false;
+        };
+        game.assisted.clear();
+        true
+    }
+
+    /// Drop the games of `--demo`.
+    pub fn forget_demo(&self) {
+        self.games.lock().unwrap().retain(|game| !game.demo);
+    }
+}
+
+/// Add every game that ends to `history`.
+pub async fn record(history: History, mut events: broadcast::Receiver<GameEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let Some(game) = FinishedGame::ended_by(event) {
+                    history.push(game);
+                }
+            }
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(id: &str, winner: Option<Side>, left: u16, right: u16) -> FinishedGame {
+        FinishedGame {
+            id: id.to_string(),
+            winner,
+            left,
+            right,
+            timeline: Vec::new(),
+            scoring: Scoring::Classic,
+            demo: false,
+            assisted: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn each_side_sees_its_own_results() {
+        let history = History::default();
+        history.push(finished("a", Some(Side::Left), 11, 4));
+        history.push(finished("b", None, 7, 0));
+        let results = |side| -> Vec<_> {
+            let results = history.of(side).into_iter();
+            results.map(|game| (game.result, game.score, game.opponent_score)).collect()
+        };
+        let left = [(GameResult::Win, 11, 4), (GameResult::None, 7, 0)];
+        assert_eq!(results(Side::Left), left);
+        let right = [(GameResult::Loss, 4, 11), (GameResult::None, 0, 7)];
+        assert_eq!(results(Side::Right), right);
+    }
+
+    #[test]
+    fn only_the_latest_games_are_kept() {
+        let history = History::default();
+        for n in 0..KEPT_GAMES + 2 {
+            history.push(finished(&n.to_string(), None, n as u16, 0));
+        }
+        assert_eq!(history.of(Side::Left).len(), KEPT_GAMES);
+        assert_eq!(history.of(Side::Left)[0].score, 2);
+        assert!(history.game("1").is_none());
+        assert_eq!(history.game(&(KEPT_GAMES + 1).to_string()).unwrap().left, 51);
+    }
+
+    #[test]
+    fn demo_games_and_assisted_flags_can_be_dropped() {
+        let history = History::default();
+        history.push(FinishedGame { demo: true, ..finished("demo", None, 3, 0) });
+        let assisted = FinishedGame {
+            assisted: vec![Side::Right],
+            ..finished("bot", Some(Side::Right), 2, 11)
+        };
+        history.push(assisted);
+        assert!(history.of(Side::Right)[1].assisted);
+        assert!(history.clear_assisted("bot"));
+        assert!(!history.clear_assisted("gone"));
+        history.forget_demo();
+        let results = history.of(Side::Right);
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].assisted && !results[0].demo);
+    }
+
+    #[tokio::test]
+    async fn only_game_overs_are_recorded() {
+        let history = History::default();
+        let (events, receiver) = broadcast::channel(8);
+        let recording = tokio::spawn(record(history.clone(), receiver));
+        events.send(GameEvent::RallyRecord { hits: 3 }).unwrap();
+        let game_over = GameEvent::GameOver {
+            winner: Some(Side::Left),
+            left: 11,
+            right: 9,
+            game_id: "over".to_string(),
+            timeline: Vec::new(),
+            top_speed: 12.,
+            scoring: Scoring::Classic,
+            assisted: Vec::new(),
+        };
+        events.send(game_over).unwrap();
+        drop(events);
+        recording.await.unwrap();
+        let game = history.game("over").unwrap();
+        assert_eq!((game.winner, game.left, game.right), (Some(Side::Left), 11, 9));
+        assert_eq!(history.of(Side::Left).len(), 1);
+    }
+}
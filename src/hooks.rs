@@ -0,0 +1,473 @@
+use crate::rules::Scoring;
+use crate::{GameState, Phase, PointSample, Side};
+use serde::Serialize;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{sleep, timeout};
+
+/// A notable moment of a game, for home automation and similar hooks.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// `side` scored, by a return in survival or the other side's miss in classic.
+    Point { side: Side, left: u16, right: u16 },
+    GameOver {
+        winner: Option<Side>,
+        left: u16,
+        right: u16,
+        /// For `/result/:id`, left out of what hooks get.
+        #[serde(skip)]
+        game_id: String,
+        #[serde(skip)]
+        timeline: Vec<PointSample>,
+        /// The fastest any ball went, in field units per tick.
+        #[serde(skip)]
+        top_speed: f64,
+        #[serde(skip)]
+        scoring: Scoring,
+        /// The sides whose inputs looked scripted, see `regularity`.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        assisted: Vec<Side>,
+    },
+    /// The rally that just ended was the longest since the server started.
+    RallyRecord { hits: u32 },
+    /// The game broke an invariant, `dump` is where its crash dump was written.
+    InvariantViolation {
+        violations: Vec<String>,
+        dump: Option<String>,
+    },
+}
+
+/// Turns successive game states into `GameEvent`s.
+pub struct EventWatcher {
+    events: broadcast::Sender<GameEvent>,
+    score: (u16, u16),
+    phase: Phase,
+    tick: u64,
+    /// Horizontal direction of the ball, which only bats reverse.
+    heading_left: bool,
+    rally: u32,
+    record: u32,
+    /// The fastest speed of a ball in the current game.
+    top_speed: f64,
+}
+
+impl EventWatcher {
+    pub fn new(events: broadcast::Sender<GameEvent>, game: &GameState) -> Self {
+        Self {
+            events,
+            score: (game.left.score, game.right.score),
+            phase: game.phase(),
+            tick: game.tick,
+            heading_left: game.ball.velocity.0 < 0,
+            rally: 0,
+            record: 0,
+            top_speed: 0.,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<GameEvent> {
+        self.events.subscribe()
+    }
+
+    /// Compare `game` with the state seen last time and emit what happened.
+    pub fn observe(&mut self, game: &GameState) {
+        let score = (game.left.score, game.right.score);
+        let phase = game.phase();
+        let heading_left = game.ball.velocity.0 < 0;
+        if game.tick < self.tick {
+            self.rally = 0;
+            self.top_speed = 0.;
+        } else if heading_left != self.heading_left && game.split_ball.is_none() {
+            self.rally += 1;
+        }
+        for ball in game.split_ball.iter().chain([&game.ball]) {
+            self.top_speed = self.top_speed.max(ball.speed());
+        }
+        let game_over = phase.is_over() && !self.phase.is_over();
+        let scored = if score.0 > self.score.0 {
+            Some(Side::Left)
+        } else if score.1 > self.score.1 {
+            Some(Side::Right)
+        } else {
+            None
+        };
+        if let Some(side) = scored {
+            self.emit(GameEvent::Point {
+                side,
+                left: score.0,
+                right: score.1,
+            });
+        }
+        // A rally continues through points scored by returns in survival.
+        let rally_over =
+            game_over || (scored.is_some() && game.rules.scoring == Scoring::Classic);
+        if rally_over {
+            if self.rally > self.record {
+                self.record = self.rally;
+                self.emit(GameEvent::RallyRecord { hits: self.rally });
+            }
+            self.rally = 0;
+        }
+        if game_over {
+            self.emit(GameEvent::GameOver {
+                winner: game.winner,
+                left: score.0,
+                right: score.1,
+                game_id: game.id.clone(),
+                timeline: game.timeline.clone(),
+                top_speed: self.top_speed,
+                scoring: game.rules.scoring,
+                assisted: game.assisted(),
+            });
+        }
+        self.score = score;
+        self.phase = phase;
+        self.tick = game.tick;
+        self.heading_left = heading_left;
+    }
+
+    pub fn emit(&self, event: GameEvent) {
+        // Nobody listening is fine, the events are just for hooks.
+        let _ = self.events.send(event);
+    }
+}
+
+/// Send every event as a JSON line datagram to the socket bound at `path`,
+/// dropping it if nobody is bound there or the receiver can't keep up.
+pub async fn write_to_socket(path: String, mut events: broadcast::Receiver<GameEvent>) {
+    // Non-blocking std socket rather than tokio's, whose `try_send_to` drops
+    // datagrams until the runtime has seen the socket become writable.
+    let socket = match UnixDatagram::unbound().and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }) {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("can't open a socket for --event-socket: {err}");
+            return;
+        }
+    };
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        let mut line = serde_json::to_vec(&event).expect("event serializes");
+        line.push(b'\n');
+        let _ = socket.send_to(&line, &path);
+    }
+}
+
+/// Where `--webhook` posts score changes and game overs to, as JSON.
+#[derive(Clone, Debug)]
+pub struct WebhookUrl {
+    /// `host:port`, with the host in brackets if it's an IPv6 address.
+    authority: String,
+    path: String,
+}
+
+impl std::str::FromStr for WebhookUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("invalid webhook {s:?}, expected an http:// URL"))?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if authority.is_empty() {
+            return Err(format!("invalid webhook {s:?}, expected a host"));
+        }
+        let has_port = authority.rsplit_once(':').is_some_and(|(_, port)| !port.ends_with(']'));
+        Ok(Self {
+            authority: if has_port {
+                authority.to_string()
+            } else {
+                format!("{authority}:80")
+            },
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+        })
+    }
+}
+
+/// POST points and game overs to `url`, each from its own task so that a slow
+/// receiver holds up nothing but its own deliveries.
+pub async fn post_to_webhook(url: WebhookUrl, mut events: broadcast::Receiver<GameEvent>) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        if matches!(event, GameEvent::Point { .. } | GameEvent::GameOver { .. }) {
+            let body = serde_json::to_string(&event).expect("event serializes");
+            tokio::spawn(deliver(url.clone(), body));
+        }
+    }
+}
+
+/// Send `body`, retrying a few times with growing delays before giving up.
+async fn deliver(url: WebhookUrl, body: String) {
+    const ATTEMPTS: u32 = 3;
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    let mut retry_after = Duration::from_millis(500);
+    for attempt in 1..=ATTEMPTS {
+        let result = match timeout(TIMEOUT, post(&url, &body)).await {
+            Ok(result) => result,
+            Err(_) => Err("timed out".to_string()),
+        };
+        match result {
+            Ok(()) => return,
+            Err(err) if attempt == ATTEMPTS => {
+                eprintln!("giving up on webhook {}{}: {err}", url.authority, url.path);
+            }
+            Err(_) => {
+                sleep(retry_after).await;
+                retry_after *= 2;
+            }
+        }
+    }
+}
+
+async fn post(url: &WebhookUrl, body: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(&url.authority)
+        .await
+        .map_err(|err| format!("can't connect: {err}"))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        url.path,
+        url.authority,
+        body.len()
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|err| format!("can't send: {err}"))?;
+    let mut status_line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut status_line)
+        .await
+        .map_err(|err| format!("can't read the response: {err}"))?;
+    match status_line.split(' ').nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(format!("got {:?}", status_line.trim_end())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use serde_json::json;
+    use std::time::Instant;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, UnixDatagram};
+    use tokio::task::JoinHandle;
+
+    /// A request as the mock receiver got it.
+    struct Received {
+        at: Instant,
+        request_line: String,
+        body: serde_json::Value,
+    }
+
+    /// Answer one request after the other with `statuses`, on a free local port.
+    async fn receiver(statuses: Vec<u16>) -> (WebhookUrl, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let received = tokio::spawn(async move {
+            let mut received = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).await.unwrap();
+                    if header == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("Content-Length: ") {
+                        length = value.trim_end().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                stream.read_exact(&mut body).await.unwrap();
+                received.push(Received {
+                    at: Instant::now(),
+                    request_line: request_line.trim_end().to_string(),
+                    body: serde_json::from_slice(&body).unwrap(),
+                });
+                let response = format!("HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\n\r\n");
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            received
+        });
+        (url.parse().unwrap(), received)
+    }
+
+    fn point() -> String {
+        let event = GameEvent::Point {
+            side: Side::Left,
+            left: 1,
+            right: 0,
+        };
+        serde_json::to_string(&event).unwrap()
+    }
+
+    #[tokio::test]
+    async fn posts_the_event_as_json() {
+        let (url, received) = receiver(vec![204]).await;
+        deliver(url, point()).await;
+        let received = received.await.unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].request_line, "POST /hook HTTP/1.1");
+        assert_eq!(
+            received[0].body,
+            json!({"kind": "point", "side": "left", "left": 1, "right": 0})
+        );
+    }
+
+    #[test]
+    fn game_overs_leave_out_what_only_the_result_page_needs() {
+        let event = GameEvent::GameOver {
+            winner: Some(Side::Right),
+            left: 3,
+            right: 11,
+            game_id: "abc".to_string(),
+            timeline: Vec::new(),
+            top_speed: 12.,
+            scoring: Scoring::Classic,
+            assisted: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"kind": "game_over", "winner": "right", "left": 3, "right": 11})
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_with_growing_delays() {
+        let (url, received) = receiver(vec![500, 503, 200]).await;
+        deliver(url, point()).await;
+        let received = received.await.unwrap();
+        assert_eq!(received.len(), 3);
+        assert!(received.iter().all(|request| request.body["kind"] == "point"));
+        assert!(received[1].at - received[0].at >= Duration::from_millis(500));
+        assert!(received[2].at - received[1].at >= Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_three_attempts() {
+        let (url, received) = receiver(vec![500; 3]).await;
+        deliver(url.clone(), point()).await;
+        assert_eq!(received.await.unwrap().len(), 3);
+        // Nobody listens anymore, so a fourth attempt would fail to connect.
+        assert!(post(&url, &point()).await.is_err());
+    }
+
+    #[test]
+    fn urls_get_port_80_unless_they_have_one() {
+        let authority = |url: &str| url.parse::<WebhookUrl>().unwrap().authority;
+        assert_eq!(authority("http://example.com/hook"), "example.com:80");
+        assert_eq!(authority("http://example.com:8080/hook"), "example.com:8080");
+        assert_eq!(authority("http://10.0.0.2"), "10.0.0.2:80");
+        assert_eq!(authority("http://[::1]/hook"), "[::1]:80");
+        assert_eq!(authority("http://[fe80::1]"), "[fe80::1]:80");
+        assert_eq!(authority("http://[::1]:8080/hook"), "[::1]:8080");
+        assert_eq!("http://[::1]".parse::<WebhookUrl>().unwrap().path, "/");
+    }
+
+    #[test]
+    fn urls_need_http_and_a_host() {
+        assert!("https://example.com/hook".parse::<WebhookUrl>().is_err());
+        assert!("http:///hook".parse::<WebhookUrl>().is_err());
+    }
+
+    /// Everything `watcher`'s channel got so far.
+    fn drain(events: &mut broadcast::Receiver<GameEvent>) -> Vec<GameEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    fn classic_game(score_limit: u16) -> GameState {
+        let rules = Rules {
+            scoring: Scoring::Classic,
+            score_limit,
+            ..Rules::default()
+        };
+        GameState::new(1, rules)
+    }
+
+    #[test]
+    fn a_played_game_emits_its_points_and_its_end() {
+        let mut game = classic_game(3);
+        let mut watcher = EventWatcher::new(broadcast::channel(64).0, &game);
+        let mut events = watcher.subscribe();
+        game.start();
+        while !game.phase().is_over() {
+            game.step();
+            watcher.observe(&game);
+        }
+        let events = drain(&mut events);
+        let points: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                GameEvent::Point { left, right, .. } => Some((*left, *right)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(points.len(), usize::from(game.left.score + game.right.score));
+        assert_eq!(points.last(), Some(&(game.left.score, game.right.score)));
+        let Some(GameEvent::GameOver { winner, game_id, .. }) = events.last() else {
+            panic!("the game ended without a game over: {events:?}");
+        };
+        assert_eq!((*winner, game_id), (game.winner, &game.id));
+    }
+
+    #[test]
+    fn only_the_longest_rally_is_a_record() {
+        let mut game = classic_game(11);
+        let mut watcher = EventWatcher::new(broadcast::channel(64).0, &game);
+        let mut events = watcher.subscribe();
+        for (hits, score) in [(3, 1), (2, 2), (4, 3)] {
+            for _ in 0..hits {
+                game.ball.velocity.0 = -game.ball.velocity.0;
+                watcher.observe(&game);
+            }
+            game.left.score = score;
+            watcher.observe(&game);
+        }
+        let records: Vec<_> = drain(&mut events)
+            .into_iter()
+            .filter_map(|event| match event {
+                GameEvent::RallyRecord { hits } => Some(hits),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(records, [3, 4]);
+    }
+
+    #[tokio::test]
+    async fn events_are_sent_as_json_lines_to_the_socket() {
+        let path = std::env::temp_dir().join(format!("rspong-events-{}", crate::random_token()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let (events, receiver) = broadcast::channel(8);
+        let writer = tokio::spawn(write_to_socket(path.display().to_string(), receiver));
+        let event = GameEvent::RallyRecord { hits: 7 };
+        events.send(event.clone()).unwrap();
+        let mut datagram = [0; 256];
+        let length = timeout(Duration::from_secs(1), socket.recv(&mut datagram)).await;
+        let line = std::str::from_utf8(&datagram[..length.unwrap().unwrap()]).unwrap();
+        assert_eq!(line, format!("{}\n", serde_json::to_string(&event).unwrap()));
+        drop(events);
+        writer.await.unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use crate::Side;
+use serde::{Serialize, Serializer};
+
+/// Samples outside of this range (in ms) are clock jumps or retried requests.
+const MIN_SAMPLE_MS: f64 = -250.0;
+const MAX_SAMPLE_MS: f64 = 5000.0;
+/// Accepted samples are clipped to this multiple of the current estimate.
+const MAX_SAMPLE_FACTOR: f64 = 3.0;
+const SMOOTHING: f64 = 0.2;
+
+/// Exponentially weighted moving average of a player's input latency.
+#[derive(Clone, Copy, Default)]
+pub struct Latency {
+    ewma_ms: Option<f64>,
+}
+
+impl Latency {
+    /// Feed a sample (server receive time minus the clock-corrected client
+    /// send time) and return whether the rounded estimate changed.
+    pub fn observe(&mut self, sample_ms: f64) -> bool {
+        if !(MIN_SAMPLE_MS..=MAX_SAMPLE_MS).contains(&sample_ms) {
+            return false;
+        }
+        let before = self.rounded_ms();
+        let sample_ms = match self.ewma_ms {
+            Some(ewma) => sample_ms.clamp(0., (ewma * MAX_SAMPLE_FACTOR).max(1.)),
+            None => sample_ms.max(0.),
+        };
+        self.ewma_ms = Some(match self.ewma_ms {
+            Some(ewma) => ewma + SMOOTHING * (sample_ms - ewma),
+            None => sample_ms,
+        });
+        self.rounded_ms() != before
+    }
+
+    pub fn rounded_ms(&self) -> Option<u32> {
+        self.ewma_ms.map(|ewma| ewma.round() as u32)
+    }
+}
+
+impl Serialize for Latency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.rounded_ms().serialize(serializer)
+    }
+}
+
+/// Latency estimates for the players on either side.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct SeatLatency {
+    pub left: Latency,
+    pub right: Latency,
+}
+
+impl SeatLatency {
+    pub fn seat_mut(&mut self, side: Side) -> &mut Latency {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_sample_sets_the_estimate_and_later_ones_smooth_it() {
+        let mut latency = Latency::default();
+        assert_eq!(latency.rounded_ms(), None);
+        assert!(latency.observe(50.));
+        assert_eq!(latency.rounded_ms(), Some(50));
+        assert!(latency.observe(100.));
+        assert_eq!(latency.rounded_ms(), Some(60));
+        assert!(!latency.observe(60.));
+    }
+
+    #[test]
+    fn clock_jumps_are_ignored_and_spikes_clipped() {
+        let mut latency = Latency::default();
+        latency.observe(40.);
+        assert!(!latency.observe(-1000.));
+        assert!(!latency.observe(60_000.));
+        assert_eq!(latency.rounded_ms(), Some(40));
+        // Counts as three times the estimate, 120 ms.
+        latency.observe(4000.);
+        assert_eq!(latency.rounded_ms(), Some(56));
+    }
+
+    #[test]
+    fn early_clocks_count_as_no_latency() {
+        let mut latency = Latency::default();
+        latency.observe(-100.);
+        assert_eq!(latency.rounded_ms(), Some(0));
+    }
+
+    #[test]
+    fn each_side_has_its_own_estimate() {
+        let mut seats = SeatLatency::default();
+        seats.seat_mut(Side::Right).observe(80.);
+        assert_eq!(seats.left.rounded_ms(), None);
+        assert_eq!(serde_json::to_string(&seats).unwrap(), r#"{"left":null,"right":80}"#);
+    }
+}
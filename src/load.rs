@@ -0,0 +1,270 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// How often the load controller looks at the game loop's timing.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Overloaded samples in a row before the ball is broadcast less often.
+const OVERLOADED_SAMPLES: u32 = 2;
+/// Calm samples in a row before it's broadcast more often again.
+const CALM_SAMPLES: u32 = 10;
+
+/// How the game loop and the SSE subscribers kept up during one
+/// `SAMPLE_INTERVAL`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoadSample {
+    pub steps: u64,
+    /// Steps that came more than half a step interval later than due.
+    pub late_steps: u64,
+    /// How often a subscriber fell behind the broadcast channel.
+    pub lag_events: u64,
+}
+
+impl LoadSample {
+    /// More than a tenth of the steps were late, or subscribers lagged.
+    fn is_overloaded(&self) -> bool {
+        self.lag_events > 0 || self.late_steps * 10 > self.steps
+    }
+
+    /// At most one step in fifty was late and no subscriber lagged. Samples
+    /// in between neither lower nor raise the rate, so that a load just
+    /// around one of the thresholds doesn't flip it back and forth.
+    fn is_calm(&self) -> bool {
+        self.lag_events == 0 && self.late_steps * 50 <= self.steps
+    }
+}
+
+/// Picks the rate to broadcast the ball at: the configured one while the
+/// server keeps up, two thirds and then half of it (30 → 20 → 15 Hz by
+/// default) while it doesn't. Physics keep their rate either way.
+pub struct LoadController {
+    /// The configured rate first.
+    rates: [u32; 3],
+    level: usize,
+    overloaded: u32,
+    calm: u32,
+}
+
+impl LoadController {
+    pub fn new(broadcast_hz: u32) -> Self {
+        Self {
+            rates: [
+                broadcast_hz,
+                (broadcast_hz * 2 / 3).max(1),
+                (broadcast_hz / 2).max(1),
+            ],
+            level: 0,
+            overloaded: 0,
+            calm: 0,
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rates[self.level]
+    }
+
+    /// Account for the last `SAMPLE_INTERVAL`, returning the rate to broadcast at now.
+    pub fn sample(&mut self, sample: LoadSample) -> u32 {
+        if sample.is_overloaded() {
+            self.overloaded += 1;
+            self.calm = 0;
+        } else if sample.is_calm() {
+            self.calm += 1;
+            self.overloaded = 0;
+        } else {
+            self.overloaded = 0;
+            self.calm = 0;
+        }
+        if self.overloaded >= OVERLOADED_SAMPLES && self.level + 1 < self.rates.len() {
+            self.level += 1;
+            self.overloaded = 0;
+        } else if self.calm >= CALM_SAMPLES && self.level > 0 {
+            self.level -= 1;
+            self.calm = 0;
+        }
+        self.rate()
+    }
+}
+
+/// Which way the spectators' `Quality` goes.
+#[derive(Clone, Copy, Debug)]
+pub enum QualityShift {
+    Lower,
+    Raise,
+}
+
+/// Picks when to lower the quality of the spectators' updates for the room
+/// to keep to `--bandwidth-budget`: after bytes in more than the budget were
+/// sent in `OVERLOADED_SAMPLES` in a row, and when to raise it again: after
+/// at most half of the budget was in `CALM_SAMPLES` in a row.
+pub struct BudgetController {
+    /// Bytes per `SAMPLE_INTERVAL`.
+    budget: u64,
+    overloaded: u32,
+    calm: u32,
+}
+
+impl BudgetController {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            overloaded: 0,
+            calm: 0,
+        }
+    }
+
+    /// Account for the bytes sent during the last `SAMPLE_INTERVAL`.
+    pub fn sample(&mut self, sent: u64) -> Option<QualityShift> {
+        if sent > self.budget {
+            self.overloaded += 1;
+            self.calm = 0;
+        } else if sent * 2 <= self.budget {
+            self.calm += 1;
+            self.overloaded = 0;
+        } else {
+            self.overloaded = 0;
+            self.calm = 0;
+        }
+        if self.overloaded >= OVERLOADED_SAMPLES {
+            self.overloaded = 0;
+            Some(QualityShift::Lower)
+        } else if self.calm >= CALM_SAMPLES {
+            self.calm = 0;
+            Some(QualityShift::Raise)
+        } else {
+            None
+        }
+    }
+}
+
+/// Counters of the game loop's steps and the broadcast rate it's told to use.
+pub struct StepTiming {
+    steps: AtomicU64,
+    late_steps: AtomicU64,
+    broadcast_hz: AtomicU32,
+}
+
+#[derive(Clone, Copy, Serialize)]
+pub struct TimingStats {
+    pub steps: u64,
+    /// Steps that came more than half a step interval later than due.
+    pub late_steps: u64,
+    /// The rate the ball is broadcast at now, lowered under load.
+    pub broadcast_hz: u32,
+}
+
+impl StepTiming {
+    pub fn new(broadcast_hz: u32) -> Self {
+        Self {
+            steps: AtomicU64::new(0),
+            late_steps: AtomicU64::new(0),
+            broadcast_hz: AtomicU32::new(broadcast_hz),
+        }
+    }
+
+    pub fn record_step(&self, late: bool) {
+        self.steps.fetch_add(1, Ordering::Relaxed);
+        if late {
+            self.late_steps.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn broadcast_hz(&self) -> u32 {
+        self.broadcast_hz.load(Ordering::Relaxed)
+    }
+
+    pub fn set_broadcast_hz(&self, broadcast_hz: u32) {
+        self.broadcast_hz.store(broadcast_hz, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> TimingStats {
+        TimingStats {
+            steps: self.steps.load(Ordering::Relaxed),
+            late_steps: self.late_steps.load(Ordering::Relaxed),
+            broadcast_hz: self.broadcast_hz(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OVERLOADED: LoadSample = LoadSample { steps: 30, late_steps: 4, lag_events: 0 };
+    const LAGGING: LoadSample = LoadSample { steps: 30, late_steps: 0, lag_events: 1 };
+    /// Neither overloaded nor calm.
+    const BUSY: LoadSample = LoadSample { steps: 100, late_steps: 5, lag_events: 0 };
+    const CALM: LoadSample = LoadSample { steps: 100, late_steps: 2, lag_events: 0 };
+
+    #[test]
+    fn samples_are_overloaded_calm_or_neither() {
+        assert!(OVERLOADED.is_overloaded() && !OVERLOADED.is_calm());
+        assert!(LAGGING.is_overloaded() && !LAGGING.is_calm());
+        assert!(!BUSY.is_overloaded() && !BUSY.is_calm());
+        assert!(!CALM.is_overloaded() && CALM.is_calm());
+    }
+
+    #[test]
+    fn the_rate_steps_down_under_load_and_back_up_once_calm() {
+        let mut controller = LoadController::new(30);
+        assert_eq!(controller.sample(OVERLOADED), 30);
+        assert_eq!(controller.sample(LAGGING), 20);
+        assert_eq!(controller.sample(OVERLOADED), 20);
+        assert_eq!(controller.sample(OVERLOADED), 15);
+        // There's no lower rate than half.
+        assert_eq!(controller.sample(OVERLOADED), 15);
+        assert_eq!(controller.sample(OVERLOADED), 15);
+        for _ in 1..CALM_SAMPLES {
+            assert_eq!(controller.sample(CALM), 15);
+        }
+        assert_eq!(controller.sample(CALM), 20);
+        for _ in 0..CALM_SAMPLES {
+            controller.sample(CALM);
+        }
+        assert_eq!(controller.rate(), 30);
+    }
+
+    #[test]
+    fn samples_in_between_reset_both_streaks() {
+        let mut controller = LoadController::new(30);
+        for _ in 0..10 {
+            controller.sample(OVERLOADED);
+            assert_eq!(controller.sample(BUSY), 30);
+        }
+        controller.sample(OVERLOADED);
+        controller.sample(OVERLOADED);
+        for _ in 0..CALM_SAMPLES * 2 {
+            controller.sample(CALM);
+            controller.sample(CALM);
+            assert_eq!(controller.sample(BUSY), 20);
+        }
+    }
+
+    #[test]
+    fn low_rates_stay_above_zero() {
+        let mut controller = LoadController::new(1);
+        for _ in 0..4 {
+            assert_eq!(controller.sample(OVERLOADED), 1);
+        }
+    }
+
+    #[test]
+    fn quality_follows_the_bytes_sent_against_the_budget() {
+        let mut controller = BudgetController::new(1000);
+        assert!(controller.sample(1001).is_none());
+        assert!(matches!(controller.sample(1001), Some(QualityShift::Lower)));
+        // The streak starts over after a shift, and at exactly the budget.
+        assert!(controller.sample(1001).is_none());
+        assert!(controller.sample(1000).is_none());
+        assert!(controller.sample(1001).is_none());
+        for _ in 1..CALM_SAMPLES {
+            assert!(controller.sample(500).is_none());
+        }
+        assert!(matches!(controller.sample(0), Some(QualityShift::Raise)));
+        for _ in 1..CALM_SAMPLES {
+            assert!(controller.sample(500).is_none());
+        }
+        assert!(controller.sample(501).is_none(), "more than half isn't calm");
+        assert!(controller.sample(500).is_none());
+    }
+}
@@ -1,433 +1,5591 @@
+mod bots;
+mod card;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod chart;
+mod clock;
+mod coalesce;
+mod config;
+mod connections;
+mod controller;
+mod demo;
+mod diagnostics;
+mod events;
+mod exhibition;
+mod forensics;
+mod history;
+mod hooks;
+mod latency;
+mod load;
+mod metrics;
+mod modes;
+mod outbox;
+mod recording;
+mod records;
+mod regularity;
+mod renderers;
+mod replay;
+mod rooms;
+mod rules;
+mod sides;
+mod watchdog;
+
+#[cfg(all(feature = "test-hooks", not(debug_assertions)))]
+compile_error!("the test-hooks feature is for test builds, not release builds");
+#[cfg(all(feature = "chaos", not(debug_assertions)))]
+compile_error!("the chaos feature is for test builds, not release builds");
+
 use axum::Form;
 use axum::{
-    extract::State,
-    http::header,
+    body::Bytes,
+    extract::{self, Query, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware,
     response::{
         sse::{Event, KeepAlive, Sse},
-        Html,
+        Html, Response,
     },
-    routing::{get, post},
-    Router,
+    routing::{any, get, post},
+    Json, Router,
 };
-use futures_util::stream::Stream;
-use minijinja::{context, Environment};
+use bots::{BotSeats, Seat};
+use clock::now_ms;
+use coalesce::KeyBursts;
+use chrono::DateTime;
+use config::{BotRegistration, Command, Config, StartMode};
+use connections::ConnectionLimit;
+use controller::Controller;
+use diagnostics::InputEcho;
+use events::{EventLog, LoggedEvent};
+use exhibition::{Exhibit, Stage};
+use forensics::CrashDump;
+use history::{FinishedGame, History, SideResult};
+use hooks::{EventWatcher, GameEvent};
+use futures_util::stream::{self, Stream, StreamExt};
+use latency::SeatLatency;
+use load::{BudgetController, LoadController, LoadSample, StepTiming, TimingStats};
+use metrics::{ChannelMetrics, ChannelStats};
+use minijinja::value::{Value, ViaDeserialize};
+use minijinja::{context, AutoEscape, Environment};
+use modes::{Mode, MODES};
+use outbox::{Names, SubscriberStats, Subscribers, Update};
+use recording::Recording;
+use records::{Record, RecordKind, RecordLine, Records};
+use renderers::{GameSnapshot, RendererStats, Renderers};
+use replay::Replay;
+use rooms::Rooms;
+use rules::{
+    BatEdges, BatShape, Bindings, KeySet, MatchPointState, MoveResolution, Next, Point, Rules,
+    Scoring, ServeMode, Stamina, BAT_STRIP,
+};
+use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
-use std::{cmp, i32};
-use std::sync::Arc;
-use std::time::Duration;
+use sides::SideClaims;
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
+use tokio::task::{AbortHandle, JoinSet};
 use tokio::sync::{mpsc, Notify, RwLock};
-use tokio::time::sleep;
+use tokio::time::{interval, sleep, timeout, MissedTickBehavior};
 use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
-use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+use watchdog::{Watchdog, WatchdogStats};
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 struct Bat {
     up_key: String,
     down_key: String,
     position: u16,
     height: u16,
+    /// Distance moved since the last tick, negative when moving up.
+    velocity: i16,
+    /// Percent left of what the bat may still move, see `Rules::stamina`.
+    #[serde(default = "full_stamina")]
+    stamina: f32,
+    /// Ran out of stamina and moves at half speed until it recovers.
+    #[serde(default)]
+    tired: bool,
+}
+
+fn full_stamina() -> f32 {
+    MAX_STAMINA
+}
+
+/// The paddles on one side of the board and the side's score.
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+struct Team {
+    bats: Vec<Bat>,
     score: u16,
+    /// Accessibility assist level (0 to `MAX_ASSIST`) pulling the ball towards the bats.
+    assist: u8,
+    /// Whether the side said it's ready for the game to start.
+    #[serde(default)]
+    ready: bool,
+    /// Misses saved this game, see `Rules::forgiveness`.
+    #[serde(default)]
+    lucky_saves: u16,
+    /// Whether the server plays the side, toggled for the right one with
+    /// `AI_KEY`, kept across resets.
+    #[serde(default)]
+    ai: bool,
+    /// Where the server's bats expect the ball over the last
+    /// `Rules::ai_reaction` ticks, oldest first, `None` while it goes away.
+    #[serde(skip)]
+    ai_sight: VecDeque<Option<u16>>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 struct Ball {
     position: (u16, u16),
     velocity: (i16, i16),
 }
 
-#[derive(Clone, Serialize)]
+/// The stats of a survival game that went on until both sides were exhausted.
+#[derive(Clone, Copy, Serialize, Deserialize, JsonSchema)]
+struct Marathon {
+    /// Returns of both sides together.
+    returns: u16,
+    ticks: u64,
+}
+
+/// A point of a game, for the summary shown once it's over.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+struct PointSample {
+    tick: u64,
+    /// `None` for the miss ending a survival game.
+    scorer: Option<Side>,
+    /// Returns in the rally the point was scored in.
+    rally: u32,
+    /// Height of the left and the right bats after the point.
+    heights: (u16, u16),
+}
+
+/// Serves to one side `count` times, re-serving after each return or miss.
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
+struct Drill {
+    side: Side,
+    count: u16,
+    hits: u16,
+    misses: u16,
+}
+
+impl Drill {
+    fn is_done(&self) -> bool {
+        self.hits + self.misses >= self.count
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 struct GameState {
-    left: Bat,
-    right: Bat,
+    left: Team,
+    right: Team,
     ball: Ball,
+    /// The right half's ball in split-screen practice, where `ball` stays in
+    /// the left half.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    split_ball: Option<Ball>,
+    /// Serving practice replacing the normal scoring while it lasts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    drill: Option<Drill>,
+    rules: Rules,
+    is_started: bool,
     is_running: bool,
     is_lost: bool,
+    winner: Option<Side>,
+    /// Whether the next point decides the game, see `Rules::sudden_death`.
+    #[serde(default)]
+    sudden_death: bool,
+    /// Set when a survival game ended by exhaustion, see `Rules::marathon_after`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    marathon: Option<Marathon>,
+    /// Returns since the ball was last served.
+    #[serde(default)]
+    rally: u32,
+    /// Every point since the last reset.
+    #[serde(default)]
+    timeline: Vec<PointSample>,
+    /// Ticks stepped since the last reset.
+    tick: u64,
+    /// Physics steps done of the current tick, see `Rules::substeps`.
+    #[serde(default)]
+    substep: u8,
+    /// Unix time in milliseconds before which the game can't be started.
+    #[serde(default)]
+    scheduled_start: Option<u64>,
+    /// Which way the ball is served after a point, kept across resets.
+    #[serde(default)]
+    serve_mode: ServeMode,
+    /// The key sets of the bats, kept across resets.
+    #[serde(default)]
+    keys: Bindings,
+    /// Counts the changes of `keys` since the server started, for pages to
+    /// tell which keys they show.
+    #[serde(skip)]
+    bindings_revision: u32,
+    /// Inputs applied since the last reset, to re-simulate the game.
+    #[serde(skip)]
+    input_log: Vec<LoggedInput>,
+    /// Tells this game apart from the ones before and after the next reset,
+    /// for clients following several games. Minted anew by every reset.
+    #[serde(skip)]
+    id: String,
+}
+
+/// Everything besides the ticks of the game loop that changes a game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum Input {
+    Key { key: String },
+    Click {
+        x: f32,
+        y: f32,
+        /// Move towards where the ball will arrive rather than the click,
+        /// see `/?tap=intercept`.
+        #[serde(default)]
+        intercept: bool,
+    },
+    Start,
+    Assist { side: Side, level: u8 },
+    /// The game loop stopped, e.g. because nobody is watching anymore.
+    Halt,
+    /// Lock the game until the given Unix time in milliseconds, or unlock it.
+    Schedule { at: Option<u64> },
+    /// Enter or leave split-screen practice.
+    Practice { enabled: bool },
+    /// Start serving to `side` `count` times.
+    Drill { side: Side, count: u16 },
+    /// `side` is ready for the game to start, see `StartMode::Ready`.
+    Ready { side: Side },
+    /// Serve the ball after the next points like this.
+    ServeMode { mode: ServeMode },
+    /// Resize the bats of `side`, e.g. to handicap it.
+    BatHeight { side: Side, height: u16 },
+    /// Presses of a held key merged into one move, see `--coalesce-keys`.
+    HeldKey { key: String, presses: u16 },
+    /// Bind another set of keys to the bats of `side`.
+    Keys { side: Side, set: KeySet },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LoggedInput {
+    /// The `GameState::tick` the input was applied at.
+    tick: u64,
+    /// The `GameState::substep` the input was applied at.
+    #[serde(default)]
+    substep: u8,
+    input: Input,
+}
+
+/// Where a game is in its lifecycle, derived from the `GameState` flags.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Phase {
+    /// Not started since the last reset.
+    Waiting,
+    Running,
+    Paused,
+    /// Locked until its scheduled start.
+    Scheduled,
+    /// Ended by a miss in survival scoring.
+    Lost,
+    /// Ended by a side reaching the score limit.
+    Won,
+    /// Ended by a miss after both sides were exhausted in survival scoring.
+    MarathonComplete,
+}
+
+impl Phase {
+    fn is_over(self) -> bool {
+        matches!(self, Self::Lost | Self::Won | Self::MarathonComplete)
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
     game: Arc<RwLock<GameState>>,
     templates: Environment<'static>,
-    update_tx: broadcast::Sender<Event>,
+    update_tx: broadcast::Sender<Update>,
+    subscribers: Subscribers,
     renderer: mpsc::Sender<Renderable>,
     wake_up: Arc<Notify>,
+    config: Arc<Config>,
+    channel_metrics: Arc<ChannelMetrics>,
+    latency: Arc<Mutex<SeatLatency>>,
+    key_bursts: KeyBursts,
+    /// The keys a side was last rebound from, see `POST /keys`.
+    rebound: Arc<Mutex<Option<Rebound>>>,
+    connections: ConnectionLimit,
+    /// Recently broadcast events, for clients catching up via `/poll`.
+    events: Arc<Mutex<EventLog>>,
+    /// Operator message still shown to clients connecting until it expires.
+    announcement: Arc<Mutex<Option<Announcement>>>,
+    /// Counts down to the scheduled start, aborted when it's cancelled.
+    schedule: Arc<Mutex<Option<AbortHandle>>>,
+    hooks: Arc<Mutex<EventWatcher>>,
+    input_echo: InputEcho,
+    controller: Controller,
+    /// Which page plays which side, see `--claim-sides`.
+    sides: SideClaims,
+    bots: BotSeats,
+    /// Whether the game broke an invariant since it last held them all, so
+    /// that only the first tick of a violation is dumped.
+    invariant_broken: Arc<AtomicBool>,
+    watchdog: Arc<Watchdog>,
+    step_timing: Arc<StepTiming>,
+    history: History,
+    stage: Arc<Stage>,
+    /// Whether the history still has the made-up games of `--demo`.
+    demo: Arc<AtomicBool>,
+    renderers: Renderers,
+    records: Records,
+    /// The rooms besides the default one, see `POST /rooms`.
+    rooms: Rooms,
+    /// The id of the room this is the game of, `None` for the one on `/`.
+    room: Option<String>,
 }
 
-#[derive(Deserialize)]
+impl AppState {
+    /// The state of the room `id`: a new game with channels and tasks of its
+    /// own, sharing the config, history, records and connection limit.
+    fn room(&self, id: &str, render_tx: mpsc::Sender<Renderable>) -> Self {
+        let (tx, _) = broadcast::channel(self.config.channel_capacity);
+        let game = new_game(&self.config);
+        let (game_events, _) = broadcast::channel(16);
+        let mut templates = self.templates.clone();
+        // Only hex digits besides the slashes, which escaping would break in scripts.
+        templates.add_global("room_path", Value::from_safe_string(format!("/room/{id}")));
+        Self {
+            hooks: Arc::new(Mutex::new(EventWatcher::new(game_events, &game))),
+            game: Arc::new(RwLock::new(game)),
+            templates,
+            update_tx: tx,
+            subscribers: Subscribers::default(),
+            renderer: render_tx,
+            wake_up: Arc::new(Notify::new()),
+            latency: Arc::new(Mutex::new(SeatLatency::default())),
+            key_bursts: KeyBursts::default(),
+            rebound: Arc::new(Mutex::new(None)),
+            events: Arc::new(Mutex::new(EventLog::new(200))),
+            announcement: Arc::new(Mutex::new(None)),
+            schedule: Arc::new(Mutex::new(None)),
+            controller: Controller::default(),
+            sides: SideClaims::default(),
+            bots: BotSeats::default(),
+            invariant_broken: Arc::new(AtomicBool::new(false)),
+            watchdog: Arc::new(Watchdog::default()),
+            step_timing: Arc::new(StepTiming::new(self.config.broadcast_hz)),
+            stage: Arc::new(Stage::default()),
+            // `--renderer` draws the default room.
+            renderers: Renderers::default(),
+            room: Some(id.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+struct Announcement {
+    html: String,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize, JsonSchema)]
 struct KeyPress {
     last_key: String,
+    /// Client send time in ms since the epoch, corrected by the `/time` offset.
+    sent_at: Option<f64>,
+    /// Session to echo the server's decision to, see `/?debug-input=1`.
+    debug_input: Option<String>,
+    /// Token of the sending page, see `--single-controller`.
+    controller: Option<String>,
+    /// Token of the sending page, see `--bandwidth-budget`.
+    page: Option<String>,
 }
 
 #[derive(Deserialize)]
-struct MousePosition {
-    x: f32,
-    y: f32,
+struct PageQuery {
+    /// `1` to echo how the server handles this page's inputs back to it.
+    #[serde(rename = "debug-input")]
+    debug_input: Option<String>,
+    /// Listed on the scoreboard while the page is open, see `--player-names`.
+    name: Option<String>,
+    /// `intercept` for clicks and taps to go for the ball, see `GameState::click`.
+    tap: Option<String>,
 }
 
-enum Renderable {
-    Scoreboard,
-    BatLeft,
-    BatRight,
-    Ball,
+#[derive(Deserialize)]
+struct ReadyQuery {
+    side: Side,
 }
 
-enum Direction {
-    Up,
-    Down,
+#[derive(Deserialize)]
+struct SseQuery {
+    #[serde(rename = "debug-input")]
+    debug_input: Option<String>,
+    name: Option<String>,
+    /// Token of the page, see `--bandwidth-budget`.
+    page: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct ControllerForm {
+    controller: String,
+}
 
-impl Default for Ball {
-    fn default() -> Self {
-        Self {
-            position: (230, 420),
-            velocity: (15, 5),
-        }
-    }
+#[derive(Deserialize)]
+struct PageForm {
+    page: String,
 }
 
+#[derive(Deserialize)]
+struct HistoryQuery {
+    side: Side,
+}
 
-impl Bat {
-    fn default_left() -> Self {
-        Self {
-            up_key: "w".to_string(),
-            down_key: "s".to_string(),
-            position: 600,
-            score: 0,
-            height: 200,
-        }
-    }
+#[derive(Deserialize)]
+struct ServeModeForm {
+    mode: ServeMode,
+}
+
+#[derive(Deserialize)]
+struct KeysForm {
+    side: Side,
+    set: KeySet,
+}
+
+#[derive(Deserialize)]
+struct ModeForm {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct BatHeightForm {
+    side: Side,
+    height: u16,
+}
+
+#[derive(Deserialize)]
+struct AssistSetting {
+    side: Side,
+    level: u8,
+}
+
+#[derive(Deserialize)]
+struct AnnounceForm {
+    message: String,
+    /// Hide the message after this many seconds instead of keeping it up.
+    dismiss_after: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GameIdForm {
+    game_id: String,
+}
+
+#[derive(Deserialize)]
+struct ScheduleForm {
+    /// RFC 3339 timestamp, e.g. `2024-06-01T18:00:00+02:00`.
+    at: String,
+}
+
+#[derive(Deserialize)]
+struct PollQuery {
+    /// ID of the last event the client has seen.
+    #[serde(default)]
+    since: u64,
+    /// Seconds to wait for new events if there are none yet.
+    #[serde(default)]
+    wait: u64,
+}
+
+#[derive(Serialize)]
+struct PollResponse {
+    /// The game on the board now, which `events` may predate.
+    game_id: String,
+    last_id: u64,
+    /// Whether `events` is a snapshot of the whole board rather than what
+    /// happened since the requested ID, which was too old.
+    keyframe: bool,
+    events: Vec<LoggedEvent>,
+}
+
+#[derive(Deserialize)]
+struct PhysicsHashQuery {
+    ticks: u32,
+    /// Refused, as nothing in the physics is random to seed.
+    seed: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct PhysicsHash {
+    ticks: u32,
+    hash: String,
+}
+
+#[derive(Serialize)]
+struct Timing {
+    physics_hz: u32,
+    /// The broadcast rate the server was started with.
+    configured_broadcast_hz: u32,
+    #[serde(flatten)]
+    stats: TimingStats,
+}
+
+#[derive(Serialize)]
+struct TemplateReport {
+    passed: Vec<String>,
+    /// Error messages by template name.
+    failed: Vec<(String, String)>,
+}
+
+/// Everything `/lite` draws, sent as JSON on every update of the board, and
+/// the positions `correct` events snap the full page's board to.
+#[derive(Serialize)]
+struct LiteView {
+    game_id: String,
+    ball: (u16, u16),
+    /// `(position, height)` of every bat.
+    left: Vec<(u16, u16)>,
+    right: Vec<(u16, u16)>,
+    score: (u16, u16),
+    phase: Phase,
+}
 
-    fn default_right() -> Self {
+impl LiteView {
+    fn new(game: &GameState) -> Self {
+        let bats = |team: &Team| team.bats.iter().map(|bat| (bat.position, bat.height)).collect();
         Self {
-            up_key: "o".to_string(),
-            down_key: "l".to_string(),
-            position: 600,
-            score: 0,
-            height: 200,
+            game_id: game.id.clone(),
+            ball: game.ball.position,
+            left: bats(&game.left),
+            right: bats(&game.right),
+            score: (game.left.score, game.right.score),
+            phase: game.phase(),
         }
     }
+}
 
-    fn score_up(&mut self) {
-        self.score += 1;
-        self.height = cmp::max(10, self.height - self.height / 10);
-    }
+#[derive(Serialize)]
+struct BotRegistered {
+    /// Bearer token for `/bot/observe` and `/bot/act`.
+    token: String,
+    seat: Seat,
 }
 
+/// What `/bot/observe` tells a bot about the board.
+#[derive(Serialize)]
+struct BotView {
+    seat: Seat,
+    tick: u64,
+    ball_velocity: (i16, i16),
+    #[serde(flatten)]
+    board: LiteView,
+}
 
-impl GameState {
-    fn reset(&mut self) {
-        self.left = Bat::default_left();
-        self.right = Bat::default_right();
-        self.ball = Ball::default();
-        self.is_running = false;
-        self.is_lost = false;
-    }
+#[derive(Deserialize)]
+struct BotMove {
+    direction: Direction,
 }
 
+/// Where the ball collides with something, in game units, for `/debug/bounds`.
+#[derive(Serialize)]
+struct Bounds {
+    field_size: (u16, u16),
+    /// The `y` the ball bounces off the top and bottom walls at.
+    walls: (u16, u16),
+    /// The `x` of each side's center wall in split-screen practice.
+    center_walls: Option<(u16, u16)>,
+    bats: Vec<BatBounds>,
+}
 
-impl Default for GameState {
-    fn default() -> Self {
+#[derive(Serialize)]
+struct BatBounds {
+    side: Side,
+    /// Which of the side's stacked bats, from 1.
+    bat: usize,
+    /// The `x` the ball is returned at.
+    x: u16,
+    /// The ball is returned between `top` and `bottom`, and at them unless
+    /// `edges` is exclusive.
+    top: u16,
+    bottom: u16,
+    edges: BatEdges,
+    shape: BatShape,
+    tip_radius: u16,
+}
+
+impl Bounds {
+    fn new(game: &GameState) -> Self {
+        let bats = [Side::Left, Side::Right]
+            .into_iter()
+            .flat_map(|side| {
+                game.team(side)
+                    .bats
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, bat)| BatBounds {
+                        side,
+                        bat: index + 1,
+                        x: bat_line(side),
+                        top: bat.position,
+                        bottom: bat.position + bat.height,
+                        edges: game.rules.bat_edges,
+                        shape: game.rules.bat_shape,
+                        tip_radius: bat.tip_radius(game.rules.bat_shape),
+                    })
+            })
+            .collect();
         Self {
-            left: Bat::default_left(),
-            right: Bat::default_right(),
-            ball: Ball::default(),
-            is_running: false,
-            is_lost: false,
+            field_size: (FIELD_SIZE, FIELD_SIZE),
+            walls: (0, 990),
+            center_walls: game
+                .split_ball
+                .as_ref()
+                .map(|_| (center_line(Side::Left), center_line(Side::Right))),
+            bats,
         }
     }
 }
 
+#[derive(Serialize)]
+struct SimulatedPoint {
+    ticks: u32,
+    /// SSE events broadcast for the point.
+    events: usize,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct MousePosition {
+    /// Normalized to `0..1` or in pixels, depending on `--click-coordinates`.
+    x: f32,
+    y: f32,
+    /// Sent by pages opened with `/?tap=intercept`.
+    #[serde(default)]
+    intercept: bool,
+    sent_at: Option<f64>,
+    debug_input: Option<String>,
+    controller: Option<String>,
+    page: Option<String>,
+}
 
-fn get_initial_state(render_tx: mpsc::Sender<Renderable>) -> AppState {
-    let (tx, _) = broadcast::channel(50);
-    AppState {
-        game: Arc::new(RwLock::new(GameState::default())),
-        templates: create_template_env(),
-        update_tx: tx,
-        renderer: render_tx,
-        wake_up: Arc::new(Notify::new()),
-    }
+#[derive(Serialize)]
+struct ServerTime {
+    now: f64,
 }
 
-#[tokio::main]
-async fn main() {
-    let (render_tx, render_rx) = mpsc::channel(50);
-    let state = get_initial_state(render_tx);
-    tokio::spawn(game_loop(state.clone()));
-    tokio::spawn(render(state.clone(), render_rx));
+/// The response of `/state`.
+#[derive(Serialize, JsonSchema)]
+struct StateView {
+    #[serde(flatten)]
+    game: GameState,
+    phase: Phase,
+}
 
-    let app = Router::new()
-        // Game views:
-        .route("/", get(game_page))
-        .route("/keypress", post(keypress))
-        .route("/click", post(click))
-        .route("/game-sse", get(sse_handler))
-        .with_state(state)
-        // Bake static files into binary:
-        .route(
-            "/scripts.js",
-            get(|| async {
-                (
-                    [(header::CONTENT_TYPE, "text/javascript")],
-                    concat!(
-                        include_str!("../static/htmx.min.js"),
-                        include_str!("../static/sse.js")
-                    ),
-                )
-            }),
-        )
-        .route(
-            "/background.svg",
-            get(|| async {
-                (
-                    [(header::CONTENT_TYPE, "image/svg+xml")],
-                    include_str!("../static/bg.svg"),
-                )
-            }),
-        )
-        .route(
-            "/favicon.svg",
-            get(|| async {
-                (
-                    [(header::CONTENT_TYPE, "image/svg+xml")],
-                    include_str!("../static/favicon.svg"),
-                )
-            }),
-        );
+/// The documents described by `/schema`.
+#[derive(Serialize)]
+struct Schemas {
+    state: schemars::schema::RootSchema,
+    keypress: schemars::schema::RootSchema,
+    click: schemars::schema::RootSchema,
+}
 
-    let listener = tokio::net::TcpListener::bind("[::1]:3000").await.unwrap();
-    let addr = listener.local_addr().unwrap();
-    println!("Listening on http://{addr}");
-    axum::serve(listener, app).await.unwrap()
+#[derive(Deserialize)]
+struct DrillForm {
+    side: Side,
+    count: u16,
 }
 
-async fn render(state: AppState, mut render_rx: mpsc::Receiver<Renderable>) {
-    let ball_template = state.templates.get_template("ball").unwrap();
-    while let Some(renderable) = render_rx.recv().await {
-        match renderable {
-            Renderable::Scoreboard => {
-                render_scoreboard(&state).await;
-            }
-            Renderable::Ball => {
-                let game = state.game.read().await;
-                let _ = state.update_tx.send(
-                    Event::default().event("ball").data(
-                        ball_template
-                            .render(context! {game => *game })
-                            .expect("ball renders"),
-                    ),
-                );
-            }
-            Renderable::BatLeft => {
-                render_bat(&state, "bat_left").await;
-            }
-            Renderable::BatRight => {
-                render_bat(&state, "bat_right").await;
-            }
-        };
-    }
+#[derive(Serialize)]
+struct DrillResult {
+    hits: u16,
+    misses: u16,
+    hit_rate: f64,
 }
 
-fn create_template_env() -> Environment<'static> {
-    let mut env = Environment::new();
-    env.add_template(
-        "ball",
-        "<div class=ball style=\"left: {{game.ball.position[0] / 10 }}%; top: {{game.ball.position[1] / 10}}%;\"></div>"
-    ).expect("ball template compiled");
-    env.add_template(
-        "bat_left",
-        "<div id=\"bat_left\" class=bat style=\"top: {{game.left.position / 10}}%; height: {{game.left.height / 10}}vh;\"></div>",
-    ).expect("bat left template compiled");
-    env.add_template(
-        "bat_right",
-        "<div id=\"bat_right\" class=bat style=\"top: {{game.right.position / 10}}%; height: {{game.right.height / 10}}vh;\"></div>",
-    ).expect("bat right template");
-    env.add_template("scoreboard", include_str!("../templates/score.jinja2"))
-        .expect("scoreboard template compiled");
-    env.add_template("forkme", include_str!("../templates/forkme.jinja2"))
-        .expect("forkme template compiled");
-    env.add_template("game", include_str!("../templates/game.jinja2"))
-        .expect("game template compiled");
-    env
+#[derive(Serialize)]
+struct Status {
+    phase: Phase,
 }
 
-async fn game_loop(state: AppState) {
-    loop {
-        state.wake_up.notified().await;
-        {
-            let mut game = state.game.write().await;
-            if game.is_lost {
-                game.reset();
-                render_all(&state).await;
-            }
-        }
-        while {
-            let game = state.game.read().await;
-            game.is_running && !game.is_lost && state.update_tx.receiver_count() > 0
-        } {
-            update_ball_position(&state).await;
-            sleep(Duration::from_millis(32)).await; // ~ 30Hz
+/// Controls and rules of the running game, for `/help` and `/help.json`.
+#[derive(Serialize)]
+struct Help {
+    controls: Vec<BatControls>,
+    /// (Un)pauses the game, or starts a new one once it's over.
+    pause_key: &'static str,
+    /// Lets the computer play the right side, or the player again.
+    ai_key: &'static str,
+    start_mode: StartMode,
+    rules: Rules,
+    /// Width and height of the board in game units.
+    field_size: (u16, u16),
+    assist: (u8, u8),
+    /// Whether the computer plays each side.
+    ai: (bool, bool),
+    practice: bool,
+    serve_mode: ServeMode,
+    scheduled_start: Option<u64>,
+    keys: Bindings,
+    bindings_revision: u32,
+}
+
+#[derive(Serialize)]
+struct BatControls {
+    side: Side,
+    /// Which of the side's stacked bats, from 1.
+    bat: usize,
+    up_key: String,
+    down_key: String,
+}
+
+impl Help {
+    fn new(game: &GameState, config: &Config) -> Self {
+        let controls = [Side::Left, Side::Right]
+            .into_iter()
+            .flat_map(|side| {
+                game.team(side)
+                    .bats
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, bat)| BatControls {
+                        side,
+                        bat: index + 1,
+                        up_key: bat.up_key.clone(),
+                        down_key: bat.down_key.clone(),
+                    })
+            })
+            .collect();
+        Self {
+            controls,
+            pause_key: PAUSE_KEY,
+            ai_key: AI_KEY,
+            start_mode: config.start_mode,
+            rules: game.rules,
+            field_size: (FIELD_SIZE, FIELD_SIZE),
+            assist: (game.left.assist, game.right.assist),
+            ai: (game.left.ai, game.right.ai),
+            practice: game.split_ball.is_some(),
+            serve_mode: game.serve_mode,
+            scheduled_start: game.scheduled_start,
+            keys: game.keys,
+            bindings_revision: game.bindings_revision,
         }
-        state.game.write().await.is_running = false;
     }
 }
 
-async fn game_page(State(state): State<AppState>) -> Html<String> {
-    let tmpl = state.templates.get_template("game").unwrap();
-    Html(
-        tmpl.render(context! {
-            game => *state.game.read().await,
-            players => state.update_tx.receiver_count(),
-        })
-        .expect("game renders"),
-    )
+#[derive(Serialize)]
+struct Metrics {
+    #[serde(flatten)]
+    channel: ChannelStats,
+    sse_connections: usize,
+    watchdog: WatchdogStats,
+    timing: TimingStats,
+    renderers: Vec<RendererStats>,
 }
 
-async fn keypress(State(state): State<AppState>, Form(input): Form<KeyPress>) -> () {
-    let mut g = state.game.write().await;
-    let offset = 50;
+#[derive(Serialize)]
+struct Stats {
+    ping: SeatLatency,
+}
 
-    if input.last_key.as_str() == "p" {
-        g.is_running = !g.is_running;
-        state.wake_up.notify_one();
-        state.renderer.send(Renderable::Scoreboard).await.unwrap();
-    } else if g.is_running {
-        if input.last_key == g.left.up_key {
-            move_bat(&mut g.left, offset, Direction::Up);
-            state.renderer.send(Renderable::BatLeft).await.unwrap();
-        } else if input.last_key == g.left.down_key {
-            move_bat(&mut g.left, offset, Direction::Down);
-            state.renderer.send(Renderable::BatLeft).await.unwrap();
-        } else if input.last_key == g.right.up_key {
-            move_bat(&mut g.right, offset, Direction::Up);
-            state.renderer.send(Renderable::BatRight).await.unwrap();
-        } else if input.last_key == g.right.down_key {
-            move_bat(&mut g.right, offset, Direction::Down);
-            state.renderer.send(Renderable::BatRight).await.unwrap();
-        }
-    };
+/// What the game made of an input, for diagnosing unresponsive controls.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+enum Outcome {
+    MovedBat(Side),
+    Started,
+    Paused,
+    /// A setting or mode changed.
+    Changed,
+    /// The key press is merged into a later move, see `--coalesce-keys`.
+    Merged,
+    Ignored(Ignored),
 }
 
-async fn click(State(state): State<AppState>, Form(input): Form<MousePosition>) -> () {
-    let mut g = state.game.write().await;
-    if !g.is_running {
-        g.is_running = true;
-        state.wake_up.notify_one();
-        state.renderer.send(Renderable::Scoreboard).await.unwrap();
-    } else {
-        let (bat, renderable) = {
-            if input.x < 0.5 {
-                (&mut g.left, Renderable::BatLeft)
-            } else {
-                (&mut g.right, Renderable::BatRight)
-            }
-        };
-        let step = bat.height / 2;
-        let y = (input.y * 1000.) as u16;
-        if y < (bat.position + (bat.height / 2)) {
-            bat.position = if step < bat.position {bat.position - step} else {1}
+/// Why an input had no effect.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Ignored {
+    /// Bats only move while the game runs.
+    NotRunning,
+    AlreadyRunning,
+    /// The game waits for its scheduled start.
+    Locked,
+    UnknownKey,
+    /// Another move of the bat this tick takes precedence.
+    AlreadyMoved,
+    /// Another page is in control, see `--single-controller`.
+    NotController,
+    /// The page plays no side, see `--claim-sides`.
+    Spectator,
+    /// The page plays the other side, see `--claim-sides`.
+    OtherSide,
+    /// With `--start-mode ready`, a new game waits for both sides to be ready.
+    NotReady,
+    /// Readiness only counts before the game started.
+    AlreadyStarted,
+}
+
+impl Outcome {
+    fn moved(side: Side, applied: bool) -> Self {
+        if applied {
+            Self::MovedBat(side)
         } else {
-            bat.position = (bat.position + step).min(1000)
+            Self::Ignored(Ignored::AlreadyMoved)
         }
-        state.renderer.send(renderable).await.unwrap();
-    };
+    }
+
+    /// Pair a key press, click or start with what needs to be re-rendered for it.
+    fn rendered(self) -> (Self, Vec<Renderable>) {
+        let renderables = match self {
+            Self::MovedBat(side) => vec![Renderable::Bat(side)],
+            Self::Started | Self::Paused => vec![Renderable::Scoreboard],
+            Self::Changed | Self::Merged | Self::Ignored(_) => Vec::new(),
+        };
+        (self, renderables)
+    }
 }
 
-async fn sse_handler(
-    State(state): State<AppState>,
-) -> Sse<impl Stream<Item = Result<Event, BroadcastStreamRecvError>>> {
-    let stream = BroadcastStream::new(state.update_tx.subscribe());
-    state.renderer.send(Renderable::Scoreboard).await.unwrap();
-    Sse::new(stream).keep_alive(KeepAlive::default())
+/// Something a ball ran into during a tick.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Collision {
+    /// The top or bottom wall, which the right half's ball hits in
+    /// split-screen practice if `split`.
+    Wall { split: bool },
+    /// The center line in split-screen practice, from `side`'s half.
+    CenterWall { side: Side },
+    /// The `bat`-th bat of `side` returns the ball, `offset` below its top.
+    Bat { side: Side, bat: usize, offset: u16 },
+    /// The ball got past the bats of `side`.
+    Miss { side: Side },
 }
 
-fn move_bat(b: &mut Bat, offset: u16, direction: Direction) {
-    match direction {
-        Direction::Up => {
-            b.position = if offset < b.position {b.position - offset} else {0};
-        },
-        Direction::Down => {
-            b.position = cmp::min(1000 - b.height, b.position + offset);
+/// What resolving collisions changed in the game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Effect {
+    /// A ball bounced off a wall, turning the scoreboard's direction arrow.
+    Bounced,
+    /// The bats of a side changed size.
+    BatsChanged(Side),
+    ScoreChanged,
+    GameOver,
+    MatchPoint(MatchPointState),
+    /// A survival game ended by exhaustion.
+    Marathon,
+    /// A miss was saved by `Rules::forgiveness`.
+    LuckySave,
+}
+
+impl Effect {
+    fn renderables(self) -> Vec<Renderable> {
+        match self {
+            Self::Bounced | Self::ScoreChanged => vec![Renderable::Scoreboard],
+            Self::BatsChanged(side) => vec![Renderable::Bat(side)],
+            Self::GameOver => Renderable::all().into(),
+            Self::MatchPoint(match_point) => vec![Renderable::MatchPoint(match_point)],
+            Self::Marathon => vec![Renderable::Marathon],
+            Self::LuckySave => vec![Renderable::Scoreboard, Renderable::Sfx("lucky")],
         }
     }
 }
 
-async fn render_bat(state: &AppState, template_name: &str) {
-    let tmpl = state.templates.get_template(template_name).unwrap();
-    let _ = state.update_tx.send(
-        Event::default().event(template_name.to_string()).data(
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Renderable {
+    Scoreboard,
+    /// All bats of a side.
+    Bat(Side),
+    Ball,
+    MatchPoint(MatchPointState),
+    /// The overlay for a survival game ending by exhaustion.
+    Marathon,
+    /// Exact positions for clients to snap to, against drift from interpolating.
+    Correction,
+    /// A sound for clients to play, if they like.
+    Sfx(&'static str),
+}
+
+/// Which parts of the `base` layout a page renders.
+#[derive(Clone, Serialize)]
+struct PageBlocks {
+    forkme: bool,
+    controls: bool,
+    board: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Side {
+    Left,
+    Right,
+}
+
+
+impl Side {
+    const ALL: [Self; 2] = [Self::Left, Self::Right];
+
+    /// The SSE event with the side's bats.
+    fn bat_event(self) -> &'static str {
+        match self {
+            Self::Left => "bat_left",
+            Self::Right => "bat_right",
+        }
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
+
+impl Renderable {
+    fn all() -> [Self; 4] {
+        [Self::Bat(Side::Left), Self::Bat(Side::Right), Self::Scoreboard, Self::Ball]
+    }
+}
+
+
+impl PageBlocks {
+    fn full() -> Self {
+        Self {
+            forkme: true,
+            controls: true,
+            board: true,
+        }
+    }
+}
+
+
+impl Default for Ball {
+    fn default() -> Self {
+        Self {
+            position: (230, 420),
+            velocity: (15, 5),
+        }
+    }
+}
+
+
+impl Ball {
+    /// A ball launched from the center of the board towards `side`.
+    fn serve(side: Side) -> Self {
+        let default = Self::default();
+        let direction = match side {
+            Side::Left => -1,
+            Side::Right => 1,
+        };
+        Self {
+            position: (500, default.position.1),
+            velocity: (direction * default.velocity.0, default.velocity.1),
+        }
+    }
+
+    /// A ball for split-screen practice, launched from the middle of `side`'s
+    /// half towards its bats.
+    fn serve_practice(side: Side) -> Self {
+        let default = Self::default();
+        let (x, direction) = match side {
+            Side::Left => (250, -1),
+            Side::Right => (750, 1),
+        };
+        Self {
+            position: (x, default.position.1),
+            velocity: (direction * default.velocity.0, default.velocity.1),
+        }
+    }
+
+    /// Move by step `substep` of `substeps`' share of the velocity, the shares
+    /// of a tick adding up to exactly the velocity.
+    fn advance(&mut self, substep: u32, substeps: u32) {
+        let share = |velocity: i16| {
+            let velocity = velocity as i32;
+            let (substep, substeps) = (substep as i32, substeps as i32);
+            velocity * (substep + 1) / substeps - velocity * substep / substeps
+        };
+        // Kept on the board, as past an edge it would wrap around to the far
+        // side and miss the checks for the walls and bats.
+        let move_by = |velocity: i16, position: u16| {
+            (share(velocity) + i32::from(position)).clamp(0, FIELD_SIZE.into()) as u16
+        };
+        self.position = (
+            move_by(self.velocity.0, self.position.0),
+            move_by(self.velocity.1, self.position.1),
+        );
+    }
+
+    /// Bounce off the top or bottom wall, returning whether it did.
+    fn bounce_off_walls(&mut self) -> bool {
+        let y = i32::from(self.position.1);
+        if y <= 0 {
+            self.position = (self.position.0, 0);
+        } else if y >= LOWEST_BALL.into() {
+            self.position = (self.position.0, LOWEST_BALL);
+        } else {
+            return false;
+        }
+        self.velocity = (self.velocity.0, -self.velocity.1);
+        true
+    }
+
+    /// Distance moved per tick, in field units.
+    fn speed(&self) -> f64 {
+        f64::from(self.velocity.0).hypot(self.velocity.1.into())
+    }
+
+    /// CSS color from green for a flat ball to red at the fastest it can go.
+    fn color(&self) -> String {
+        let min_speed = f64::from(Self::default().velocity.0);
+        let max_speed = min_speed.hypot(MAX_VERTICAL_SPEED.into());
+        let speed = self.speed();
+        let ratio = ((speed - min_speed) / (max_speed - min_speed)).clamp(0., 1.);
+        let hue = 120. * (1. - ratio);
+        format!("hsl({hue:.0}, 100%, 50%)")
+    }
+}
+
+
+/// Width and height of the board in game units.
+const FIELD_SIZE: u16 = 1000;
+/// The `y` of the ball touching the bottom wall.
+const LOWEST_BALL: u16 = 990;
+/// How far a key press moves a bat.
+const KEY_STEP: u16 = 50;
+const PAUSE_KEY: &str = "p";
+/// Lets the server play the right side, or the player again.
+const AI_KEY: &str = "a";
+/// How far the bats the server plays move per tick at most, less than a
+/// held key.
+const AI_SPEED: u16 = 20;
+/// Height of a bat before it shrinks.
+const BAT_HEIGHT: u16 = 200;
+/// Bats shrink with every return in survival scoring, but not below this.
+const MIN_BAT_HEIGHT: u16 = 10;
+/// Bats resized with `POST /paddle-height` don't grow beyond this.
+const MAX_BAT_HEIGHT: u16 = FIELD_SIZE / 2;
+
+const MAX_ASSIST: u8 = 3;
+/// The assist never speeds the ball up vertically beyond this.
+const MAX_ASSISTED_SPEED: i16 = 20;
+/// Spin from the bats never speeds the ball up vertically beyond this.
+const MAX_VERTICAL_SPEED: i16 = 25;
+/// Length of the arcs at the ends of rounded bats, about the bats' width.
+const TIP_RADIUS: u16 = 10;
+const MAX_STAMINA: f32 = 100.;
+/// A tired bat moves at full speed again once its stamina is back above this.
+const RECOVERED_STAMINA: f32 = 30.;
+
+/// Starting positions of up to two stacked paddles per side, see
+/// `Rules::bat_start` for a single one.
+const STACKED_POSITIONS: [u16; 2] = [600, 200];
+
+
+impl Bat {
+    fn new(up_key: &str, down_key: &str, position: u16) -> Self {
+        Self {
+            up_key: up_key.to_string(),
+            down_key: down_key.to_string(),
+            position,
+            height: BAT_HEIGHT,
+            velocity: 0,
+            stamina: MAX_STAMINA,
+            tired: false,
+        }
+    }
+
+    /// How far `y` is from the bat, 0 if it's level with it.
+    fn distance_to(&self, y: u16) -> u16 {
+        if y < self.position {
+            self.position - y
+        } else {
+            y.saturating_sub(self.position + self.height)
+        }
+    }
+
+    fn covers(&self, y: u16, edges: BatEdges) -> bool {
+        match edges {
+            BatEdges::Inclusive => y >= self.position && y <= self.position + self.height,
+            BatEdges::Exclusive => y > self.position && y < self.position + self.height,
+        }
+    }
+
+    /// Vertical speed a ball returned at `y` gains from hitting the arc at one
+    /// of the ends of a rounded bat, the more the closer to the very tip.
+    fn tip_deflection(&self, y: u16, shape: BatShape) -> i16 {
+        let to_top = y.saturating_sub(self.position);
+        let to_bottom = (self.position + self.height).saturating_sub(y);
+        let radius = self.tip_radius(shape);
+        if to_top < radius {
+            -((radius - to_top) as i16)
+        } else if to_bottom < radius {
+            (radius - to_bottom) as i16
+        } else {
+            0
+        }
+    }
+
+    /// Get ready for a move according to how moves within one tick resolve,
+    /// returning `false` if the move is to be dropped.
+    fn prepare_move(&mut self, resolution: MoveResolution) -> bool {
+        match resolution {
+            MoveResolution::Sum => true,
+            MoveResolution::First => self.velocity == 0,
+            MoveResolution::Last => {
+                self.position = (i32::from(self.position) - i32::from(self.velocity)) as u16;
+                self.velocity = 0;
+                true
+            }
+        }
+    }
+
+    /// Length of the arcs at either end, 0 for rectangles.
+    fn tip_radius(&self, shape: BatShape) -> u16 {
+        match shape {
+            BatShape::Rectangle => 0,
+            BatShape::Rounded => TIP_RADIUS.min(self.height / 2),
+        }
+    }
+
+    /// How far a move that would normally go `full` distance goes.
+    fn stride(&self, full: u16) -> u16 {
+        if self.tired {
+            full / 2
+        } else {
+            full
+        }
+    }
+
+    /// Drain the stamina if the bat moved this tick or regenerate it if it
+    /// didn't, returning whether the drawn bar changed.
+    fn integrate_stamina(&mut self, stamina: Stamina) -> bool {
+        let shown = self.shown_stamina();
+        if self.velocity == 0 {
+            self.set_stamina(self.stamina + stamina.regen);
+        } else {
+            self.set_stamina(self.stamina - stamina.drain);
+        }
+        shown != self.shown_stamina()
+    }
+
+    fn set_stamina(&mut self, stamina: f32) {
+        self.stamina = stamina.clamp(0., MAX_STAMINA);
+        if self.stamina == 0. {
+            self.tired = true;
+        } else if self.stamina > RECOVERED_STAMINA {
+            self.tired = false;
+        }
+    }
+
+    /// The stamina bar as drawn, in steps of 10 percent.
+    fn shown_stamina(&self) -> (u8, bool) {
+        ((self.stamina / 10.) as u8, self.tired)
+    }
+
+    fn move_to(&mut self, position: u16) {
+        self.velocity += (i32::from(position) - i32::from(self.position)) as i16;
+        self.position = position;
+    }
+}
+
+
+impl Team {
+    fn new(side: Side, paddles: usize, bat_start: Option<u16>) -> Self {
+        let keys = KeySet::default().keys(side);
+        let lone_bat_start = bat_start.unwrap_or((FIELD_SIZE - BAT_HEIGHT) / 2);
+        Self {
+            bats: keys[..paddles]
+                .iter()
+                .zip(STACKED_POSITIONS)
+                .map(|(&(up_key, down_key), position)| {
+                    let position = if paddles == 1 { lone_bat_start } else { position };
+                    Bat::new(up_key, down_key, position)
+                })
+                .collect(),
+            score: 0,
+            assist: 0,
+            ready: false,
+            lucky_saves: 0,
+            ai: false,
+            ai_sight: VecDeque::new(),
+        }
+    }
+
+    fn score_up(&mut self) {
+        self.score += 1;
+        for bat in &mut self.bats {
+            bat.height = cmp::max(MIN_BAT_HEIGHT, bat.height - bat.height / 10);
+        }
+    }
+
+    /// The index of the first bat covering `y`.
+    fn bat_at(&self, y: u16, edges: BatEdges) -> Option<usize> {
+        self.bats.iter().position(|bat| bat.covers(y, edges))
+    }
+
+    fn has_key(&self, key: &str) -> bool {
+        self.bats
+            .iter()
+            .any(|bat| bat.up_key == key || bat.down_key == key)
+    }
+
+    fn bat_for_key(&mut self, key: &str) -> Option<(&mut Bat, Direction)> {
+        self.bats.iter_mut().find_map(|bat| {
+            if bat.up_key == key {
+                Some((bat, Direction::Up))
+            } else if bat.down_key == key {
+                Some((bat, Direction::Down))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The paddle whose center is closest to `y`.
+    fn nearest_bat(&self, y: u16) -> &Bat {
+        &self.bats[self.nearest_bat_index(y)]
+    }
+
+    fn nearest_bat_mut(&mut self, y: u16) -> &mut Bat {
+        let index = self.nearest_bat_index(y);
+        &mut self.bats[index]
+    }
+
+    fn nearest_bat_index(&self, y: u16) -> usize {
+        (0..self.bats.len())
+            .min_by_key(|&index| {
+                let bat = &self.bats[index];
+                (bat.position + bat.height / 2).abs_diff(y)
+            })
+            .expect("a team has at least one bat")
+    }
+}
+
+
+impl GameState {
+    fn new(paddles: usize, rules: Rules) -> Self {
+        Self {
+            left: Team::new(Side::Left, paddles, rules.bat_start),
+            right: Team::new(Side::Right, paddles, rules.bat_start),
+            ball: Ball::default(),
+            split_ball: None,
+            drill: None,
+            rules,
+            is_started: false,
+            is_running: false,
+            is_lost: false,
+            winner: None,
+            sudden_death: false,
+            marathon: None,
+            rally: 0,
+            timeline: Vec::new(),
+            tick: 0,
+            substep: 0,
+            scheduled_start: None,
+            serve_mode: ServeMode::default(),
+            keys: Bindings::default(),
+            bindings_revision: 0,
+            input_log: Vec::new(),
+            id: new_game_id(),
+        }
+    }
+
+    fn phase(&self) -> Phase {
+        if self.marathon.is_some() {
+            Phase::MarathonComplete
+        } else if self.winner.is_some() {
+            Phase::Won
+        } else if self.is_lost {
+            Phase::Lost
+        } else if self.is_running {
+            Phase::Running
+        } else if self.scheduled_start.is_some() {
+            Phase::Scheduled
+        } else if self.is_started {
+            Phase::Paused
+        } else {
+            Phase::Waiting
+        }
+    }
+
+    /// The sides whose inputs so far look scripted, see `regularity`.
+    fn assisted(&self) -> Vec<Side> {
+        regularity::flagged(&self.input_log, self.rules.substeps, |key| {
+            Side::ALL.into_iter().find(|&side| self.team(side).has_key(key))
+        })
+    }
+
+    fn team(&self, side: Side) -> &Team {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn team_mut(&mut self, side: Side) -> &mut Team {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    fn match_point(&self) -> MatchPointState {
+        if self.sudden_death {
+            return MatchPointState::SuddenDeath;
+        }
+        self.rules.match_point(self.left.score, self.right.score)
+    }
+
+    /// Accelerate the ball vertically by up to the assist level of the side it
+    /// approaches, towards the nearest bat, once it's within `assist_range`.
+    ///
+    /// With the default range and serve speed the assist acts for about 13
+    /// ticks, so the ball deviates by at most ~90 units per level (half a bat).
+    fn apply_assist(&mut self) {
+        let (side, distance) = if self.ball.velocity.0 < 0 {
+            (Side::Left, self.ball.position.0.saturating_sub(10))
+        } else {
+            (Side::Right, 990u16.saturating_sub(self.ball.position.0))
+        };
+        let team = self.team(side);
+        if self.rules.competitive || team.assist == 0 || distance > self.rules.assist_range {
+            return;
+        }
+        let bat = team.nearest_bat(self.ball.position.1);
+        let offset = i32::from(bat.position + bat.height / 2) - i32::from(self.ball.position.1);
+        let velocity = self.ball.velocity.1 + offset.signum() as i16 * i16::from(team.assist);
+        if velocity.abs() <= MAX_ASSISTED_SPEED {
+            self.ball.velocity.1 = velocity;
+        }
+    }
+
+    /// Move a bat of each side the server plays towards where the ball will
+    /// arrive, as it looked `Rules::ai_reaction` ticks ago, or back to the
+    /// middle while it goes away.
+    fn play_ai(&mut self, renderables: &mut Vec<Renderable>) {
+        let (reaction, resolution) = (self.rules.ai_reaction, self.rules.same_tick_moves);
+        for side in Side::ALL {
+            if !self.team(side).ai {
+                continue;
+            }
+            let arrival = intercept_y(self.ball_of(side), bat_line(side));
+            let team = self.team_mut(side);
+            team.ai_sight.push_back(arrival);
+            if team.ai_sight.len() <= usize::from(reaction) {
+                continue;
+            }
+            let target = team.ai_sight.pop_front().flatten().unwrap_or(FIELD_SIZE / 2);
+            let bat = team.nearest_bat_mut(target);
+            let (center, position) = (bat.position + bat.height / 2, bat.position);
+            let stride = bat.stride(AI_SPEED).min(center.abs_diff(target));
+            let direction = if target < center { Direction::Up } else { Direction::Down };
+            let moved = stride > 0 && move_bat(bat, stride, direction, resolution);
+            if moved && bat.position != position {
+                renderables.push(Renderable::Bat(side));
+            }
+        }
+    }
+
+    /// The ball was returned by a bat on `side`.
+    fn hit(&mut self, side: Side, effects: &mut Vec<Effect>) {
+        if let Some(drill) = &mut self.drill {
+            drill.hits += 1;
+            self.serve_drill(effects);
+            return;
+        }
+        self.rally += 1;
+        let point = self.rules.scoring_rule().on_hit(side);
+        self.score(point, side, effects);
+    }
+
+    /// Return a ball that got past the bats on `side` by no more than the
+    /// `Rules::forgiveness` distance, slowly and steeply away from the bat it
+    /// missed, if the side has saves left. Returns whether it did.
+    fn lucky_save(&mut self, side: Side, effects: &mut Vec<Effect>) -> bool {
+        let Some(forgiveness) = self.rules.forgiveness.filter(|_| !self.rules.competitive) else {
+            return false;
+        };
+        let y = self.ball.position.1;
+        let team = self.team(side);
+        let Some(bat) = team.bats.iter().min_by_key(|bat| bat.distance_to(y)) else {
+            return false;
+        };
+        if bat.distance_to(y) > forgiveness.distance || team.lucky_saves >= forgiveness.saves {
+            return false;
+        }
+        let vertical = if y < bat.position { -MAX_VERTICAL_SPEED } else { MAX_VERTICAL_SPEED };
+        let horizontal = (self.ball.velocity.0 / 2).abs().max(1);
+        self.ball.position = (bat_line(side), y);
+        self.ball.velocity = match side {
+            Side::Left => (horizontal, vertical),
+            Side::Right => (-horizontal, vertical),
+        };
+        self.team_mut(side).lucky_saves += 1;
+        self.rally += 1;
+        effects.push(Effect::LuckySave);
+        true
+    }
+
+    /// The ball got past the bats on `side`.
+    fn miss(&mut self, side: Side, effects: &mut Vec<Effect>) {
+        if let Some(drill) = &mut self.drill {
+            drill.misses += 1;
+            self.serve_drill(effects);
+            return;
+        }
+        let point = self.rules.scoring_rule().on_miss(side);
+        self.score(point, side, effects);
+    }
+
+    /// Apply what the scoring rule decided for the ball hitting or passing
+    /// the bats on `side`.
+    fn score(&mut self, point: Point, side: Side, effects: &mut Vec<Effect>) {
+        if let Some(scorer) = point.scorer {
+            let team = self.team_mut(scorer);
+            if point.shrinks_bats {
+                team.score_up();
+                effects.push(Effect::BatsChanged(scorer));
+            } else {
+                team.score += 1;
+            }
+            self.winner = if self.sudden_death {
+                Some(scorer)
+            } else {
+                self.rules.winner(self.left.score, self.right.score)
+            };
+        }
+        let game_over = point.next == Next::GameOver || self.winner.is_some();
+        if point.scorer.is_some() || game_over {
+            self.timeline.push(PointSample {
+                tick: self.tick,
+                scorer: point.scorer,
+                rally: self.rally,
+                heights: (self.left.bats[0].height, self.right.bats[0].height),
+            });
+        }
+        if game_over {
+            self.is_lost = true;
+            if self.winner.is_none() && self.is_exhausted() {
+                self.marathon = Some(Marathon {
+                    returns: self.left.score + self.right.score,
+                    ticks: self.tick,
+                });
+                effects.push(Effect::Marathon);
+            }
+            effects.push(Effect::GameOver);
+            return;
+        }
+        if point.next == Next::Serve {
+            self.rally = 0;
+            self.ball = self.serve_after_point(side);
+            if self.is_tied_at_match_point() {
+                self.enter_sudden_death(effects);
+            }
+        }
+        effects.push(Effect::ScoreChanged);
+        let match_point = self.match_point();
+        if point.next == Next::Serve && match_point != MatchPointState::None {
+            effects.push(Effect::MatchPoint(match_point));
+        }
+    }
+
+    /// The ball served after `conceder` lost a point, see `ServeMode`.
+    fn serve_after_point(&self, conceder: Side) -> Ball {
+        match self.serve_mode {
+            ServeMode::Fixed => Ball::serve(Side::Right),
+            ServeMode::TowardConceder => Ball::serve(conceder),
+            ServeMode::Random => {
+                // Rolled from the game rather than a random number generator,
+                // so that a replay of the inputs serves alike.
+                let roll = [self.tick, self.left.score.into(), self.right.score.into()]
+                    .iter()
+                    .fold(FNV_OFFSET_BASIS, |hash, n: &u64| fnv1a(hash, &n.to_le_bytes()));
+                let mut ball = Ball::serve(if roll & 1 == 0 { Side::Left } else { Side::Right });
+                if roll & 2 != 0 {
+                    ball.velocity.1 = -ball.velocity.1;
+                }
+                ball
+            }
+        }
+    }
+
+    fn is_tied_at_match_point(&self) -> bool {
+        self.rules.sudden_death
+            && matches!(
+                self.match_point(),
+                MatchPointState::Deuce | MatchPointState::DecidingPoint
+            )
+    }
+
+    /// Make the next point decide the game, with a faster serve and half as
+    /// high bats for tension.
+    fn enter_sudden_death(&mut self, effects: &mut Vec<Effect>) {
+        self.sudden_death = true;
+        self.ball.velocity.0 += self.ball.velocity.0 / 2;
+        for bat in self.left.bats.iter_mut().chain(&mut self.right.bats) {
+            bat.height = cmp::max(MIN_BAT_HEIGHT, bat.height / 2);
+        }
+        effects.extend([Effect::BatsChanged(Side::Left), Effect::BatsChanged(Side::Right)]);
+    }
+
+    /// What's wrong with the state, which is empty unless there's a bug.
+    fn violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        let balls = [("ball", Some(&self.ball)), ("split ball", self.split_ball.as_ref())];
+        for (name, ball) in balls {
+            let Some(ball) = ball else {
+                continue;
+            };
+            if ball.position.0 > FIELD_SIZE || ball.position.1 > FIELD_SIZE {
+                violations.push(format!("{name} at {:?} is off the board", ball.position));
+            }
+        }
+        for side in [Side::Left, Side::Right] {
+            for (index, bat) in self.team(side).bats.iter().enumerate() {
+                let name = format!("{side:?} bat {}", index + 1);
+                if bat.position > FIELD_SIZE {
+                    violations.push(format!("{name} at {} is off the board", bat.position));
+                }
+                if bat.height < MIN_BAT_HEIGHT {
+                    violations.push(format!("{name} is only {} high", bat.height));
+                }
+                if !(0. ..=MAX_STAMINA).contains(&bat.stamina) {
+                    violations.push(format!("{name} has {} stamina", bat.stamina));
+                }
+            }
+        }
+        if self.winner.is_some() && !self.is_lost {
+            violations.push("the game has a winner but isn't over".to_string());
+        }
+        if let Some(drill) = &self.drill {
+            if drill.hits + drill.misses > drill.count {
+                let served = drill.hits + drill.misses;
+                violations.push(format!("the drill served {served} of {} balls", drill.count));
+            }
+        }
+        violations
+    }
+
+    /// Both sides' bats are as small as they get and the game has gone on for
+    /// longer than `Rules::marathon_after`.
+    fn is_exhausted(&self) -> bool {
+        let Some(threshold) = self.rules.marathon_after else {
+            return false;
+        };
+        let bats_at_minimum = self
+            .left
+            .bats
+            .iter()
+            .chain(&self.right.bats)
+            .all(|bat| bat.height == MIN_BAT_HEIGHT);
+        bats_at_minimum && self.left.score + self.right.score > threshold
+    }
+
+    /// Serve the next ball of the drill, or stop once it's done.
+    fn serve_drill(&mut self, effects: &mut Vec<Effect>) {
+        let Some(drill) = &self.drill else {
+            return;
+        };
+        if drill.is_done() {
+            self.is_running = false;
+        } else {
+            self.ball = Ball::serve(drill.side);
+        }
+        effects.push(Effect::ScoreChanged);
+    }
+
+    /// The ball bats and walls of `side` act on: in split-screen practice each
+    /// half has its own.
+    fn ball_of(&self, side: Side) -> &Ball {
+        match (side, &self.split_ball) {
+            (Side::Right, Some(split_ball)) => split_ball,
+            _ => &self.ball,
+        }
+    }
+
+    fn ball_of_mut(&mut self, side: Side) -> &mut Ball {
+        match (side, &mut self.split_ball) {
+            (Side::Right, Some(split_ball)) => split_ball,
+            _ => &mut self.ball,
+        }
+    }
+
+    /// What the balls run into after moving this tick, in the order to resolve
+    /// it in.
+    fn detect_collisions(&self) -> Vec<Collision> {
+        let mut collisions = Vec::new();
+        let at_bats = |side: Side, ball: &Ball| match side {
+            Side::Left => ball.position.0 <= bat_line(side),
+            Side::Right => ball.position.0 >= bat_line(side),
+        };
+        let bat_or_miss = |side: Side, ball: &Ball| {
+            let team = self.team(side);
+            match team.bat_at(ball.position.1, self.rules.bat_edges) {
+                Some(bat) => Collision::Bat {
+                    side,
+                    bat,
+                    offset: ball.position.1 - team.bats[bat].position,
+                },
+                None => Collision::Miss { side },
+            }
+        };
+        if self.split_ball.is_some() {
+            for side in [Side::Left, Side::Right] {
+                let ball = self.ball_of(side);
+                let at_center = match side {
+                    Side::Left => ball.position.0 >= center_line(side),
+                    Side::Right => ball.position.0 <= center_line(side),
+                };
+                if at_bats(side, ball) {
+                    collisions.push(bat_or_miss(side, ball));
+                } else if at_center {
+                    collisions.push(Collision::CenterWall { side });
+                }
+            }
+            collisions.push(Collision::Wall { split: true });
+        } else if let Some(side) = [Side::Left, Side::Right]
+            .into_iter()
+            .find(|&side| at_bats(side, &self.ball))
+        {
+            collisions.push(bat_or_miss(side, &self.ball));
+        }
+        collisions.push(Collision::Wall { split: false });
+        collisions
+    }
+
+    /// Apply the consequences of `collisions` to the balls, scores and phase.
+    fn resolve(&mut self, collisions: Vec<Collision>) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        let practice = self.split_ball.is_some();
+        for collision in collisions {
+            match collision {
+                Collision::Wall { split } => {
+                    let ball = if split {
+                        self.split_ball.as_mut()
+                    } else {
+                        Some(&mut self.ball)
+                    };
+                    // A point may have served a new ball which is off the walls.
+                    if ball.is_some_and(Ball::bounce_off_walls) {
+                        effects.push(Effect::Bounced);
+                    }
+                }
+                Collision::CenterWall { side } => {
+                    let ball = self.ball_of_mut(side);
+                    ball.position = (center_line(side), ball.position.1);
+                    ball.velocity = (-ball.velocity.0, ball.velocity.1);
+                }
+                Collision::Bat { side, bat, .. } => {
+                    let ball = self.ball_of(side);
+                    let vertical_velocity =
+                        rebound(ball, &self.team(side).bats[bat], self.rules.bat_shape);
+                    let ball = self.ball_of_mut(side);
+                    ball.position = (bat_line(side), ball.position.1);
+                    ball.velocity = (-ball.velocity.0, vertical_velocity);
+                    if !practice {
+                        self.hit(side, &mut effects);
+                    }
+                }
+                Collision::Miss { side } if practice => {
+                    *self.ball_of_mut(side) = Ball::serve_practice(side);
+                }
+                Collision::Miss { side } => {
+                    if !self.lucky_save(side, &mut effects) {
+                        self.miss(side, &mut effects);
+                    }
+                }
+            }
+        }
+        effects
+    }
+
+    fn reset(&mut self) {
+        let bat_start = self.rules.bat_start;
+        let keys = self.keys;
+        for side in Side::ALL {
+            let team = self.team_mut(side);
+            *team = Team {
+                assist: team.assist,
+                ai: team.ai,
+                ..Team::new(side, team.bats.len(), bat_start)
+            };
+        }
+        self.ball = Ball::default();
+        if self.split_ball.is_some() {
+            self.serve_practice();
+        }
+        self.drill = None;
+        self.is_started = false;
+        self.is_running = false;
+        self.is_lost = false;
+        self.winner = None;
+        self.sudden_death = false;
+        self.marathon = None;
+        self.rally = 0;
+        self.timeline.clear();
+        self.tick = 0;
+        self.substep = 0;
+        self.input_log.clear();
+        // The serve mode outlasts the reset, so log it for replays of the new game.
+        if self.serve_mode != ServeMode::default() {
+            self.input_log.push(LoggedInput {
+                tick: 0,
+                substep: 0,
+                input: Input::ServeMode { mode: self.serve_mode },
+            });
+        }
+        self.bind_at_start(keys);
+        self.id = new_game_id();
+    }
+
+    /// Bind the key sets of `keys` to the bats of a game that hasn't started,
+    /// logging the ones besides the default for replays of it.
+    fn bind_at_start(&mut self, keys: Bindings) {
+        for side in Side::ALL {
+            let set = keys.of(side);
+            self.bind_keys(side, set);
+            if set != KeySet::default() {
+                self.input_log.push(LoggedInput {
+                    tick: 0,
+                    substep: 0,
+                    input: Input::Keys { side, set },
+                });
+            }
+        }
+    }
+
+    fn bind_keys(&mut self, side: Side, set: KeySet) {
+        self.keys.set(side, set);
+        for (bat, (up_key, down_key)) in self.team_mut(side).bats.iter_mut().zip(set.keys(side)) {
+            bat.up_key = up_key.to_string();
+            bat.down_key = down_key.to_string();
+        }
+    }
+
+    /// Serve one ball into each half, for split-screen practice.
+    fn serve_practice(&mut self) {
+        self.ball = Ball::serve_practice(Side::Left);
+        self.split_ball = Some(Ball::serve_practice(Side::Right));
+    }
+
+    /// Apply an input, record it in the input log and return what the game made
+    /// of it together with what needs to be re-rendered.
+    fn apply_input(&mut self, input: Input, start_mode: StartMode) -> (Outcome, Vec<Renderable>) {
+        let (outcome, renderables) = match &input {
+            Input::Key { key } if key == AI_KEY => {
+                let team = self.team_mut(Side::Right);
+                team.ai = !team.ai;
+                team.ai_sight.clear();
+                (Outcome::Changed, vec![Renderable::Scoreboard])
+            }
+            Input::Key { key } => self.press_key(key, 1, start_mode).rendered(),
+            Input::HeldKey { key, presses } => {
+                self.press_key(key, *presses, start_mode).rendered()
+            }
+            Input::Click { x, y, intercept } => {
+                self.click(*x, *y, *intercept, start_mode).rendered()
+            }
+            Input::Start => self.request_start(start_mode).rendered(),
+            Input::Assist { side, level } => {
+                self.team_mut(*side).assist = (*level).min(MAX_ASSIST);
+                (Outcome::Changed, vec![Renderable::Scoreboard])
+            }
+            Input::Halt => {
+                self.is_running = false;
+                (Outcome::Changed, Vec::new())
+            }
+            Input::Schedule { at } => {
+                self.scheduled_start = *at;
+                self.is_running = false;
+                (Outcome::Changed, vec![Renderable::Scoreboard])
+            }
+            Input::Practice { enabled } => {
+                if *enabled {
+                    self.serve_practice();
+                } else {
+                    self.ball = Ball::default();
+                    self.split_ball = None;
+                }
+                (Outcome::Changed, vec![Renderable::Ball])
+            }
+            Input::Drill { side, count } => {
+                self.drill = Some(Drill {
+                    side: *side,
+                    count: *count,
+                    hits: 0,
+                    misses: 0,
+                });
+                self.ball = Ball::serve(*side);
+                let (outcome, mut renderables) = self.start().rendered();
+                renderables.push(Renderable::Ball);
+                (outcome, renderables)
+            }
+            Input::Ready { .. } if self.is_started => {
+                (Outcome::Ignored(Ignored::AlreadyStarted), Vec::new())
+            }
+            Input::Ready { side } => {
+                self.team_mut(*side).ready = true;
+                if Side::ALL.into_iter().all(|side| self.team(side).ready) {
+                    self.start().rendered()
+                } else {
+                    (Outcome::Changed, vec![Renderable::Scoreboard])
+                }
+            }
+            Input::ServeMode { mode } => {
+                self.serve_mode = *mode;
+                (Outcome::Changed, vec![Renderable::Scoreboard])
+            }
+            Input::BatHeight { side, height } => {
+                let height = (*height).clamp(MIN_BAT_HEIGHT, MAX_BAT_HEIGHT);
+                for bat in &mut self.team_mut(*side).bats {
+                    bat.height = height;
+                    // Keep the bat on the board by moving it up if need be.
+                    bat.position = bat.position.min(FIELD_SIZE - height);
+                }
+                (Outcome::Changed, vec![Renderable::Bat(*side)])
+            }
+            Input::Keys { side, set } => {
+                self.bind_keys(*side, *set);
+                self.bindings_revision += 1;
+                (Outcome::Changed, vec![Renderable::Scoreboard])
+            }
+        };
+        self.input_log.push(LoggedInput {
+            tick: self.tick,
+            substep: self.substep,
+            input,
+        });
+        (outcome, renderables)
+    }
+
+    fn start(&mut self) -> Outcome {
+        if self.is_running {
+            return Outcome::Ignored(Ignored::AlreadyRunning);
+        }
+        if self.scheduled_start.is_some() {
+            return Outcome::Ignored(Ignored::Locked);
+        }
+        self.is_started = true;
+        self.is_running = true;
+        Outcome::Started
+    }
+
+    /// Start or resume the game for a player, unless a new game has to wait
+    /// for both sides to be ready.
+    fn request_start(&mut self, start_mode: StartMode) -> Outcome {
+        if start_mode == StartMode::Ready && !self.is_started {
+            return Outcome::Ignored(Ignored::NotReady);
+        }
+        self.start()
+    }
+
+    /// Press `key` as often as `presses`, the bat moving as far as that many
+    /// presses would at once.
+    fn press_key(&mut self, key: &str, presses: u16, start_mode: StartMode) -> Outcome {
+        let offset = KEY_STEP.saturating_mul(presses);
+        let resolution = self.rules.same_tick_moves;
+        if key == PAUSE_KEY {
+            if self.is_running {
+                self.is_running = false;
+                Outcome::Paused
+            } else {
+                self.request_start(start_mode)
+            }
+        } else if !self.is_running {
+            Outcome::Ignored(Ignored::NotRunning)
+        } else {
+            for side in Side::ALL {
+                if let Some((bat, direction)) = self.team_mut(side).bat_for_key(key) {
+                    let stride = bat.stride(offset);
+                    return Outcome::moved(side, move_bat(bat, stride, direction, resolution));
+                }
+            }
+            Outcome::Ignored(Ignored::UnknownKey)
+        }
+    }
+
+    /// A click at `x`, `y` (both in `0..1`) moves the nearest bat on that side towards it.
+    /// With `intercept`, it moves the bat nearest to where the ball will reach
+    /// the side's bats by a key press towards there instead, while the ball
+    /// comes towards them.
+    fn click(&mut self, x: f32, y: f32, intercept: bool, start_mode: StartMode) -> Outcome {
+        if !self.is_running {
+            return match start_mode {
+                StartMode::FirstInput => self.start(),
+                StartMode::Explicit | StartMode::Ready => Outcome::Ignored(Ignored::NotRunning),
+            };
+        }
+        let side = if x < 0.5 { Side::Left } else { Side::Right };
+        let resolution = self.rules.same_tick_moves;
+        let arrival = intercept
+            .then(|| intercept_y(self.ball_of(side), bat_line(side)))
+            .flatten();
+        if let Some(arrival) = arrival {
+            let bat = self.team_mut(side).nearest_bat_mut(arrival);
+            let center = bat.position + bat.height / 2;
+            let stride = bat.stride(KEY_STEP).min(center.abs_diff(arrival));
+            let direction = if arrival < center { Direction::Up } else { Direction::Down };
+            return Outcome::moved(side, move_bat(bat, stride, direction, resolution));
+        }
+        let stamina = self.rules.stamina;
+        let team = self.team_mut(side);
+        let y = (y * 1000.) as u16;
+        let bat = team.nearest_bat_mut(y);
+        if !bat.prepare_move(resolution) {
+            return Outcome::Ignored(Ignored::AlreadyMoved);
+        }
+        let step = bat.stride(bat.height / 2);
+        // Clicks jump the bat, so they cost a chunk on top of the tick's drain.
+        if let Some(stamina) = stamina {
+            bat.set_stamina(bat.stamina - stamina.click_cost);
+        }
+        if y < (bat.position + (bat.height / 2)) {
+            bat.move_to(if step < bat.position {bat.position - step} else {1})
+        } else {
+            bat.move_to((bat.position + step).min(1000))
+        }
+        Outcome::MovedBat(side)
+    }
+
+    /// Advance the balls by one physics step and return what needs to be
+    /// re-rendered, besides the ball itself. Ticks start with the assists,
+    /// and bats tire and stop counting as moving once all substeps are done.
+    fn step(&mut self) -> Vec<Renderable> {
+        let (substep, substeps) = (self.substep, self.rules.substeps.max(1));
+        if substep == 0 {
+            self.tick += 1;
+            self.apply_assist();
+        }
+        // Fast balls move in several parts, each checked for collisions, so
+        // that they can't skip over the bats.
+        let moves = self.rules.max_ball_step.map_or(1, |max_step| {
+            let speed = self.split_ball.iter().chain([&self.ball]).map(|ball| ball.velocity.0);
+            let step = speed.map(i16::unsigned_abs).max().unwrap_or(0).div_ceil(substeps.into());
+            u32::from(step.div_ceil(max_step.max(1))).max(1)
+        });
+        let mut renderables = Vec::new();
+        for part in 0..moves {
+            let (part, parts) = (u32::from(substep) * moves + part, u32::from(substeps) * moves);
+            self.ball.advance(part, parts);
+            if let Some(split_ball) = &mut self.split_ball {
+                split_ball.advance(part, parts);
+            }
+            let collisions = self.detect_collisions();
+            renderables.extend(self.resolve(collisions).into_iter().flat_map(Effect::renderables));
+            if self.phase() != Phase::Running {
+                break;
+            }
+        }
+        self.substep = (substep + 1) % substeps;
+        if self.substep != 0 {
+            return renderables;
+        }
+        if self.phase() == Phase::Running {
+            self.play_ai(&mut renderables);
+        }
+        let stamina = self.rules.stamina;
+        for side in Side::ALL {
+            let mut tiring = false;
+            for bat in &mut self.team_mut(side).bats {
+                if let Some(stamina) = stamina {
+                    tiring |= bat.integrate_stamina(stamina);
+                }
+                bat.velocity = 0;
+            }
+            if tiring {
+                renderables.push(Renderable::Bat(side));
+            }
+        }
+        renderables
+    }
+}
+
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new(1, Rules::default())
+    }
+}
+
+
+fn get_initial_state(
+    render_tx: mpsc::Sender<Renderable>,
+    config: Config,
+    records: Records,
+) -> AppState {
+    let (tx, _) = broadcast::channel(config.channel_capacity);
+    let game = new_game(&config);
+    let (game_events, _) = broadcast::channel(16);
+    AppState {
+        hooks: Arc::new(Mutex::new(EventWatcher::new(game_events, &game))),
+        game: Arc::new(RwLock::new(game)),
+        templates: create_template_env(&config),
+        update_tx: tx,
+        subscribers: Subscribers::default(),
+        renderer: render_tx,
+        wake_up: Arc::new(Notify::new()),
+        channel_metrics: Arc::new(ChannelMetrics::default()),
+        latency: Arc::new(Mutex::new(SeatLatency::default())),
+        key_bursts: KeyBursts::default(),
+        rebound: Arc::new(Mutex::new(None)),
+        connections: ConnectionLimit::new(config.max_connections),
+        events: Arc::new(Mutex::new(EventLog::new(200))),
+        announcement: Arc::new(Mutex::new(None)),
+        schedule: Arc::new(Mutex::new(None)),
+        input_echo: InputEcho::default(),
+        controller: Controller::default(),
+        sides: SideClaims::default(),
+        bots: BotSeats::default(),
+        invariant_broken: Arc::new(AtomicBool::new(false)),
+        watchdog: Arc::new(Watchdog::default()),
+        step_timing: Arc::new(StepTiming::new(config.broadcast_hz)),
+        history: History::default(),
+        stage: Arc::new(Stage::default()),
+        demo: Arc::new(AtomicBool::new(config.demo)),
+        renderers: Renderers::start(&config.renderers),
+        records,
+        rooms: Rooms::default(),
+        room: None,
+        config: Arc::new(config),
+    }
+}
+
+fn new_game(config: &Config) -> GameState {
+    let mut game = GameState::new(config.paddles, config.rules);
+    game.bind_at_start(config.keys);
+    game
+}
+
+/// Start what keeps the game of a room going and its pages up to date.
+fn spawn_room_tasks(state: &AppState, render_rx: mpsc::Receiver<Renderable>) -> JoinSet<()> {
+    let mut tasks = JoinSet::new();
+    tasks.spawn(supervise_render(state.clone(), render_rx));
+    if state.config.player_names {
+        tasks.spawn(redraw_on_leave(state.clone()));
+    }
+    tasks.spawn(adapt_broadcast_rate(state.clone()));
+    if let Some(budget) = state.config.bandwidth_budget {
+        tasks.spawn(keep_to_bandwidth_budget(state.clone(), budget));
+    }
+    tasks.spawn(watch_game_loop(state.clone()));
+    let events = state.hooks.lock().unwrap().subscribe();
+    tasks.spawn(history::record(state.history.clone(), events));
+    let events = state.hooks.lock().unwrap().subscribe();
+    tasks.spawn(records::record(state.clone(), events));
+    tasks
+}
+
+/// The routes of a room's game, on the default room at `/` and on the others
+/// under `/room/{id}`.
+fn game_routes(config: &Config) -> Router<AppState> {
+    let mut app = Router::new()
+        .route("/", get(game_page))
+        .route("/keypress", post(keypress))
+        .route("/click", post(click))
+        .route("/start", post(start))
+        .route("/ready", post(ready))
+        .route("/practice", post(enter_practice).delete(leave_practice))
+        .route("/drill", post(drill))
+        .route("/assist", post(assist))
+        .route("/serve-mode", post(serve_mode))
+        .route("/keys", post(rebind_keys))
+        .route("/paddle-height", post(paddle_height))
+        .route("/mode", get(modes).post(switch_mode))
+        .route("/controller/claim", post(claim_control))
+        .route("/controller/release", post(release_control))
+        .route("/sides/release", post(release_side))
+        .route("/game-sse", get(sse_handler))
+        .route("/lite", get(lite_page))
+        .route("/lite-sse", get(lite_sse))
+        .route("/poll", get(poll))
+        .route("/state", get(game_state))
+        .route("/status", get(status))
+        .route("/help", get(help_page))
+        .route("/help.json", get(help))
+        .route("/bot/register", post(register_bot).delete(release_bot))
+        .route("/bot/observe", get(observe_bot))
+        .route("/bot/act", post(act_bot))
+        .route("/replay.rpong", get(download_replay))
+        .route("/replay/load", post(load_replay));
+    if config.compat_refresh.is_some() {
+        app = app.route("/compat", get(compat_page));
+    }
+    if config.admin_token.is_some() {
+        app = app
+            .route("/admin/announce", post(announce))
+            .route("/admin/clear-assisted", post(clear_assisted))
+            .route(
+                "/admin/schedule-start",
+                post(schedule_start).delete(cancel_schedule),
+            );
+    }
+    if config.debug {
+        app = app
+            .route("/debug/physics-hash", get(physics_hash))
+            .route("/debug/bounds", get(bounds))
+            .route("/debug/simulate-point", post(simulate_point))
+            .route("/debug/input-log", get(input_log))
+            .route("/debug/templates", get(template_report))
+            .route("/debug/timing", get(timing))
+            .route("/debug/connections", get(connections));
+    }
+    #[cfg(feature = "test-hooks")]
+    {
+        app = app
+            .route("/__test/advance-time", post(advance_time))
+            .route("/__test/set-state", post(set_state));
+    }
+    #[cfg(feature = "chaos")]
+    {
+        app = app.route("/__chaos", post(chaos::configure));
+    }
+    app
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match Command::from_args(std::env::args().skip(1)) {
+        Ok(Command::Serve(config)) => *config,
+        Ok(Command::ReplayFile { path, trace }) => {
+            std::process::exit(recording::replay_file(&path, trace))
+        }
+        Ok(Command::Check(args)) => std::process::exit(check(args)),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2)
+        }
+    };
+    let favicon = match config.branding.favicon.as_deref().map(load_favicon) {
+        Some(Ok(favicon)) => Some(favicon),
+        Some(Err(err)) => {
+            eprintln!("{err}");
+            std::process::exit(2)
+        }
+        None => None,
+    };
+    let held_records = Records::load(config.records.clone()).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2)
+    });
+    let (render_tx, render_rx) = mpsc::channel(50);
+    let state = get_initial_state(render_tx, config, held_records);
+    let (passed, failed) = check_templates(&state.templates, &state.config);
+    println!("Templates rendering: {}", passed.join(", "));
+    for (name, err) in &failed {
+        eprintln!("template {name} fails to render: {err}");
+    }
+    if state.config.strict_templates && !failed.is_empty() {
+        std::process::exit(1)
+    }
+    let problems = path_problems(&state.config);
+    for problem in &problems {
+        eprintln!("{problem}");
+    }
+    if state.config.strict_startup && !(failed.is_empty() && problems.is_empty()) {
+        std::process::exit(1)
+    }
+    // Run as long as the server does.
+    let _default_room = spawn_room_tasks(&state, render_rx);
+    tokio::spawn(watch_channel_lag(state.clone()));
+    tokio::spawn(watch_wall_clock());
+    tokio::spawn(rooms::close_empty(state.rooms.clone()));
+    if let Some(path) = state.config.event_socket.clone() {
+        let events = state.hooks.lock().unwrap().subscribe();
+        tokio::spawn(hooks::write_to_socket(path, events));
+    }
+    let mut exhibits: Vec<Exhibit> =
+        state.config.exhibition.iter().cloned().map(Exhibit::File).collect();
+    if state.config.demo {
+        exhibits.extend(demo::prefill(&state));
+    }
+    if !exhibits.is_empty() {
+        tokio::spawn(exhibition::run(state.clone(), exhibits));
+    }
+    if let Some(url) = state.config.webhook.clone() {
+        let events = state.hooks.lock().unwrap().subscribe();
+        tokio::spawn(hooks::post_to_webhook(url, events));
+    }
+
+    let app = game_routes(&state.config)
+        .route("/rooms", post(rooms::create))
+        .route("/room/:id", any(rooms::dispatch))
+        .route("/room/:id/*rest", any(rooms::dispatch))
+        .route("/time", get(server_time))
+        .route("/schema", get(schema))
+        .route("/stats", get(stats))
+        .route("/history", get(history))
+        .route("/result/:id", get(result_page))
+        .route("/result/:id/card.png", get(result_card))
+        .route("/records", get(records_page))
+        .route("/records.json", get(records))
+        .route("/metrics", get(metrics));
+    let (debug, listen) = (state.config.debug, state.config.listen.clone());
+    let app = app
+        .with_state(state)
+        // Bake static files into binary:
+        .route(
+            "/scripts.js",
+            get(|| async {
+                (
+                    [(header::CONTENT_TYPE, "text/javascript")],
+                    concat!(
+                        include_str!("../static/htmx.min.js"),
+                        include_str!("../static/sse.js")
+                    ),
+                )
+            }),
+        )
+        .route(
+            "/background.svg",
+            get(|| async {
+                (
+                    [(header::CONTENT_TYPE, "image/svg+xml")],
+                    include_str!("../static/bg.svg"),
+                )
+            }),
+        )
+        .route(
+            "/favicon.svg",
+            get(|| async {
+                (
+                    [(header::CONTENT_TYPE, "image/svg+xml")],
+                    include_str!("../static/favicon.svg"),
+                )
+            }),
+        )
+        .route(
+            "/favicon.ico",
+            get(|| async {
+                (
+                    [(header::CONTENT_TYPE, "image/x-icon")],
+                    include_bytes!("../static/favicon.ico").as_slice(),
+                )
+            }),
+        );
+    let app = match favicon {
+        Some((content_type, icon)) => app.route(
+            "/favicon",
+            get(move || async move { ([(header::CONTENT_TYPE, content_type)], icon) }),
+        ),
+        None => app,
+    };
+    let app = app.layer(middleware::from_fn_with_state(debug, tag_request));
+
+    let listener = tokio::net::TcpListener::bind(&listen)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("can't listen on {listen}: {err}");
+            std::process::exit(1)
+        });
+    let addr = listener.local_addr().unwrap();
+    println!("Listening on http://{addr}");
+    axum::serve(listener, app).await.unwrap()
+}
+
+/// Read the icon file of `--favicon`, with its content type guessed from the extension.
+fn load_favicon(path: &str) -> Result<(&'static str, Bytes), String> {
+    let icon = std::fs::read(path).map_err(|err| format!("can't read favicon {path}: {err}"))?;
+    let content_type = match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    };
+    Ok((content_type, Bytes::from(icon)))
+}
+
+/// How long the render task stays down after failing.
+const RENDER_RESTART_DELAY: Duration = Duration::from_millis(100);
+
+/// Run the render task, restarting it whenever it fails. The receiving end of
+/// `AppState::renderer` outlives the task, so sending to it goes on working,
+/// and whatever a failed task didn't get to draw is redrawn. Aborting this
+/// aborts the render task too, as when a room is closed.
+async fn supervise_render(state: AppState, render_rx: mpsc::Receiver<Renderable>) {
+    let render_rx = Arc::new(tokio::sync::Mutex::new(render_rx));
+    let mut task = JoinSet::new();
+    task.spawn(render(state.clone(), render_rx.clone()));
+    while let Some(Err(err)) = task.join_next().await {
+        eprintln!("error: the render task failed, restarting it: {err}");
+        state.watchdog.record_render_restart();
+        // Don't spin if whatever failed fails again right away.
+        sleep(RENDER_RESTART_DELAY).await;
+        task.spawn(render(state.clone(), render_rx.clone()));
+        render_all(&state).await;
+    }
+}
+
+async fn render(
+    state: AppState,
+    render_rx: Arc<tokio::sync::Mutex<mpsc::Receiver<Renderable>>>,
+) {
+    let mut render_rx = render_rx.lock().await;
+    let step_interval = state.config.step_interval();
+    let mut moves = MoveBudget::new();
+    loop {
+        let received = match moves.due(step_interval) {
+            Some(due) => tokio::time::timeout_at(due.into(), render_rx.recv()).await.ok(),
+            None => Some(render_rx.recv().await),
+        };
+        let tick = state.game.read().await.tick;
+        for renderable in moves.roll(tick, Instant::now(), step_interval) {
+            draw(&state, renderable).await;
+        }
+        match received {
+            // Only the held back moves were due.
+            None => {}
+            Some(None) => return,
+            Some(Some(renderable)) => {
+                #[cfg(feature = "chaos")]
+                {
+                    sleep(chaos::delay(chaos::Point::Render)).await;
+                    if chaos::dropped(chaos::Point::Render) {
+                        continue;
+                    }
+                }
+                if moves.admit(&renderable, state.config.max_moves_per_tick) {
+                    draw(&state, renderable).await;
+                } else {
+                    state.channel_metrics.record_collapsed();
+                }
+            }
+        }
+    }
+}
+
+/// The bat and ball updates sent in the current tick, held back past
+/// `--max-moves-per-tick`. A game that isn't running stays on its tick, so a
+/// tick also ends after a step's time.
+struct MoveBudget {
+    tick: u64,
+    started: Instant,
+    sent: u32,
+    /// Updates past the limit, each sent once with the state the tick ends in.
+    held_back: Vec<Renderable>,
+}
+
+impl MoveBudget {
+    fn new() -> Self {
+        Self {
+            tick: 0,
+            started: Instant::now(),
+            sent: 0,
+            held_back: Vec::new(),
+        }
+    }
+
+    /// When the held back updates are to be sent, if there are any.
+    fn due(&self, step_interval: Duration) -> Option<Instant> {
+        (!self.held_back.is_empty()).then(|| self.started + step_interval)
+    }
+
+    /// Start a new tick if `tick` or the step's time at `now` ended the
+    /// current one, returning the updates held back in it.
+    fn roll(&mut self, tick: u64, now: Instant, step_interval: Duration) -> Vec<Renderable> {
+        if tick == self.tick && now < self.started + step_interval {
+            return Vec::new();
+        }
+        let held_back = mem::take(&mut self.held_back);
+        *self = Self {
+            tick,
+            started: now,
+            sent: held_back.len() as u32,
+            held_back: Vec::new(),
+        };
+        held_back
+    }
+
+    /// Whether `renderable` is to be sent right away, holding it back if it's
+    /// a move past `limit`.
+    fn admit(&mut self, renderable: &Renderable, limit: Option<u32>) -> bool {
+        if !matches!(renderable, Renderable::Ball | Renderable::Bat(_)) {
+            return true;
+        }
+        if limit.is_none_or(|limit| self.sent < limit) {
+            self.sent += 1;
+            return true;
+        }
+        if !self.held_back.contains(renderable) {
+            self.held_back.push(*renderable);
+        }
+        false
+    }
+}
+
+async fn draw(state: &AppState, renderable: Renderable) {
+    #[cfg(feature = "chaos")]
+    chaos::before_broadcast().await;
+    if !state.renderers.is_empty() {
+        let snapshot = GameSnapshot::new(&*state.game.read().await);
+        state.renderers.send(renderable, snapshot);
+    }
+    match renderable {
+        Renderable::Scoreboard => {
+            render_scoreboard(state).await;
+        }
+        Renderable::Ball => {
+            let game = state.game.read().await;
+            broadcast(state, "ball", ball_fragment(state, &game));
+        }
+        Renderable::Bat(side) => {
+            render_bat(state, side).await;
+        }
+        Renderable::MatchPoint(match_point) => {
+            render_match_point(state, match_point).await;
+        }
+        Renderable::Marathon => {
+            let tmpl = state.templates.get_template("marathon").unwrap();
+            let game = state.game.read().await;
+            broadcast(
+                state,
+                "marathon_complete",
+                tmpl.render(context! { game => *game, game_id => game.id })
+                    .expect("marathon renders"),
+            );
+        }
+        Renderable::Correction => {
+            let view = LiteView::new(&*state.game.read().await);
+            broadcast(
+                state,
+                "correct",
+                serde_json::to_string(&view).expect("view serializes"),
+            );
+        }
+        Renderable::Sfx(sound) => broadcast(state, "sfx", sound.to_string()),
+    }
+}
+
+fn create_template_env(config: &Config) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_auto_escape_callback(|_| AutoEscape::Html);
+    env.add_global("precision", config.coordinate_precision);
+    env.add_global("start_mode", Value::from_serialize(config.start_mode));
+    env.add_global("score_bars", config.score_bars);
+    env.add_global("every_key", KeySet::every_key(config.paddles));
+    env.add_function("score_progress", rules::progress);
+    // Like the HTML escaping, but leaves the slashes of a URL readable.
+    env.add_filter("url", |url: String| {
+        Value::from_safe_string(
+            url.replace('&', "&amp;")
+                .replace('"', "&quot;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+        )
+    });
+    // Percent-encodes the value of a query parameter.
+    env.add_filter("query", |value: String| {
+        value
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    char::from(byte).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect::<String>()
+    });
+    env.add_function("timeline_chart", |timeline: ViaDeserialize<Vec<PointSample>>| {
+        Value::from_safe_string(chart::timeline(&timeline))
+    });
+    env.add_template(
+        "ball",
+        "<div class=ball style=\"left: {{(game.ball.position[0] / 10)|round(precision) }}%; top: {{(game.ball.position[1] / 10)|round(precision)}}%; background-color: {{ ball_color }};\"></div>\
+        {% if game.split_ball %}<div class=\"ball split_ball\" style=\"left: {{(game.split_ball.position[0] / 10)|round(precision) }}%; top: {{(game.split_ball.position[1] / 10)|round(precision)}}%; background-color: {{ split_ball_color }};\"></div>{% endif %}"
+    ).expect("ball template compiled");
+    env.add_template(
+        "bat",
+        "{% for bat in game[side].bats %}<div id=\"bat_{{ side }}_{{ loop.index0 }}\" class=\"bat bat_{{ side }}{% if game.rules.bat_shape == 'rounded' %} rounded{% endif %}\" style=\"top: {{bat.position / 10}}%; height: {{bat.height / 10}}vh;\">\
+        {% if game.rules.stamina %}<div class=\"stamina{% if bat.tired %} tired{% endif %}\" style=\"height: {{ bat.stamina|int }}%;\"></div>{% endif %}</div>{% endfor %}",
+    ).expect("bat template compiled");
+    env.add_template("scoreboard", include_str!("../templates/score.jinja2"))
+        .expect("scoreboard template compiled");
+    env.add_template("match_point", include_str!("../templates/match_point.jinja2"))
+        .expect("match point template compiled");
+    env.add_template("marathon", include_str!("../templates/marathon.jinja2"))
+        .expect("marathon template compiled");
+    env.add_template("summary", include_str!("../templates/summary.jinja2"))
+        .expect("summary template compiled");
+    env.add_template("banner", include_str!("../templates/banner.jinja2"))
+        .expect("banner template compiled");
+    env.add_template("forkme", include_str!("../templates/forkme.jinja2"))
+        .expect("forkme template compiled");
+    env.add_template("controls", include_str!("../templates/controls.jinja2"))
+        .expect("controls template compiled");
+    env.add_template("board", include_str!("../templates/board.jinja2"))
+        .expect("board template compiled");
+    env.add_template("base", include_str!("../templates/base.jinja2"))
+        .expect("base template compiled");
+    env.add_template("game", include_str!("../templates/game.jinja2"))
+        .expect("game template compiled");
+    env.add_template("lite", include_str!("../templates/lite.jinja2"))
+        .expect("lite template compiled");
+    env.add_template("compat", include_str!("../templates/compat.jinja2"))
+        .expect("compat template compiled");
+    env.add_template("help", include_str!("../templates/help.jinja2"))
+        .expect("help template compiled");
+    env.add_template("result", include_str!("../templates/result.jinja2"))
+        .expect("result template compiled");
+    env.add_template("records", include_str!("../templates/records.jinja2"))
+        .expect("records template compiled");
+    env.add_template("debug_input", include_str!("../templates/debug_input.jinja2"))
+        .expect("debug input template compiled");
+    env
+}
+
+/// Render every template with sample games covering their branches, returning
+/// the names of those that rendered and the errors of those that didn't.
+fn check_templates<'env>(
+    templates: &'env Environment<'static>,
+    config: &Config,
+) -> (Vec<&'env str>, Vec<(&'env str, minijinja::Error)>) {
+    let fresh = GameState::new(config.paddles, config.rules);
+    let mut finished = GameState::new(
+        config.paddles,
+        Rules {
+            scoring: Scoring::Classic,
+            bat_shape: BatShape::Rounded,
+            stamina: Some(Stamina::default()),
+            ..config.rules
+        },
+    );
+    finished.left.score = 11;
+    finished.left.assist = 2;
+    finished.split_ball = Some(Ball::serve_practice(Side::Right));
+    finished.drill = Some(Drill {
+        side: Side::Left,
+        count: 3,
+        hits: 1,
+        misses: 2,
+    });
+    finished.sudden_death = true;
+    finished.marathon = Some(Marathon { returns: 40, ticks: 9000 });
+    finished.winner = Some(Side::Left);
+    finished.is_started = true;
+    finished.is_lost = true;
+    finished.timeline = vec![
+        PointSample { tick: 40, scorer: Some(Side::Right), rally: 3, heights: (200, 180) },
+        PointSample { tick: 90, scorer: None, rally: 1, heights: (200, 180) },
+    ];
+    let result = FinishedGame {
+        id: finished.id.clone(),
+        winner: finished.winner,
+        left: finished.left.score,
+        right: finished.right.score,
+        timeline: finished.timeline.clone(),
+        scoring: Scoring::Classic,
+        demo: true,
+        assisted: vec![Side::Right],
+    };
+    let (mut passed, mut failed) = (Vec::new(), Vec::new());
+    for (name, tmpl) in templates.templates() {
+        // The marathon overlay is only rendered once a game completed one.
+        let samples = if name == "marathon" { vec![&finished] } else { vec![&fresh, &finished] };
+        let failure = samples.into_iter().find_map(|game| {
             tmpl.render(context! {
-                game => *state.game.read().await
+                game => game,
+                game_id => game.id,
+                ball_color => game.ball.color(),
+                split_ball_color => game.split_ball.as_ref().map(Ball::color),
+                starts_in => 3,
+                players => 2,
+                player_names => Names { names: vec!["Name".to_string()], more: 2 },
+                demo => true,
+                name => "Name",
+                bindings_revision => 1,
+                bots => [Seat { side: Side::Right, bat: 1 }],
+                claimed => [Side::Left],
+                assisted => [Side::Right],
+                claim_sides => true,
+                ping => SeatLatency::default(),
+                branding => config.branding,
+                blocks => PageBlocks::full(),
+                poll_url => "/poll",
+                sse_url => "/lite-sse",
+                debug_input => "session",
+                controller => "token",
+                intercept_taps => true,
+                compat => true,
+                refresh => 2,
+                phase => game.phase(),
+                help => Help::new(game, config),
+                match_point => MatchPointState::Advantage(Side::Left),
+                side => Side::Left,
+                message => "Message",
+                dismiss_after => 10,
+                input => "{}",
+                outcome => "{}",
+                timeline => game.timeline,
+                result => result,
+                records => [RecordLine {
+                    title: "Record",
+                    value: "Value".to_string(),
+                    holder: Some(Side::Left),
+                }],
             })
-            .expect("bat renders"),
-        ),
+            .err()
+        });
+        match failure {
+            Some(err) => failed.push((name, err)),
+            None => passed.push(name),
+        }
+    }
+    (passed, failed)
+}
+
+/// Problems with the files `config` names that would only surface once
+/// they're used, except for the favicon which is read at startup.
+fn path_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(dir) = &config.crash_dump_dir {
+        if Path::new(dir).exists() && !Path::new(dir).is_dir() {
+            problems.push(format!("crash dump directory {dir} is not a directory"));
+        }
+    }
+    let dir = &config.card_dir;
+    if Path::new(dir).exists() && !Path::new(dir).is_dir() {
+        problems.push(format!("card directory {dir} is not a directory"));
+    }
+    for path in &config.exhibition {
+        if let Err(err) = Recording::read(path) {
+            problems.push(format!("exhibition {path}: {err}"));
+        }
+    }
+    if let Err(err) = Records::load(config.records.clone()) {
+        problems.push(err);
+    }
+    problems
+}
+
+/// Run `rspong check`: parse `args` like the server would, then load the
+/// favicon, check the paths and render the templates, reporting every problem
+/// found. Returns the exit code, 1 if there were any.
+fn check(args: Vec<String>) -> i32 {
+    let mut problems = Vec::new();
+    let config = Config::from_args(args).unwrap_or_else(|err| {
+        problems.push(format!("invalid arguments: {err}"));
+        Config::default()
+    });
+    if let Some(Err(err)) = config.branding.favicon.as_deref().map(load_favicon) {
+        problems.push(err);
+    }
+    problems.extend(path_problems(&config));
+    let templates = create_template_env(&config);
+    let (passed, failed) = check_templates(&templates, &config);
+    println!("Templates rendering: {}", passed.join(", "));
+    for (name, err) in failed {
+        problems.push(format!("template {name} fails to render: {err}"));
+    }
+    if problems.is_empty() {
+        println!("No problems found");
+        return 0;
+    }
+    for problem in &problems {
+        eprintln!("- {problem}");
+    }
+    eprintln!("{} problem(s) found", problems.len());
+    1
+}
+
+async fn game_loop(state: AppState) {
+    let step_interval = state.config.step_interval();
+    // Broadcast whenever the broadcast rate added up over the steps reaches
+    // the physics rate, which also works for rates that don't divide it.
+    let mut broadcast_credit = 0;
+    loop {
+        let is_over = state.game.read().await.phase().is_over();
+        match state.config.auto_reset {
+            Some(delay) if is_over => {
+                if clock::timeout(delay, state.wake_up.notified()).await.is_none() {
+                    reset_if_over(&state).await;
+                    continue;
+                }
+            }
+            _ => state.wake_up.notified().await,
+        }
+        reset_if_over(&state).await;
+        // Paced from the start of each step, so that the time the steps take
+        // doesn't slow the game down; late steps push the following ones back.
+        let mut steps = interval(step_interval);
+        steps.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        steps.tick().await;
+        let mut last_step: Option<Instant> = None;
+        while should_step(&state, &*state.game.read().await) {
+            let now = Instant::now();
+            let late = last_step.is_some_and(|at| now - at > step_interval * 3 / 2);
+            last_step = Some(now);
+            state.step_timing.record_step(late);
+            broadcast_credit += state.step_timing.broadcast_hz();
+            let due = broadcast_credit >= state.config.physics_hz;
+            if due {
+                broadcast_credit -= state.config.physics_hz;
+            }
+            update_ball_position(&state, due).await;
+            state.watchdog.beat();
+            let interval = state.config.correction_interval;
+            let game = state.game.read().await;
+            let (tick, substep) = (game.tick, game.substep);
+            drop(game);
+            if interval > 0 && substep == 0 && tick % interval == 0 {
+                state.renderer.send(Renderable::Correction).await.unwrap();
+            }
+            steps.tick().await;
+        }
+        state
+            .game
+            .write()
+            .await
+            .apply_input(Input::Halt, state.config.start_mode);
+    }
+}
+
+/// Whether the game loop should be stepping the game: while it's running and
+/// somebody is watching or drilling.
+fn should_step(state: &AppState, game: &GameState) -> bool {
+    let drilling = game.drill.as_ref().is_some_and(|drill| !drill.is_done());
+    game.phase() == Phase::Running && (state.update_tx.receiver_count() > 0 || drilling)
+}
+
+/// Clear the board for a new game once the last one is over.
+async fn reset_if_over(state: &AppState) {
+    let mut game = state.game.write().await;
+    if game.phase().is_over() {
+        game.reset();
+        render_all(state).await;
+    }
+}
+
+async fn game_page(State(state): State<AppState>, Query(query): Query<PageQuery>) -> Html<String> {
+    let tmpl = state.templates.get_template("game").unwrap();
+    let debug_input = matches!(query.debug_input.as_deref(), Some("1" | "true"))
+        .then(|| state.input_echo.open());
+    let controller = state.config.single_controller.then(random_token);
+    let page = (state.config.bandwidth_budget.is_some() || state.config.claim_sides)
+        .then(random_token);
+    let side = page
+        .as_deref()
+        .filter(|_| state.config.claim_sides)
+        .and_then(|page| {
+            let bot_sides: Vec<Side> = state.bots.seats().iter().map(|seat| seat.side).collect();
+            state.sides.claim(page, &bot_sides)
+        });
+    if side.is_some() {
+        state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    }
+    let game = state.game.read().await;
+    Html(
+        tmpl.render(context! {
+            game => *game,
+            game_id => game.id,
+            ball_color => game.ball.color(),
+            split_ball_color => game.split_ball.as_ref().map(Ball::color),
+            starts_in => starts_in(&game),
+            players => state.update_tx.receiver_count(),
+            player_names => player_names(&state),
+            demo => state.demo.load(Ordering::Relaxed),
+            bindings_revision => game.bindings_revision,
+            bots => state.bots.seats(),
+            claimed => state.config.claim_sides.then(|| state.sides.claimed()),
+            assisted => game.assisted(),
+            ping => *state.latency.lock().unwrap(),
+            branding => state.config.branding,
+            blocks => PageBlocks {
+                forkme: state.config.branding.ribbon_visible,
+                ..PageBlocks::full()
+            },
+            poll_url => "/poll",
+            debug_input => debug_input,
+            name => query.name,
+            intercept_taps => query.tap.as_deref() == Some("intercept"),
+            controller => controller,
+            page => page,
+            claim_sides => state.config.claim_sides,
+            side => side,
+            compat => state.config.compat_refresh.is_some(),
+        })
+        .expect("game renders"),
+    )
+}
+
+/// The board for browsers without scripts or server-sent events, rendered
+/// anew on every reload. Its viewers don't subscribe to updates, so they
+/// don't count as players.
+async fn compat_page(State(state): State<AppState>) -> Html<String> {
+    let tmpl = state.templates.get_template("compat").unwrap();
+    let game = state.game.read().await;
+    Html(
+        tmpl.render(context! {
+            game => *game,
+            game_id => game.id,
+            ball_color => game.ball.color(),
+            split_ball_color => game.split_ball.as_ref().map(Ball::color),
+            starts_in => starts_in(&game),
+            players => state.update_tx.receiver_count(),
+            player_names => player_names(&state),
+            demo => state.demo.load(Ordering::Relaxed),
+            bindings_revision => game.bindings_revision,
+            bots => state.bots.seats(),
+            claimed => state.config.claim_sides.then(|| state.sides.claimed()),
+            assisted => game.assisted(),
+            ping => *state.latency.lock().unwrap(),
+            branding => state.config.branding,
+            blocks => PageBlocks {
+                forkme: state.config.branding.ribbon_visible,
+                controls: false,
+                board: true,
+            },
+            poll_url => "/poll",
+            refresh => state.config.compat_refresh.map(|refresh| refresh.as_secs()),
+        })
+        .expect("compat page renders"),
+    )
+}
+
+/// A viewer without htmx for slow connections, updated from `/lite-sse`.
+async fn lite_page(State(state): State<AppState>) -> Html<String> {
+    let tmpl = state.templates.get_template("lite").unwrap();
+    let game = state.game.read().await;
+    Html(
+        tmpl.render(context! {
+            game => *game,
+            phase => game.phase(),
+            branding => state.config.branding,
+            sse_url => "/lite-sse",
+        })
+        .expect("lite page renders"),
+    )
+}
+
+/// The board as a `state` JSON event whenever it changes. Every event is a
+/// full `LiteView`, so a lagging client just misses some frames.
+async fn lite_sse(
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let connection = state
+        .connections
+        .acquire()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let game = state.game.clone();
+    let channel_metrics = state.channel_metrics.clone();
+    let updates = BroadcastStream::new(state.update_tx.subscribe()).inspect(move |item| {
+        // Released when the client disconnects and the stream is dropped.
+        let _ = &connection;
+        if let Err(BroadcastStreamRecvError::Lagged(skipped)) = item {
+            channel_metrics.record_lag(*skipped);
+        }
+    });
+    let stream = stream::once(async {}).chain(updates.map(|_| ())).then(move |()| {
+        let game = game.clone();
+        async move {
+            let view = LiteView::new(&*game.read().await);
+            Event::default().event("state").json_data(view)
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn help_page(State(state): State<AppState>) -> Html<String> {
+    let tmpl = state.templates.get_template("help").unwrap();
+    Html(
+        tmpl.render(context! {
+            help => Help::new(&*state.game.read().await, &state.config),
+            branding => state.config.branding,
+            blocks => PageBlocks {
+                forkme: state.config.branding.ribbon_visible,
+                controls: false,
+                board: false,
+            },
+        })
+        .expect("help renders"),
+    )
+}
+
+/// The summary of a finished game that's still in the history.
+async fn result_page(
+    State(state): State<AppState>,
+    extract::Path(id): extract::Path<String>,
+) -> Result<Html<String>, StatusCode> {
+    let result = state.history.game(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let tmpl = state.templates.get_template("result").unwrap();
+    Ok(Html(
+        tmpl.render(context! {
+            result => result,
+            branding => state.config.branding,
+            blocks => PageBlocks {
+                forkme: state.config.branding.ribbon_visible,
+                controls: false,
+                board: false,
+            },
+        })
+        .expect("result renders"),
+    ))
+}
+
+/// The result of a finished game as a picture, which chat apps show better
+/// than pages. Never changes, so it's drawn once into `--card-dir`.
+async fn result_card(
+    State(state): State<AppState>,
+    extract::Path(id): extract::Path<String>,
+) -> Result<([(header::HeaderName, &'static str); 2], Vec<u8>), StatusCode> {
+    let result = state.history.game(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let dir = PathBuf::from(&state.config.card_dir);
+    let title = state.config.branding.title.clone();
+    let png = tokio::task::spawn_blocking(move || card::cached(&dir, &result, &title))
+        .await
+        .expect("card drawing doesn't panic");
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        png,
+    ))
+}
+
+/// The all-time records of the server, see `records`.
+async fn records_page(State(state): State<AppState>) -> Html<String> {
+    let tmpl = state.templates.get_template("records").unwrap();
+    Html(
+        tmpl.render(context! {
+            records => state.records.lines(),
+            branding => state.config.branding,
+            blocks => PageBlocks {
+                forkme: state.config.branding.ribbon_visible,
+                controls: false,
+                board: false,
+            },
+        })
+        .expect("records render"),
+    )
+}
+
+async fn records(State(state): State<AppState>) -> Json<BTreeMap<RecordKind, Record>> {
+    Json(state.records.held())
+}
+
+async fn help(State(state): State<AppState>) -> Json<Help> {
+    Json(Help::new(&*state.game.read().await, &state.config))
+}
+
+async fn keypress(State(state): State<AppState>, Form(input): Form<KeyPress>) -> () {
+    if !in_control(&state, input.controller.as_deref()) {
+        if let Some(session) = input.debug_input {
+            let key = Input::Key { key: input.last_key };
+            echo_input(&state, &session, &key, Outcome::Ignored(Ignored::NotController), None);
+        }
+        return;
+    }
+    if let Some(page) = &input.page {
+        state.subscribers.played(page);
+    }
+    #[cfg(feature = "chaos")]
+    chaos::before_lock().await;
+    let mut g = state.game.write().await;
+    let key = rebound_key(&state, &g, input.last_key);
+    let side = Side::ALL.into_iter().find(|&side| g.team(side).has_key(&key));
+    // The computer plays the right side, so it's that side's to hand over.
+    let owner = if key == AI_KEY { Some(Side::Right) } else { side };
+    if let Err(ignored) = may_play(&state, input.page.as_deref(), owner) {
+        if let Some(session) = input.debug_input {
+            let key = Input::Key { key };
+            echo_input(&state, &session, &key, Outcome::Ignored(ignored), None);
+        }
+        return;
+    }
+    if let Some(side) = side {
+        record_latency(&state, input.sent_at, side).await;
+    }
+    let outcome = match (side, state.config.coalesce_keys) {
+        (Some(side), Some(window)) if g.is_running => {
+            coalesce_press(&state, &mut g, side, &key, window).await
+        }
+        _ => apply_input(&state, &mut g, Input::Key { key: key.clone() }).await,
+    };
+    if let Some(session) = input.debug_input {
+        let revision = Some(g.bindings_revision);
+        echo_input(&state, &session, &Input::Key { key }, outcome, revision);
+    }
+}
+
+/// Apply a press of one of `side`'s keys, unless it's merged into a later
+/// move of the same key, see `--coalesce-keys`.
+async fn coalesce_press(
+    state: &AppState,
+    g: &mut GameState,
+    side: Side,
+    key: &str,
+    window: Duration,
+) -> Outcome {
+    let press = state.key_bursts.press(side, key, Instant::now(), window);
+    if let Some(flush_at) = press.flush_at {
+        tokio::spawn(flush_presses(state.clone(), side, flush_at));
+    }
+    let mut outcome = Outcome::Merged;
+    for (key, presses) in press.moves {
+        outcome = apply_input(state, g, held_key(key, presses)).await;
+    }
+    outcome
+}
+
+/// Apply the presses of `side` merged until `at` as one move.
+async fn flush_presses(state: AppState, side: Side, at: Instant) {
+    tokio::time::sleep_until(at.into()).await;
+    let mut g = state.game.write().await;
+    if let Some((key, presses)) = state.key_bursts.flush(side, Instant::now()) {
+        apply_input(&state, &mut g, held_key(key, presses)).await;
+    }
+}
+
+fn held_key(key: String, presses: u16) -> Input {
+    if presses == 1 {
+        Input::Key { key }
+    } else {
+        Input::HeldKey { key, presses }
+    }
+}
+
+async fn click(State(state): State<AppState>, Form(input): Form<MousePosition>) -> () {
+    let (x, y) = state.config.click_coordinates.normalize(input.x, input.y);
+    if !in_control(&state, input.controller.as_deref()) {
+        if let Some(session) = input.debug_input {
+            let click = Input::Click { x, y, intercept: input.intercept };
+            echo_input(&state, &session, &click, Outcome::Ignored(Ignored::NotController), None);
+        }
+        return;
+    }
+    if let Some(page) = &input.page {
+        state.subscribers.played(page);
+    }
+    let side = if x < 0.5 { Side::Left } else { Side::Right };
+    if let Err(ignored) = may_play(&state, input.page.as_deref(), Some(side)) {
+        if let Some(session) = input.debug_input {
+            let click = Input::Click { x, y, intercept: input.intercept };
+            echo_input(&state, &session, &click, Outcome::Ignored(ignored), None);
+        }
+        return;
+    }
+    #[cfg(feature = "chaos")]
+    chaos::before_lock().await;
+    let mut g = state.game.write().await;
+    if g.is_running {
+        record_latency(&state, input.sent_at, side).await;
+    }
+    let click = Input::Click { x, y, intercept: input.intercept };
+    let outcome = apply_input(&state, &mut g, click.clone()).await;
+    if let Some(session) = input.debug_input {
+        echo_input(&state, &session, &click, outcome, Some(g.bindings_revision));
+    }
+}
+
+/// Whether inputs from the page rendered with `token` count, which claims
+/// control for it if it's free. Always true without `--single-controller`.
+fn in_control(state: &AppState, token: Option<&str>) -> bool {
+    !state.config.single_controller || token.is_some_and(|token| state.controller.claim(token))
+}
+
+/// Whether the page rendered with `token` may send an input for `side`'s
+/// bats, or for neither side's: with `--claim-sides` only if it plays a side,
+/// and for no other side's bats. Always without `--claim-sides`.
+fn may_play(state: &AppState, token: Option<&str>, side: Option<Side>) -> Result<(), Ignored> {
+    if !state.config.claim_sides {
+        return Ok(());
+    }
+    match (token.and_then(|token| state.sides.side_of(token)), side) {
+        (None, _) => Err(Ignored::Spectator),
+        (Some(played), Some(side)) if played != side => Err(Ignored::OtherSide),
+        _ => Ok(()),
+    }
+}
+
+/// Give up the side of a page, e.g. when it's closed.
+async fn release_side(
+    State(state): State<AppState>,
+    Form(form): Form<PageForm>,
+) -> Result<(), (StatusCode, &'static str)> {
+    if state.sides.release(&form.page) {
+        state.renderer.send(Renderable::Scoreboard).await.unwrap();
+        Ok(())
+    } else {
+        Err((StatusCode::CONFLICT, "the page plays no side"))
+    }
+}
+
+/// Take control for a page, answering 409 if another page holds it.
+async fn claim_control(
+    State(state): State<AppState>,
+    Form(form): Form<ControllerForm>,
+) -> Result<(), (StatusCode, &'static str)> {
+    if state.controller.claim(&form.controller) {
+        Ok(())
+    } else {
+        Err((StatusCode::CONFLICT, "another page is in control"))
+    }
+}
+
+/// Give up control, e.g. when the controlling page is closed.
+async fn release_control(
+    State(state): State<AppState>,
+    Form(form): Form<ControllerForm>,
+) -> Result<(), (StatusCode, &'static str)> {
+    if state.controller.release(&form.controller) {
+        Ok(())
+    } else {
+        Err((StatusCode::CONFLICT, "the page isn't in control"))
+    }
+}
+
+/// Tell a client diagnosing its controls what the server saw and decided,
+/// under which `bindings_revision` if the input got to the game.
+fn echo_input(
+    state: &AppState,
+    session: &str,
+    input: &Input,
+    outcome: Outcome,
+    bindings_revision: Option<u32>,
+) {
+    let html = state
+        .templates
+        .get_template("debug_input")
+        .unwrap()
+        .render(context! {
+            input => serde_json::to_string(input).expect("input serializes"),
+            outcome => serde_json::to_string(&outcome).expect("outcome serializes"),
+            bindings_revision => bindings_revision,
+        })
+        .expect("input echo renders");
+    state
+        .input_echo
+        .send(session, Event::default().event("debug_input").data(html));
+}
+
+async fn server_time() -> Json<ServerTime> {
+    Json(ServerTime { now: now_ms() })
+}
+
+async fn game_state(State(state): State<AppState>) -> Json<StateView> {
+    let game = state.game.read().await;
+    Json(StateView {
+        game: game.clone(),
+        phase: game.phase(),
+    })
+}
+
+/// JSON Schemas of `/state` and the form fields of `/keypress` and `/click`.
+async fn schema() -> Json<Schemas> {
+    Json(Schemas {
+        state: schema_for!(StateView),
+        keypress: schema_for!(KeyPress),
+        click: schema_for!(MousePosition),
+    })
+}
+
+async fn status(State(state): State<AppState>) -> Json<Status> {
+    Json(Status {
+        phase: state.game.read().await.phase(),
+    })
+}
+
+async fn stats(State(state): State<AppState>) -> Json<Stats> {
+    Json(Stats {
+        ping: *state.latency.lock().unwrap(),
+    })
+}
+
+/// The results of the last finished games for one side, oldest first, e.g.
+/// for drawing a sparkline.
+async fn history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Vec<SideResult>> {
+    Json(state.history.of(query.side))
+}
+
+/// The next of the pseudo-random numbers `state` runs through, the same for
+/// the same start, for simulations that must come out the same every time.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A hard to guess hex string, for tokens handed out to pages and bots.
+fn random_token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock is after the epoch")
+            .as_nanos(),
     );
+    format!("{:016x}", hasher.finish())
+}
+
+/// Identifies a request and its response, so that the log lines of one
+/// client's requests can be told apart from everybody else's.
+static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+/// The longest request id taken from a client.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Tag every request with the `X-Request-Id` it came with, or a new one if it
+/// had none that's short, printable ASCII, and return it in the same header.
+/// With `--debug` each request is logged along with its id.
+async fn tag_request(
+    State(debug): State<bool>,
+    request: Request,
+    next: middleware::Next,
+) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID)
+        .filter(|id| {
+            let id = id.as_bytes();
+            (1..=MAX_REQUEST_ID_LEN).contains(&id.len()) && id.iter().all(u8::is_ascii_graphic)
+        })
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&random_token()).expect("a token is ASCII"));
+    let (method, uri) = (request.method().clone(), request.uri().clone());
+    let mut response = next.run(request).await;
+    if debug {
+        eprintln!(
+            "request {}: {method} {uri} {}",
+            id.to_str().expect("the id is ASCII"),
+            response.status()
+        );
+    }
+    response.headers_mut().insert(REQUEST_ID.clone(), id);
+    response
+}
+
+/// A ULID: the time in milliseconds followed by 80 random bits, in Crockford's
+/// base 32, so that IDs minted later sort after earlier ones.
+fn new_game_id() -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    let random = |salt: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(salt);
+        hasher.finish()
+    };
+    let time = now_ms() as u128 & ((1 << 48) - 1);
+    let randomness = (u128::from(random(0)) << 16 | u128::from(random(1) >> 48)) & ((1 << 80) - 1);
+    let ulid = time << 80 | randomness;
+    (0..26)
+        .rev()
+        .map(|digit| ALPHABET[(ulid >> (digit * 5)) as usize & 31] as char)
+        .collect()
+}
+
+/// Feed the client timestamp of an input into the seat's latency estimate and
+/// re-render the scoreboard if the displayed value changed.
+async fn record_latency(
+    state: &AppState,
+    sent_at: Option<f64>,
+    side: Side,
+) {
+    let Some(sent_at) = sent_at else {
+        return;
+    };
+    let changed = state.latency.lock().unwrap().seat_mut(side).observe(now_ms() - sent_at);
+    if changed {
+        state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    }
+}
+
+async fn start(State(state): State<AppState>) {
+    #[cfg(feature = "chaos")]
+    chaos::before_lock().await;
+    let mut g = state.game.write().await;
+    apply_input(&state, &mut g, Input::Start).await;
+}
+
+/// Mark a side ready, starting the game once both are. A finished game is
+/// cleared first, so that the next one can be readied without starting it.
+async fn ready(State(state): State<AppState>, Query(query): Query<ReadyQuery>) -> Json<Outcome> {
+    reset_if_over(&state).await;
+    #[cfg(feature = "chaos")]
+    chaos::before_lock().await;
+    let mut g = state.game.write().await;
+    Json(apply_input(&state, &mut g, Input::Ready { side: query.side }).await)
+}
+
+/// Split the board into two halves with a ball each, to warm up both hands.
+async fn enter_practice(State(state): State<AppState>) {
+    let mut g = state.game.write().await;
+    apply_input(&state, &mut g, Input::Practice { enabled: true }).await;
+}
+
+async fn leave_practice(State(state): State<AppState>) {
+    let mut g = state.game.write().await;
+    apply_input(&state, &mut g, Input::Practice { enabled: false }).await;
+}
+
+/// Serve to one side `count` times and respond with how many it returned once
+/// that's done, or with a conflict once the game stops running before that.
+/// Runs without anybody watching, but only on an idle board.
+async fn drill(
+    State(state): State<AppState>,
+    Form(form): Form<DrillForm>,
+) -> Result<Json<DrillResult>, (StatusCode, &'static str)> {
+    if form.count == 0 {
+        return Err((StatusCode::BAD_REQUEST, "count must be at least 1"));
+    }
+    {
+        let mut g = state.game.write().await;
+        if !matches!(g.phase(), Phase::Waiting | Phase::Paused) || g.split_ball.is_some() {
+            return Err((StatusCode::CONFLICT, "drills need a paused or new game"));
+        }
+        let input = Input::Drill {
+            side: form.side,
+            count: form.count,
+        };
+        apply_input(&state, &mut g, input).await;
+    }
+    loop {
+        sleep(Duration::from_millis(100)).await;
+        let g = state.game.read().await;
+        match &g.drill {
+            Some(drill) if drill.is_done() => {
+                return Ok(Json(DrillResult {
+                    hits: drill.hits,
+                    misses: drill.misses,
+                    hit_rate: f64::from(drill.hits) / f64::from(drill.count),
+                }))
+            }
+            Some(_) if g.phase() == Phase::Running => {}
+            Some(_) => return Err((StatusCode::CONFLICT, "the drill was interrupted by a pause")),
+            None => return Err((StatusCode::CONFLICT, "the drill was interrupted by a reset")),
+        }
+    }
+}
+
+/// Choose which way the ball is served after points, from the next one on.
+async fn serve_mode(State(state): State<AppState>, Form(form): Form<ServeModeForm>) {
+    let mut g = state.game.write().await;
+    apply_input(&state, &mut g, Input::ServeMode { mode: form.mode }).await;
+}
+
+/// How long presses of a side's old keys still count after it was rebound,
+/// from pages that didn't redraw their controls yet.
+const REBIND_GRACE: Duration = Duration::from_secs(2);
+
+/// The up and down keys of a side's bats before it was rebound.
+struct Rebound {
+    side: Side,
+    keys: Vec<(String, String)>,
+    until: Instant,
+}
+
+/// Bind another key set to a side's bats, unless the other side uses any of
+/// its keys.
+async fn rebind_keys(
+    State(state): State<AppState>,
+    Form(form): Form<KeysForm>,
+) -> Result<(), (StatusCode, &'static str)> {
+    let mut g = state.game.write().await;
+    let mut keys = g.keys;
+    keys.set(form.side, form.set);
+    if keys.overlap(g.left.bats.len()) {
+        return Err((StatusCode::CONFLICT, "the other side uses some of these keys"));
+    }
+    let old_keys = g.team(form.side).bats.iter();
+    *state.rebound.lock().unwrap() = Some(Rebound {
+        side: form.side,
+        keys: old_keys.map(|bat| (bat.up_key.clone(), bat.down_key.clone())).collect(),
+        until: clock::now() + REBIND_GRACE,
+    });
+    let input = Input::Keys {
+        side: form.side,
+        set: form.set,
+    };
+    apply_input(&state, &mut g, input).await;
+    Ok(())
+}
+
+/// `key` as bound now, if it's none of the current keys but one a side was
+/// just rebound from, so that a rebinding doesn't lose presses.
+fn rebound_key(state: &AppState, game: &GameState, key: String) -> String {
+    if Side::ALL.into_iter().any(|side| game.team(side).has_key(&key)) {
+        return key;
+    }
+    let rebound = state.rebound.lock().unwrap();
+    let Some(rebound) = rebound.as_ref().filter(|rebound| clock::now() < rebound.until) else {
+        return key;
+    };
+    let bats = &game.team(rebound.side).bats;
+    for ((up_key, down_key), bat) in rebound.keys.iter().zip(bats) {
+        if *up_key == key {
+            return bat.up_key.clone();
+        }
+        if *down_key == key {
+            return bat.down_key.clone();
+        }
+    }
+    key
+}
+
+async fn paddle_height(State(state): State<AppState>, Form(form): Form<BatHeightForm>) {
+    let mut g = state.game.write().await;
+    let input = Input::BatHeight {
+        side: form.side,
+        height: form.height,
+    };
+    apply_input(&state, &mut g, input).await;
+}
+
+async fn modes() -> Json<&'static [Mode]> {
+    Json(MODES)
+}
+
+/// Switch to the rules of a mode, which starts a new game.
+async fn switch_mode(
+    State(state): State<AppState>,
+    Form(form): Form<ModeForm>,
+) -> Result<(), (StatusCode, String)> {
+    let mode = Mode::find(&form.name).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    let mut game = state.game.write().await;
+    game.rules = mode.rules(&state.config.rules);
+    game.reset();
+    drop(game);
+    render_all(&state).await;
+    Ok(())
+}
+
+async fn assist(
+    State(state): State<AppState>,
+    Form(setting): Form<AssistSetting>,
+) -> Result<(), (StatusCode, &'static str)> {
+    if setting.level > MAX_ASSIST {
+        return Err((StatusCode::BAD_REQUEST, "assist level must be between 0 and 3"));
+    }
+    let mut g = state.game.write().await;
+    if g.rules.competitive {
+        return Err((StatusCode::FORBIDDEN, "assists are disabled in competitive games"));
+    }
+    let input = Input::Assist {
+        side: setting.side,
+        level: setting.level,
+    };
+    apply_input(&state, &mut g, input).await;
+    Ok(())
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Check the `Authorization: Bearer <token>` header against `--admin-token`.
+fn authorize_admin(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, &'static str)> {
+    let expected = state.config.admin_token.as_deref();
+    match (expected, bearer_token(headers)) {
+        (Some(expected), Some(given)) if expected == given => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "admin token required")),
+    }
+}
+
+const BOT_TOKEN_REQUIRED: (StatusCode, &str) = (StatusCode::UNAUTHORIZED, "bot token required");
+
+/// Hand a free bat to a bot, alternating sides so two bots play each other.
+async fn register_bot(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<BotRegistered>, (StatusCode, &'static str)> {
+    if state.config.bot_registration == BotRegistration::Admin {
+        authorize_admin(&state, &headers)?;
+    }
+    let paddles = state.game.read().await.left.bats.len();
+    // A side a page plays with `--claim-sides` has no seats for bots.
+    let claimed = if state.config.claim_sides {
+        state.sides.claimed()
+    } else {
+        Vec::new()
+    };
+    let seats = (1..=paddles)
+        .flat_map(|bat| [Side::Left, Side::Right].map(|side| Seat { side, bat }))
+        .filter(|seat| !claimed.contains(&seat.side));
+    let (token, seat) = state
+        .bots
+        .register(seats)
+        .ok_or((StatusCode::CONFLICT, "no free seat"))?;
+    state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    Ok(Json(BotRegistered { token, seat }))
+}
+
+async fn release_bot(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<(), (StatusCode, &'static str)> {
+    if !state.bots.release(bearer_token(&headers).unwrap_or_default()) {
+        return Err(BOT_TOKEN_REQUIRED);
+    }
+    state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    Ok(())
+}
+
+/// The board as seen by a bot, delayed to hand out at most one per tick.
+async fn observe_bot(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<BotView>, (StatusCode, &'static str)> {
+    let token = bearer_token(&headers).unwrap_or_default();
+    let seat = state.bots.seat(token).ok_or(BOT_TOKEN_REQUIRED)?;
+    let wait = state.bots.observe(token).ok_or(BOT_TOKEN_REQUIRED)?;
+    sleep(wait).await;
+    let game = state.game.read().await;
+    Ok(Json(BotView {
+        seat,
+        tick: game.tick,
+        ball_velocity: game.ball.velocity,
+        board: LiteView::new(&game),
+    }))
+}
+
+/// Move the bot's bat like its key would, once per tick at most.
+async fn act_bot(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(bot_move): Form<BotMove>,
+) -> Result<Json<Outcome>, (StatusCode, &'static str)> {
+    let token = bearer_token(&headers).unwrap_or_default();
+    let seat = state.bots.seat(token).ok_or(BOT_TOKEN_REQUIRED)?;
+    #[cfg(feature = "chaos")]
+    chaos::before_lock().await;
+    let mut g = state.game.write().await;
+    if !state.bots.claim_move(token, g.tick).ok_or(BOT_TOKEN_REQUIRED)? {
+        return Ok(Json(Outcome::Ignored(Ignored::AlreadyMoved)));
+    }
+    let bat = &g.team(seat.side).bats[seat.bat - 1];
+    let key = match bot_move.direction {
+        Direction::Up => bat.up_key.clone(),
+        Direction::Down => bat.down_key.clone(),
+    };
+    Ok(Json(apply_input(&state, &mut g, Input::Key { key }).await))
+}
+
+/// Show a message to everyone connected, and to whoever connects while it's up.
+async fn announce(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<AnnounceForm>,
+) -> Result<(), (StatusCode, &'static str)> {
+    authorize_admin(&state, &headers)?;
+    show_banner(&state, &form.message, form.dismiss_after);
+    Ok(())
+}
+
+/// Clear a finished game of the flag that a side's inputs looked scripted,
+/// e.g. after its players showed they weren't.
+async fn clear_assisted(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<GameIdForm>,
+) -> Result<(), (StatusCode, &'static str)> {
+    authorize_admin(&state, &headers)?;
+    if state.history.clear_assisted(&form.game_id) {
+        Ok(())
+    } else {
+        Err((StatusCode::NOT_FOUND, "no such game in the history"))
+    }
+}
+
+/// Show `message` on all pages, including those connecting while it's up.
+fn show_banner(state: &AppState, message: &str, dismiss_after: Option<u32>) {
+    const SHOWN_TO_NEW_CLIENTS: Duration = Duration::from_secs(60);
+    let html = state
+        .templates
+        .get_template("banner")
+        .unwrap()
+        .render(context! { message, dismiss_after })
+        .expect("banner renders");
+    let shown_for = dismiss_after
+        .map_or(SHOWN_TO_NEW_CLIENTS, |seconds| Duration::from_secs(seconds.into()));
+    *state.announcement.lock().unwrap() = Some(Announcement {
+        html: html.clone(),
+        expires_at: clock::now() + shown_for,
+    });
+    broadcast(state, "announcement", html);
+}
+
+/// Lock the game until the time given in the form, then start it. Replaces an
+/// earlier schedule; a time in the past starts the game right away.
+async fn schedule_start(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<ScheduleForm>,
+) -> Result<&'static str, (StatusCode, &'static str)> {
+    authorize_admin(&state, &headers)?;
+    let at = DateTime::parse_from_rfc3339(&form.at)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "at must be an RFC 3339 timestamp"))?;
+    let at = clock::from_system_ms(u64::try_from(at.timestamp_millis()).unwrap_or(0));
+    let in_past = at <= now_ms() as u64;
+    let mut g = state.game.write().await;
+    if let Some(previous) = state.schedule.lock().unwrap().take() {
+        previous.abort();
+    }
+    apply_input(&state, &mut g, Input::Schedule { at: Some(at) }).await;
+    let countdown = tokio::spawn(count_down_to_start(state.clone(), at));
+    *state.schedule.lock().unwrap() = Some(countdown.abort_handle());
+    if in_past {
+        eprintln!("scheduled start {} is in the past, starting now", form.at);
+        Ok("the scheduled time has passed, starting now\n")
+    } else {
+        Ok("")
+    }
+}
+
+async fn cancel_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<(), (StatusCode, &'static str)> {
+    authorize_admin(&state, &headers)?;
+    let mut g = state.game.write().await;
+    if let Some(countdown) = state.schedule.lock().unwrap().take() {
+        countdown.abort();
+    }
+    apply_input(&state, &mut g, Input::Schedule { at: None }).await;
+    Ok(())
+}
+
+/// Re-render the scoreboard's countdown every second until `at`, then start.
+async fn count_down_to_start(state: AppState, at: u64) {
+    loop {
+        let remaining = at.saturating_sub(now_ms() as u64);
+        if remaining == 0 {
+            break;
+        }
+        render_scoreboard(&state).await;
+        // Wake up when the displayed number of seconds changes.
+        clock::sleep(Duration::from_millis((remaining - 1) % 1000 + 1)).await;
+    }
+    let mut g = state.game.write().await;
+    apply_input(&state, &mut g, Input::Schedule { at: None }).await;
+    apply_input(&state, &mut g, Input::Start).await;
+}
+
+/// Time left until the scheduled start as `h:mm:ss` or `m:ss`, rounded up.
+fn starts_in(game: &GameState) -> Option<String> {
+    let remaining = game.scheduled_start?.saturating_sub(now_ms() as u64);
+    let seconds = remaining.div_ceil(1000);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    Some(if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    })
+}
+
+/// Apply an input to the game, waking up the game loop if it started the game.
+async fn apply_input(state: &AppState, g: &mut GameState, input: Input) -> Outcome {
+    let was_running = g.is_running;
+    let (outcome, renderables) = g.apply_input(input, state.config.start_mode);
+    state.hooks.lock().unwrap().observe(g);
+    for renderable in renderables {
+        state.renderer.send(renderable).await.unwrap();
+    }
+    let played = matches!(outcome, Outcome::MovedBat(_)) || (g.is_running && !was_running);
+    // Somebody sat down, so the demo is over.
+    if played && state.demo.swap(false, Ordering::Relaxed) {
+        state.history.forget_demo();
+        state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    }
+    if g.is_running && !was_running {
+        state.wake_up.notify_one();
+    }
+    outcome
+}
+
+async fn sse_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SseQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, BroadcastStreamRecvError>>>, StatusCode> {
+    let connection = state
+        .connections
+        .acquire()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    let channel_metrics = state.channel_metrics.clone();
+    let announcement = state
+        .announcement
+        .lock()
+        .unwrap()
+        .as_ref()
+        .filter(|announcement| announcement.expires_at > clock::now())
+        .map(|announcement| {
+            Ok(Event::default()
+                .event("announcement")
+                .data(&announcement.html))
+        });
+    let updates = state
+        .subscribers
+        .subscribe(state.update_tx.subscribe(), query.name.as_deref(), query.page);
+    let input_echo = query
+        .debug_input
+        .and_then(|session| state.input_echo.subscribe(&session));
+    let (input_echo, echo_guard) = input_echo.unzip();
+    let input_echo = input_echo.map(|echo| ReceiverStream::new(echo).map(Ok));
+    let updates = stream::select(updates, stream::iter(input_echo).flatten());
+    let stream = stream::iter(announcement).chain(updates).inspect(move |item| {
+        // Released when the client disconnects and the stream is dropped.
+        let _ = (&connection, &echo_guard);
+        if let Err(BroadcastStreamRecvError::Lagged(skipped)) = item {
+            channel_metrics.record_lag(*skipped);
+        }
+    });
+    state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// The `x` at which the bats of `side` return the ball.
+fn bat_line(side: Side) -> u16 {
+    match side {
+        Side::Left => BAT_STRIP,
+        Side::Right => FIELD_SIZE - BAT_STRIP,
+    }
+}
+
+/// The `x` at which the center wall of split-screen practice bounces the
+/// ball of `side`'s half back.
+fn center_line(side: Side) -> u16 {
+    match side {
+        Side::Left => 490,
+        Side::Right => 500,
+    }
+}
+
+/// A moving bat passes a fifth of its velocity on to the ball it hits.
+fn spin(ball_velocity: i16, bat_velocity: i16) -> i16 {
+    (ball_velocity + bat_velocity / 5).clamp(-MAX_VERTICAL_SPEED, MAX_VERTICAL_SPEED)
+}
+
+/// The vertical velocity of `ball` after `bat` returned it.
+fn rebound(ball: &Ball, bat: &Bat, shape: BatShape) -> i16 {
+    let deflection = bat.tip_deflection(ball.position.1, shape);
+    spin(ball.velocity.1 + deflection, bat.velocity)
+}
+
+/// Fallback for clients that can't keep an SSE connection open: the events
+/// since the given ID, waiting up to `wait` seconds for new ones.
+async fn poll(State(state): State<AppState>, Query(query): Query<PollQuery>) -> Json<PollResponse> {
+    const MAX_WAIT: Duration = Duration::from_secs(25);
+    // Subscribe before looking at the log so no event slips through in between.
+    let mut update_rx = state.update_tx.subscribe();
+    let mut events = state.events.lock().unwrap().since(query.since);
+    if matches!(&events, Some(events) if events.is_empty()) && query.wait > 0 {
+        let wait = Duration::from_secs(query.wait).min(MAX_WAIT);
+        let _ = timeout(wait, update_rx.recv()).await;
+        events = state.events.lock().unwrap().since(query.since);
+    }
+    let (keyframe, events) = match events {
+        Some(events) => (false, events),
+        None => (true, keyframe(&state).await),
+    };
+    Json(PollResponse {
+        game_id: state.game.read().await.id.clone(),
+        last_id: events.last().map_or(query.since, |event| event.id),
+        keyframe,
+        events,
+    })
+}
+
+/// Where `ball` will cross `x`, going on as it does and bouncing off the top
+/// and bottom walls on the way. `None` if it doesn't move towards `x`.
+fn intercept_y(ball: &Ball, x: u16) -> Option<u16> {
+    let (vx, vy) = (i64::from(ball.velocity.0), i64::from(ball.velocity.1));
+    let distance = i64::from(x) - i64::from(ball.position.0);
+    if vx == 0 || distance * vx < 0 {
+        return None;
+    }
+    // Unfold the bounces into a straight line and fold it back onto the board.
+    let bottom = i64::from(LOWEST_BALL);
+    let unfolded = i64::from(ball.position.1) + vy * distance / vx;
+    let folded = unfolded.rem_euclid(2 * bottom);
+    Some(if folded > bottom { 2 * bottom - folded } else { folded } as u16)
+}
+
+/// Move by `offset`, returning `false` if another move this tick takes precedence.
+fn move_bat(b: &mut Bat, offset: u16, direction: Direction, resolution: MoveResolution) -> bool {
+    if !b.prepare_move(resolution) {
+        return false;
+    }
+    match direction {
+        Direction::Up => {
+            b.move_to(b.position.saturating_sub(offset));
+        },
+        Direction::Down => {
+            b.move_to(cmp::min(1000 - b.height, b.position + offset));
+        }
+    }
+    true
+}
+
+async fn metrics(State(state): State<AppState>) -> Json<Metrics> {
+    Json(Metrics {
+        channel: state.channel_metrics.snapshot(),
+        sse_connections: state.connections.open(),
+        watchdog: state.watchdog.snapshot(),
+        timing: state.step_timing.snapshot(),
+        renderers: state.renderers.stats(),
+    })
+}
+
+/// How fast the game loop steps and broadcasts the ball, and how often it
+/// couldn't keep up.
+async fn timing(State(state): State<AppState>) -> Json<Timing> {
+    Json(Timing {
+        physics_hz: state.config.physics_hz,
+        configured_broadcast_hz: state.config.broadcast_hz,
+        stats: state.step_timing.snapshot(),
+    })
+}
+
+/// What's queued for every open `/game-sse` connection and how much of it
+/// had to be dropped.
+async fn connections(State(state): State<AppState>) -> Json<Vec<SubscriberStats>> {
+    Json(state.subscribers.snapshot())
+}
+
+/// The collision boundaries of the current game, to draw over the board.
+async fn bounds(State(state): State<AppState>) -> Json<Bounds> {
+    Json(Bounds::new(&*state.game.read().await))
+}
+
+/// Run a fresh game headless for up to `ticks` ticks (stopping early once the
+/// ball is lost) and hash the state after every tick, so that two builds can
+/// be checked for identical physics.
+async fn physics_hash(
+    Query(query): Query<PhysicsHashQuery>,
+) -> Result<Json<PhysicsHash>, (StatusCode, &'static str)> {
+    if query.seed.is_some() {
+        let reason = "nothing in the physics is random to seed, every run is the same";
+        return Err((StatusCode::BAD_REQUEST, reason));
+    }
+    tokio::task::spawn_blocking(move || hash_physics(query.ticks))
+        .await
+        .map(Json)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "hashing the physics failed"))
+}
+
+/// `physics_hash` for up to `max_ticks`. Blocks, so run it with `spawn_blocking`.
+fn hash_physics(max_ticks: u32) -> PhysicsHash {
+    const MAX_TICKS: u32 = 1_000_000;
+    let mut game = GameState {
+        is_running: true,
+        ..GameState::default()
+    };
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut ticks = 0;
+    while ticks < max_ticks.min(MAX_TICKS) && !game.is_lost {
+        game.step();
+        hash = fnv1a(hash, &serde_json::to_vec(&game).expect("game serializes"));
+        ticks += 1;
+    }
+    PhysicsHash {
+        ticks,
+        hash: format!("{hash:016x}"),
+    }
+}
+
+/// Replace the game with a fresh one and step it as fast as possible until the
+/// first point is decided, rendering every tick like the game loop does, to
+/// load test the render and broadcast path with a deterministic sequence.
+async fn simulate_point(State(state): State<AppState>) -> Json<SimulatedPoint> {
+    const MAX_TICKS: u32 = 100_000;
+    let point_decided = |game: &GameState| {
+        let classic_point = game.rules.scoring == Scoring::Classic
+            && game.left.score + game.right.score > 0;
+        game.is_lost || classic_point
+    };
+    let mut game = state.game.write().await;
+    let keys = game.keys;
+    *game = GameState::new(state.config.paddles, game.rules);
+    game.bind_at_start(keys);
+    drop(game);
+    render_all(&state).await;
+    let mut events = Renderable::all().len();
+    let mut ticks = 0;
+    while ticks < MAX_TICKS && !point_decided(&*state.game.read().await) {
+        let mut renderables = state.game.write().await.step();
+        renderables.push(Renderable::Ball);
+        events += renderables.len();
+        for renderable in renderables {
+            state.renderer.send(renderable).await.unwrap();
+        }
+        ticks += 1;
+    }
+    Json(SimulatedPoint { ticks, events })
+}
+
+/// Check that every template renders, like at startup.
+async fn template_report(State(state): State<AppState>) -> Json<TemplateReport> {
+    let (passed, failed) = check_templates(&state.templates, &state.config);
+    Json(TemplateReport {
+        passed: passed.into_iter().map(str::to_string).collect(),
+        failed: failed
+            .into_iter()
+            .map(|(name, err)| (name.to_string(), err.to_string()))
+            .collect(),
+    })
+}
+
+#[cfg(feature = "test-hooks")]
+#[derive(Deserialize)]
+struct AdvanceForm {
+    ms: u32,
+}
+
+/// Move the clock of countdowns and timeouts ahead by `ms` milliseconds,
+/// returning the time it shows now.
+#[cfg(feature = "test-hooks")]
+async fn advance_time(Form(form): Form<AdvanceForm>) -> Json<ServerTime> {
+    clock::advance(Duration::from_millis(form.ms.into()));
+    Json(ServerTime { now: now_ms() })
+}
+
+/// Replace the game with the one posted, e.g. as returned by `/state`, and
+/// draw it on every board.
+#[cfg(feature = "test-hooks")]
+async fn set_state(State(state): State<AppState>, Json(game): Json<GameState>) {
+    let is_running = game.is_running;
+    *state.game.write().await = game;
+    render_all(&state).await;
+    if is_running {
+        state.wake_up.notify_one();
+    }
+}
+
+async fn input_log(State(state): State<AppState>) -> Json<Recording> {
+    Json(Recording::new(&*state.game.read().await, state.config.start_mode))
+}
+
+/// The current game as a `.rpong` replay to share, see `replay::encode`.
+async fn download_replay(
+    State(state): State<AppState>,
+) -> Result<([(header::HeaderName, String); 2], String), (StatusCode, String)> {
+    let (recording, id) = {
+        let game = state.game.read().await;
+        (Recording::new(&game, state.config.start_mode), game.id.clone())
+    };
+    let replay = tokio::task::spawn_blocking(move || Replay::record(&recording))
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|replay| replay)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err))?;
+    let headers = [
+        (header::CONTENT_TYPE, "text/plain; charset=utf-8".to_string()),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{id}.rpong\"")),
+    ];
+    Ok((headers, replay::encode(&replay)))
+}
+
+/// Play a `.rpong` replay on the board while the game waits to be started,
+/// until the replay ends or somebody plays.
+async fn load_replay(
+    State(state): State<AppState>,
+    body: String,
+) -> Result<(), (StatusCode, String)> {
+    let replay = replay::decode(&body).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+    if replay.paddles != state.config.paddles {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "the replay has {} paddles per side, this board {}",
+                replay.paddles, state.config.paddles
+            ),
+        ));
+    }
+    if state.game.read().await.phase() != Phase::Waiting {
+        return Err((StatusCode::CONFLICT, "a game is on".to_string()));
+    }
+    tokio::spawn(exhibition::show_replay(state, replay));
+    Ok(())
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64-bit FNV-1a, stable across Rust releases unlike `DefaultHasher`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Take the players who left off the scoreboard's list.
+async fn redraw_on_leave(state: AppState) {
+    loop {
+        state.subscribers.left().await;
+        state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    }
+}
+
+/// Warn when the system clock jumps, which the game's clock doesn't follow.
+async fn watch_wall_clock() {
+    const INTERVAL: Duration = Duration::from_secs(5);
+    let mut last = clock::skew_ms();
+    loop {
+        sleep(INTERVAL).await;
+        let skew = clock::skew_ms();
+        if (skew - last).abs() >= clock::JUMP_MS {
+            eprintln!(
+                "warning: the system clock jumped by {:+.1}s and is {:+.1}s off the game's \
+                 clock now; schedules, countdowns and latencies keep to the game's clock",
+                (skew - last) as f64 / 1000.,
+                skew as f64 / 1000.,
+            );
+        }
+        last = skew;
+    }
+}
+
+/// Periodically warn when subscribers keep lagging behind the broadcast channel.
+async fn watch_channel_lag(state: AppState) {
+    const WINDOW: Duration = Duration::from_secs(10);
+    const LAG_THRESHOLD: u64 = 3;
+    let mut last = state.channel_metrics.snapshot();
+    loop {
+        sleep(WINDOW).await;
+        let current = state.channel_metrics.snapshot();
+        let lag_events = current.lag_events - last.lag_events;
+        if lag_events >= LAG_THRESHOLD {
+            eprintln!(
+                "warning: SSE subscribers lagged {lag_events} times in the last {}s \
+                 (queue high-water {} of {}), consider --channel-capacity {}",
+                WINDOW.as_secs(),
+                current.queue_high_water,
+                state.config.channel_capacity,
+                state.config.channel_capacity * 2,
+            );
+        }
+        last = current;
+    }
+}
+
+/// Broadcast the ball less often while the game loop's steps run late or
+/// subscribers lag, and as often as configured again once they don't.
+async fn adapt_broadcast_rate(state: AppState) {
+    let mut controller = LoadController::new(state.config.broadcast_hz);
+    let mut last_timing = state.step_timing.snapshot();
+    let mut last_channel = state.channel_metrics.snapshot();
+    loop {
+        sleep(load::SAMPLE_INTERVAL).await;
+        let timing = state.step_timing.snapshot();
+        let channel = state.channel_metrics.snapshot();
+        let before = controller.rate();
+        let rate = controller.sample(LoadSample {
+            steps: timing.steps - last_timing.steps,
+            late_steps: timing.late_steps - last_timing.late_steps,
+            lag_events: channel.lag_events - last_channel.lag_events,
+        });
+        if rate != before {
+            let reason = if rate < before { "can't keep up" } else { "keeps up again" };
+            eprintln!("broadcasting the ball at {rate} Hz, the server {reason}");
+            state.step_timing.set_broadcast_hz(rate);
+        }
+        (last_timing, last_channel) = (timing, channel);
+    }
+}
+
+/// Lower the quality of the spectators' updates while the room sends more
+/// than `--bandwidth-budget` bytes per second, and raise it once it's well
+/// below again.
+async fn keep_to_bandwidth_budget(state: AppState, budget: u64) {
+    let mut controller = BudgetController::new(budget);
+    let mut last = state.subscribers.sent();
+    loop {
+        sleep(load::SAMPLE_INTERVAL).await;
+        let sent = state.subscribers.sent();
+        // Also run without a shift, for pages that started playing.
+        state.subscribers.shift_quality(controller.sample(sent - last));
+        last = sent;
+    }
+}
+
+/// Run the game loop, nudging it when the game should be stepping but isn't,
+/// and restarting it with a reset game if that doesn't help either. Aborting
+/// this aborts the game loop too, as when a room is closed.
+async fn watch_game_loop(state: AppState) {
+    let mut game_loop_task = JoinSet::new();
+    game_loop_task.spawn(game_loop(state.clone()));
+    let stall_after = state.config.step_interval() * watchdog::MISSED_STEPS;
+    let is_stalled = || async {
+        // A game lock held for this long counts as a stall too.
+        let Ok(game) = timeout(stall_after, state.game.read()).await else {
+            return true;
+        };
+        should_step(&state, &game) && state.watchdog.since_beat() > stall_after
+    };
+    loop {
+        sleep(watchdog::CHECK_INTERVAL).await;
+        if !is_stalled().await {
+            continue;
+        }
+        state.watchdog.record_stall();
+        let flags = match state.game.try_read() {
+            Ok(game) => format!(
+                "phase {:?}, tick {}, started {}, running {}, lost {}",
+                game.phase(),
+                game.tick,
+                game.is_started,
+                game.is_running,
+                game.is_lost
+            ),
+            Err(_) => "game locked".to_string(),
+        };
+        eprintln!(
+            "warning: game loop stalled for {:?} ({flags}, {} renders queued, \
+             {} events queued, {} subscribers), waking it up",
+            state.watchdog.since_beat(),
+            state.renderer.max_capacity() - state.renderer.capacity(),
+            state.update_tx.len(),
+            state.update_tx.receiver_count(),
+        );
+        state.wake_up.notify_one();
+        sleep(stall_after).await;
+        if !is_stalled().await {
+            continue;
+        }
+        eprintln!("error: game loop still stalled, restarting it with a reset game");
+        // Aborting first frees the game lock if the loop is what holds it.
+        game_loop_task.abort_all();
+        let locked = timeout(stall_after, state.game.write()).await;
+        while game_loop_task.try_join_next().is_some() {}
+        game_loop_task.spawn(game_loop(state.clone()));
+        let Ok(mut game) = locked else {
+            eprintln!("error: the game stays locked, can't reset it");
+            continue;
+        };
+        game.reset();
+        drop(game);
+        state.watchdog.record_reset();
+        render_all(&state).await;
+        show_banner(&state, "Sorry, the game got stuck and had to be reset.", Some(10));
+    }
+}
+
+/// Fragments that replace what clients show, so sending one again unchanged
+/// does nothing but cost bandwidth.
+const IDEMPOTENT_EVENTS: [&str; 4] = ["ball", "bat_left", "bat_right", "scoreboard"];
+
+fn broadcast(state: &AppState, event: &'static str, data: String) {
+    #[cfg(feature = "chaos")]
+    let dropped = chaos::dropped(chaos::Point::Broadcast);
+    let mut events = state.events.lock().unwrap();
+    if IDEMPOTENT_EVENTS.contains(&event) && events.last_data(event) == Some(data.as_str()) {
+        return;
+    }
+    let frame = Event::default()
+        .id(events.push_id().to_string())
+        .event(event)
+        .data(&data);
+    let size = event.len() + data.len();
+    events.push(event, data);
+    // Lost on the way to the subscribers, `/poll` still has it.
+    #[cfg(feature = "chaos")]
+    if dropped {
+        return;
+    }
+    let _ = state.update_tx.send(Update { event, frame, size });
+    state.channel_metrics.record_queue_len(state.update_tx.len());
+}
+
+async fn render_bat(state: &AppState, side: Side) {
+    let game = state.game.read().await;
+    broadcast(state, side.bat_event(), bat_fragment(state, &game, side));
+}
+
+fn bat_fragment(state: &AppState, game: &GameState, side: Side) -> String {
+    let tmpl = state.templates.get_template("bat").unwrap();
+    tmpl.render(context! { game => game, side }).expect("bat renders")
 }
 
 async fn render_scoreboard(state: &AppState) {
+    let game = state.game.read().await;
+    broadcast(state, "scoreboard", scoreboard_fragment(state, &game));
+}
+
+/// The balls, rendered from just the fields the template needs rather than
+/// the whole game, as they change every tick.
+fn ball_fragment(state: &AppState, game: &GameState) -> String {
+    let tmpl = state.templates.get_template("ball").unwrap();
+    tmpl.render(context! {
+        game => context! { ball => game.ball, split_ball => game.split_ball },
+        ball_color => game.ball.color(),
+        split_ball_color => game.split_ball.as_ref().map(Ball::color),
+    })
+    .expect("ball renders")
+}
+
+/// Who's watching, for the scoreboard to list with `--player-names`.
+fn player_names(state: &AppState) -> Option<Names> {
+    state.config.player_names.then(|| state.subscribers.names())
+}
+
+fn scoreboard_fragment(state: &AppState, game: &GameState) -> String {
     let tmpl = state.templates.get_template("scoreboard").unwrap();
-    let _ = state.update_tx.send(
-        Event::default().event("scoreboard").data(
-            tmpl.render(context! {
-                game => *state.game.read().await,
-                players => state.update_tx.receiver_count(),
-            })
-            .expect("scoreboard renders"),
-        ),
-    );
+    tmpl.render(context! {
+        game => game,
+        game_id => game.id,
+        starts_in => starts_in(game),
+        players => state.update_tx.receiver_count(),
+        player_names => player_names(state),
+        demo => state.demo.load(Ordering::Relaxed),
+        bindings_revision => game.bindings_revision,
+        bots => state.bots.seats(),
+        claimed => state.config.claim_sides.then(|| state.sides.claimed()),
+        assisted => game.assisted(),
+        ping => *state.latency.lock().unwrap(),
+    })
+    .expect("scoreboard renders")
 }
 
-async fn update_ball_position(state: &AppState) {
-    let mut g = state.game.write().await;
-    g.ball.position = (
-        ((g.ball.velocity.0 as i32) + (g.ball.position.0 as i32)) as u16,
-        ((g.ball.velocity.1 as i32) + (g.ball.position.1 as i32)) as u16,
+/// The current board as events, for clients that missed too much to catch up.
+async fn keyframe(state: &AppState) -> Vec<LoggedEvent> {
+    let game = state.game.read().await;
+    let last_id = state.events.lock().unwrap().last_id();
+    let mut events = vec![LoggedEvent {
+        id: last_id,
+        event: "scoreboard",
+        data: scoreboard_fragment(state, &game),
+    }];
+    let tmpl = state.templates.get_template("bat").unwrap();
+    for side in Side::ALL {
+        events.push(LoggedEvent {
+            id: last_id,
+            event: side.bat_event(),
+            data: tmpl
+                .render(context! { game => *game, side })
+                .expect("keyframe renders"),
+        });
+    }
+    events.push(LoggedEvent {
+        id: last_id,
+        event: "ball",
+        data: ball_fragment(state, &game),
+    });
+    events
+}
+
+async fn render_match_point(state: &AppState, match_point: MatchPointState) {
+    let tmpl = state.templates.get_template("match_point").unwrap();
+    let game = state.game.read().await;
+    broadcast(
+        state,
+        "match_point",
+        tmpl.render(context! {
+            game => *game,
+            game_id => game.id,
+            match_point => match_point,
+        })
+        .expect("match point renders"),
     );
-    if g.ball.position.0 <= 10 {
-        if g.ball.position.1 > g.left.position
-            && g.ball.position.1 < g.left.position + g.left.height
-        {
-            g.ball.position = (10, g.ball.position.1);
-            g.ball.velocity = (g.ball.velocity.0 * -1, g.ball.velocity.1);
-            g.left.score_up();
-            state.renderer.send(Renderable::BatLeft).await.unwrap();
-            state.renderer.send(Renderable::Scoreboard).await.unwrap();
-        } else {
-            g.is_lost = true;
-            render_all(state).await;
-        }
-    } else if g.ball.position.0 >= 990 {
-        if g.ball.position.1 > g.right.position
-            && g.ball.position.1 < g.right.position + g.right.height
-        {
-            g.ball.position = (990, g.ball.position.1);
-            g.ball.velocity = (g.ball.velocity.0 * -1, g.ball.velocity.1);
-            g.right.score_up();
-            state.renderer.send(Renderable::BatRight).await.unwrap();
-            state.renderer.send(Renderable::Scoreboard).await.unwrap();
-        } else {
-            g.is_lost = true;
-            render_all(state).await;
+}
+
+/// Step the physics once, sending the ball along with everything else that
+/// changed if `broadcast` is set.
+async fn update_ball_position(state: &AppState, broadcast: bool) {
+    let (renderables, crash_dump) = {
+        let mut game = state.game.write().await;
+        let mut renderables = game.step();
+        if broadcast {
+            renderables.push(Renderable::Ball);
         }
+        state.hooks.lock().unwrap().observe(&game);
+        (renderables, check_invariants(state, &game))
+    };
+    if let Some(crash_dump) = crash_dump {
+        tokio::spawn(report_violation(state.clone(), crash_dump));
     }
-    if g.ball.position.1 <= 0 {
-        g.ball.position = (g.ball.position.0, 0);
-        g.ball.velocity = (g.ball.velocity.0, g.ball.velocity.1 * -1);
-        state.renderer.send(Renderable::Scoreboard).await.unwrap();
-    } else if g.ball.position.1 >= 990 {
-        g.ball.position = (g.ball.position.0, 990);
-        g.ball.velocity = (g.ball.velocity.0, g.ball.velocity.1 * -1);
-        state.renderer.send(Renderable::Scoreboard).await.unwrap();
+    for renderable in renderables {
+        state.renderer.send(renderable).await.unwrap();
+    }
+}
+
+/// Snapshot the game on the first tick it breaks an invariant.
+fn check_invariants(state: &AppState, game: &GameState) -> Option<CrashDump> {
+    let violations = game.violations();
+    if violations.is_empty() {
+        state.invariant_broken.store(false, Ordering::Release);
+        return None;
+    }
+    if state.invariant_broken.swap(true, Ordering::AcqRel) {
+        return None;
     }
-    state.renderer.send(Renderable::Ball).await.unwrap();
+    let events = state.events.lock().unwrap().recent();
+    Some(CrashDump::new(
+        game,
+        violations,
+        events,
+        state.config.start_mode,
+        state.room.clone(),
+        now_ms() as u64,
+    ))
+}
+
+/// Log the violation, write the crash dump if there's a `--crash-dump-dir`
+/// and tell the operator's `--event-socket` about it.
+async fn report_violation(state: AppState, crash_dump: CrashDump) {
+    eprintln!("invariant violated: {}", crash_dump.violations.join(", "));
+    let violations = crash_dump.violations.clone();
+    let dump = match state.config.crash_dump_dir.clone() {
+        Some(dir) => {
+            let written =
+                tokio::task::spawn_blocking(move || crash_dump.write(&PathBuf::from(dir)))
+                    .await
+                    .expect("crash dump writer doesn't panic");
+            match written {
+                Ok(path) => {
+                    eprintln!("crash dump written to {}", path.display());
+                    Some(path.display().to_string())
+                }
+                Err(err) => {
+                    eprintln!("{err}");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    state
+        .hooks
+        .lock()
+        .unwrap()
+        .emit(GameEvent::InvariantViolation { violations, dump });
 }
 
 
 async fn render_all(state: &AppState) {
-    state.renderer.send(Renderable::BatLeft).await.unwrap();
-    state.renderer.send(Renderable::BatRight).await.unwrap();
-    state.renderer.send(Renderable::Scoreboard).await.unwrap();
-    state.renderer.send(Renderable::Ball).await.unwrap();
+    for renderable in Renderable::all() {
+        state.renderer.send(renderable).await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A started game with one bat per side and the default rules.
+    fn running_game() -> GameState {
+        GameState {
+            is_started: true,
+            is_running: true,
+            ..GameState::default()
+        }
+    }
+
+    /// A server's state with the given flags, and what it sends to its renderer.
+    fn test_state(args: &str) -> (AppState, mpsc::Receiver<Renderable>) {
+        let config = Config::from_args(args.split_whitespace().map(String::from)).unwrap();
+        let (render_tx, render_rx) = mpsc::channel(1024);
+        let state = get_initial_state(render_tx, config, Records::load(None).unwrap());
+        (state, render_rx)
+    }
+
+    #[tokio::test]
+    async fn physics_steps_faster_than_the_ball_is_sent() {
+        let args = "--physics-hz 120 --broadcast-hz 30 --scoring classic --score-limit 99";
+        let (state, mut render_rx) = test_state(args);
+        let _watching = state.update_tx.subscribe();
+        state.game.write().await.start();
+        state.wake_up.notify_one();
+        let game_loop = tokio::spawn(game_loop(state.clone()));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        game_loop.abort();
+        let game = state.game.read().await;
+        let steps = game.tick * 4 + u64::from(game.substep);
+        drop(game);
+        let mut balls = 0;
+        while let Ok(renderable) = render_rx.try_recv() {
+            balls += u64::from(renderable == Renderable::Ball);
+        }
+        // A tick is 32 ms, so a little over 120 steps and 30 ball updates a second.
+        let expected = (1_000_000 / state.config.step_interval().as_micros()) as u64;
+        assert!(steps.abs_diff(expected) <= expected / 20, "{steps} steps, not {expected}");
+        assert!(balls.abs_diff(steps / 4) <= 2, "{balls} ball updates for {steps} steps");
+    }
+
+    #[tokio::test]
+    async fn only_the_right_side_hands_it_to_the_computer() {
+        let (state, _render_rx) = test_state("--claim-sides");
+        assert_eq!(state.sides.claim("left-page", &[]), Some(Side::Left));
+        assert_eq!(state.sides.claim("right-page", &[]), Some(Side::Right));
+        for (page, ai) in [("left-page", false), ("right-page", true)] {
+            let press = KeyPress {
+                last_key: AI_KEY.to_string(),
+                sent_at: None,
+                debug_input: None,
+                controller: None,
+                page: Some(page.to_string()),
+            };
+            keypress(State(state.clone()), Form(press)).await;
+            assert_eq!(state.game.read().await.right.ai, ai, "after a press from {page}");
+        }
+    }
+
+    #[test]
+    fn random_serves_split_evenly() {
+        let mut game = running_game();
+        game.serve_mode = ServeMode::Random;
+        let toward_left = Ball::serve(Side::Left).velocity.0.signum();
+        // Rally lengths from a fixed xorshift, so the test always sees the same points.
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let (mut lefts, mut ups) = (0, 0);
+        for _ in 0..10_000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            game.tick += 30 + seed % 600;
+            let ball = game.serve_after_point(Side::Left);
+            if ball.velocity.0.signum() == toward_left {
+                lefts += 1;
+                game.right.score += 1;
+            } else {
+                game.left.score += 1;
+            }
+            ups += i32::from(ball.velocity.1 < 0);
+        }
+        assert!((4_800..=5_200).contains(&lefts), "{lefts} of 10000 serves to the left");
+        assert!((4_800..=5_200).contains(&ups), "{ups} of 10000 serves upwards");
+    }
+
+    #[test]
+    fn ball_near_the_top_wall_bounces_without_wrapping() {
+        let mut game = running_game();
+        game.ball = Ball {
+            position: (500, 3),
+            velocity: (10, -5),
+        };
+        game.step();
+        assert_eq!(game.ball.position.1, 0);
+        assert_eq!(game.ball.velocity.1, 5);
+    }
+
+    fn key(key: &str) -> Input {
+        Input::Key { key: key.to_string() }
+    }
+
+    fn click(x: f32, y: f32) -> Input {
+        Input::Click { x, y, intercept: false }
+    }
+
+    #[test]
+    fn explicit_start_mode_only_starts_on_the_pause_key() {
+        let mut game = GameState::default();
+        let not_running = Outcome::Ignored(Ignored::NotRunning);
+        assert_eq!(game.apply_input(click(0.2, 0.5), StartMode::Explicit).0, not_running);
+        assert_eq!(game.apply_input(key("w"), StartMode::Explicit).0, not_running);
+        assert_eq!(game.phase(), Phase::Waiting);
+        assert_eq!(game.apply_input(key(PAUSE_KEY), StartMode::Explicit).0, Outcome::Started);
+        assert_eq!(game.phase(), Phase::Running);
+    }
+
+    #[test]
+    fn any_input_starts_by_default() {
+        let mut game = GameState::default();
+        assert_eq!(game.apply_input(click(0.2, 0.5), StartMode::FirstInput).0, Outcome::Started);
+        assert_eq!(game.apply_input(key(PAUSE_KEY), StartMode::FirstInput).0, Outcome::Paused);
+        assert_eq!(game.apply_input(key(PAUSE_KEY), StartMode::FirstInput).0, Outcome::Started);
+    }
+
+    #[tokio::test]
+    async fn the_physics_hash_is_the_same_on_every_run() {
+        let query = |ticks, seed| Query(PhysicsHashQuery { ticks, seed });
+        let hash = |ticks| async move { physics_hash(query(ticks, None)).await.unwrap().0 };
+        let (first, second) = (hash(200).await, hash(200).await);
+        assert_eq!(first.hash, second.hash);
+        assert!(first.ticks > 0);
+        assert_ne!(hash(first.ticks - 1).await.hash, first.hash);
+        let seeded = physics_hash(query(200, Some(1))).await;
+        assert_eq!(seeded.err().map(|(status, _)| status), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn second_paddles_have_keys_of_their_own() {
+        let mut game = GameState {
+            is_started: true,
+            is_running: true,
+            ..GameState::new(2, Rules::default())
+        };
+        let positions = |game: &GameState| game.left.bats.iter().map(|bat| bat.position).collect();
+        let before: Vec<u16> = positions(&game);
+        assert!(before[1] + game.left.bats[1].height <= before[0], "the paddles are stacked");
+        let moved = game.apply_input(key("d"), StartMode::FirstInput).0;
+        assert_eq!(moved, Outcome::MovedBat(Side::Left));
+        let after: Vec<u16> = positions(&game);
+        assert_eq!(after[0], before[0]);
+        assert!(after[1] > before[1]);
+        let right_keys: Vec<&str> = game.right.bats.iter().map(|bat| bat.up_key.as_str()).collect();
+        assert_eq!(right_keys, ["o", "i"]);
+    }
+
+    /// Step `game` until it stops running, nobody moving the bats.
+    fn play_out(game: &mut GameState) -> Phase {
+        for _ in 0..100_000 {
+            if game.phase() != Phase::Running {
+                break;
+            }
+            game.step();
+        }
+        game.phase()
+    }
+
+    #[test]
+    fn phases_follow_the_game() {
+        let mut game = GameState::default();
+        assert_eq!(game.phase(), Phase::Waiting);
+        game.apply_input(key(PAUSE_KEY), StartMode::FirstInput);
+        assert_eq!(game.phase(), Phase::Running);
+        game.apply_input(key(PAUSE_KEY), StartMode::FirstInput);
+        assert_eq!(game.phase(), Phase::Paused);
+        game.apply_input(Input::Schedule { at: Some(u64::MAX) }, StartMode::FirstInput);
+        assert_eq!(game.phase(), Phase::Scheduled);
+        assert!(!Phase::Scheduled.is_over());
+        game.apply_input(Input::Schedule { at: None }, StartMode::FirstInput);
+        game.apply_input(key(PAUSE_KEY), StartMode::FirstInput);
+        assert_eq!(play_out(&mut game), Phase::Lost);
+        assert!(game.phase().is_over());
+    }
+
+    #[test]
+    fn classic_games_end_won() {
+        let rules = Rules {
+            scoring: Scoring::Classic,
+            score_limit: 2,
+            ..Rules::default()
+        };
+        let mut game = GameState::new(1, rules);
+        game.start();
+        assert_eq!(play_out(&mut game), Phase::Won);
+        let score = (game.left.score, game.right.score);
+        assert_eq!(score.0.max(score.1), 2);
+        assert_eq!(game.winner, rules.winner(score.0, score.1));
+    }
+
+    /// A running game with the ball coming towards the left bat from `y` and
+    /// the left side's assist at `level`.
+    fn assisted_game(y: u16, level: u8) -> GameState {
+        let mut game = running_game();
+        game.apply_input(Input::Assist { side: Side::Left, level }, StartMode::FirstInput);
+        game.left.bats[0].position = 100;
+        game.ball = Ball { position: (150, y), velocity: (-10, 0) };
+        game
+    }
+
+    #[test]
+    fn assists_pull_the_ball_towards_the_nearest_bat() {
+        let mut game = assisted_game(600, 2);
+        game.apply_assist();
+        assert_eq!(game.ball.velocity.1, -2);
+        let mut game = assisted_game(600, 9);
+        assert_eq!(game.left.assist, MAX_ASSIST);
+        game.ball.velocity.1 = -MAX_ASSISTED_SPEED;
+        game.apply_assist();
+        assert_eq!(game.ball.velocity.1, -MAX_ASSISTED_SPEED, "never beyond the top speed");
+    }
+
+    #[test]
+    fn assists_act_only_near_the_bats_and_outside_competitive_games() {
+        let mut game = assisted_game(600, 2);
+        game.ball.position.0 = 800;
+        game.apply_assist();
+        assert_eq!(game.ball.velocity.1, 0);
+        let mut game = assisted_game(600, 2);
+        game.rules.competitive = true;
+        game.apply_assist();
+        assert_eq!(game.ball.velocity.1, 0);
+        let mut game = assisted_game(600, 0);
+        game.apply_assist();
+        assert_eq!(game.ball.velocity.1, 0);
+    }
+
+    #[test]
+    fn moving_bats_spin_the_ball() {
+        assert_eq!(spin(3, 50), 13);
+        assert_eq!(spin(3, -50), -7);
+        assert_eq!(spin(0, 4), 0, "slow bats don't spin");
+        assert_eq!(spin(MAX_VERTICAL_SPEED, 100), MAX_VERTICAL_SPEED);
+        let mut game = running_game();
+        let bat = &mut game.left.bats[0];
+        bat.velocity = 50;
+        let center = bat.position + bat.height / 2;
+        game.ball = Ball { position: (20, center), velocity: (-10, 0) };
+        game.step();
+        assert!(game.ball.velocity.0 > 0, "the ball is returned");
+        assert_eq!(game.ball.velocity.1, 10);
+    }
+
+    #[test]
+    fn the_schema_describes_every_field_of_the_state() {
+        let mut game = running_game();
+        game.apply_input(Input::Practice { enabled: true }, StartMode::FirstInput);
+        let view = serde_json::to_value(StateView { phase: game.phase(), game }).unwrap();
+        let schema = serde_json::to_value(schema_for!(StateView)).unwrap();
+        let properties = schema["properties"].as_object().unwrap();
+        for field in view.as_object().unwrap().keys() {
+            assert!(properties.contains_key(field), "{field} is missing from the schema");
+        }
+        assert!(properties.contains_key("split_ball"));
+    }
+
+    #[test]
+    fn the_state_reads_back_as_written() {
+        let mut game = running_game();
+        game.step();
+        let json = serde_json::to_value(&game).unwrap();
+        let read: GameState = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), json);
+    }
+
+    #[test]
+    fn announcements_reach_subscribers_and_later_clients() {
+        let (state, _render_rx) = test_state("");
+        let mut updates = state.update_tx.subscribe();
+        show_banner(&state, "Back in <5> minutes", Some(10));
+        assert_eq!(updates.try_recv().unwrap().event, "announcement");
+        let announcement = state.announcement.lock().unwrap().take().unwrap();
+        assert!(announcement.html.contains("Back in &lt;5&gt; minutes"));
+        assert!(announcement.html.contains("fadeOut 10s"));
+        let shown_for = announcement.expires_at - clock::now();
+        assert!(shown_for <= Duration::from_secs(10) && shown_for > Duration::from_secs(9));
+    }
+
+    #[test]
+    fn the_ball_turns_from_green_to_red_as_it_speeds_up() {
+        let serve = Ball::default();
+        let flat = Ball { velocity: (serve.velocity.0, 0), ..serve };
+        assert_eq!(flat.color(), "hsl(120, 100%, 50%)");
+        let fastest = Ball { velocity: (serve.velocity.0, MAX_VERTICAL_SPEED), ..serve };
+        assert_eq!(fastest.color(), "hsl(0, 100%, 50%)");
+        let faster = Ball { velocity: (serve.velocity.0 * 3, -MAX_VERTICAL_SPEED), ..serve };
+        assert_eq!(faster.color(), "hsl(0, 100%, 50%)", "red is as far as it goes");
+        let hue = |ball: Ball| ball.color()[4..].split(',').next().unwrap().parse::<u32>().unwrap();
+        let between = Ball { velocity: (serve.velocity.0, 10), ..serve };
+        assert!((1..120).contains(&hue(between)));
+    }
+
+    #[test]
+    fn scheduled_games_refuse_to_start() {
+        let mut game = GameState::default();
+        let now = now_ms() as u64;
+        game.apply_input(Input::Schedule { at: Some(now + 61_500) }, StartMode::FirstInput);
+        let locked = Outcome::Ignored(Ignored::Locked);
+        assert_eq!(game.apply_input(Input::Start, StartMode::FirstInput).0, locked);
+        assert_eq!(game.apply_input(key(PAUSE_KEY), StartMode::FirstInput).0, locked);
+        assert_eq!(game.phase(), Phase::Scheduled);
+        assert_eq!(starts_in(&game).as_deref(), Some("1:02"));
+        game.scheduled_start = Some(now + 3_600_000);
+        assert_eq!(starts_in(&game).as_deref(), Some("1:00:00"));
+        game.apply_input(Input::Schedule { at: None }, StartMode::FirstInput);
+        assert_eq!(starts_in(&game), None);
+        assert_eq!(game.apply_input(Input::Start, StartMode::FirstInput).0, Outcome::Started);
+    }
+
+    #[tokio::test]
+    async fn a_schedule_in_the_past_starts_right_away() {
+        let (state, _render_rx) = test_state("--admin-token secret");
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let form = |at: &str| Form(ScheduleForm { at: at.to_string() });
+        let past = "2000-01-01T00:00:00Z";
+        let refused = schedule_start(State(state.clone()), HeaderMap::new(), form(past));
+        assert_eq!(refused.await.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        let invalid = schedule_start(State(state.clone()), headers.clone(), form("tomorrow"));
+        assert_eq!(invalid.await.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let started = schedule_start(State(state.clone()), headers, form(past));
+        assert!(started.await.unwrap().contains("starting now"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let game = state.game.read().await;
+        assert_eq!((game.phase(), game.scheduled_start), (Phase::Running, None));
+    }
+
+    #[tokio::test]
+    async fn simulated_points_are_the_same_every_time() {
+        let (state, mut render_rx) = test_state("");
+        let rendered = tokio::spawn(async move {
+            let mut count = 0;
+            while render_rx.recv().await.is_some() {
+                count += 1;
+            }
+            count
+        });
+        let Json(first) = simulate_point(State(state.clone())).await;
+        assert!(state.game.read().await.is_lost);
+        let Json(second) = simulate_point(State(state.clone())).await;
+        assert_eq!((first.ticks, first.events), (second.ticks, second.events));
+        assert!(first.ticks > 0);
+        drop(state);
+        assert_eq!(rendered.await.unwrap(), first.events + second.events);
+    }
+
+    #[test]
+    fn the_lite_view_has_the_whole_board() {
+        let mut game = GameState::new(2, Rules::default());
+        game.right.score = 3;
+        let view = serde_json::to_value(LiteView::new(&game)).unwrap();
+        let bats = |team: &Team| -> Vec<_> {
+            team.bats.iter().map(|bat| json!([bat.position, bat.height])).collect()
+        };
+        assert_eq!(view["game_id"], json!(game.id));
+        assert_eq!(view["ball"], json!([game.ball.position.0, game.ball.position.1]));
+        assert_eq!(view["left"], json!(bats(&game.left)));
+        assert_eq!(view["right"].as_array().unwrap().len(), 2);
+        assert_eq!(view["score"], json!([0, 3]));
+        assert_eq!(view["phase"], json!(Phase::Waiting));
+    }
+
+    #[test]
+    fn practice_balls_stay_in_their_halves() {
+        let mut game = running_game();
+        game.apply_input(Input::Practice { enabled: true }, StartMode::FirstInput);
+        let mut bounced = [false; 2];
+        for _ in 0..3000 {
+            game.step();
+            let left = game.ball.position.0;
+            let right = game.split_ball.as_ref().unwrap().position.0;
+            assert!(left <= center_line(Side::Left), "left ball at {left}");
+            assert!(right >= center_line(Side::Right), "right ball at {right}");
+            bounced[0] |= left == center_line(Side::Left);
+            bounced[1] |= right == center_line(Side::Right);
+        }
+        assert_eq!(bounced, [true; 2]);
+        assert_eq!((game.phase(), game.left.score, game.right.score), (Phase::Running, 0, 0));
+        game.apply_input(Input::Practice { enabled: false }, StartMode::FirstInput);
+        assert!(game.split_ball.is_none());
+    }
+
+    #[tokio::test]
+    async fn pixel_clicks_move_bats_like_normalized_ones() {
+        let mut positions = Vec::new();
+        for (args, x, y) in [("", 0.2, 0.1), ("--click-coordinates 1920x1080", 384., 108.)] {
+            let (state, _render_rx) = test_state(args);
+            state.game.write().await.start();
+            let position = MousePosition {
+                x,
+                y,
+                intercept: false,
+                sent_at: None,
+                debug_input: None,
+                controller: None,
+                page: None,
+            };
+            super::click(State(state.clone()), Form(position)).await;
+            positions.push(state.game.read().await.left.bats[0].position);
+        }
+        assert_eq!(positions[0], positions[1]);
+        assert_ne!(positions[0], GameState::default().left.bats[0].position);
+    }
+
+    #[test]
+    fn help_lists_the_keys_the_bats_are_bound_to() {
+        let mut game = GameState::new(2, Rules::default());
+        let keys = |help: &Help| -> Vec<_> {
+            help.controls
+                .iter()
+                .map(|controls| (controls.side, controls.bat, controls.up_key.clone()))
+                .collect()
+        };
+        let help = Help::new(&game, &Config::default());
+        let expected = [
+            (Side::Left, 1, "w".to_string()),
+            (Side::Left, 2, "e".to_string()),
+            (Side::Right, 1, "o".to_string()),
+            (Side::Right, 2, "i".to_string()),
+        ];
+        assert_eq!(keys(&help), expected);
+        assert_eq!((help.pause_key, help.ai_key), (PAUSE_KEY, AI_KEY));
+        let arrows = Input::Keys { side: Side::Left, set: KeySet::Arrows };
+        game.apply_input(arrows, StartMode::FirstInput);
+        let help = Help::new(&game, &Config::default());
+        assert_eq!(keys(&help)[0].2, "ArrowUp");
+        assert_eq!(help.bindings_revision, 1);
+    }
+
+    #[test]
+    fn drills_serve_their_count_and_leave_the_score_alone() {
+        let mut game = GameState::default();
+        let drill = Input::Drill { side: Side::Left, count: 5 };
+        assert_eq!(game.apply_input(drill, StartMode::FirstInput).0, Outcome::Started);
+        while game.is_running {
+            game.step();
+        }
+        let drill = game.drill.as_ref().unwrap();
+        assert_eq!((drill.hits + drill.misses, drill.is_done()), (5, true));
+        assert_eq!((game.left.score, game.right.score, game.is_lost), (0, 0, false));
+    }
+
+    #[tokio::test]
+    async fn drills_need_an_idle_board_and_end_on_a_pause() {
+        let (state, _render_rx) = test_state("");
+        let form = |count| Form(DrillForm { side: Side::Right, count });
+        let empty = drill(State(state.clone()), form(0)).await;
+        assert_eq!(empty.err().unwrap().0, StatusCode::BAD_REQUEST);
+        let drilling = tokio::spawn(drill(State(state.clone()), form(100)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(state.game.read().await.phase(), Phase::Running);
+        let busy = drill(State(state.clone()), form(1)).await;
+        assert_eq!(busy.err().unwrap().0, StatusCode::CONFLICT);
+        let mut g = state.game.write().await;
+        apply_input(&state, &mut g, key(PAUSE_KEY)).await;
+        drop(g);
+        let interrupted = drilling.await.unwrap().err().unwrap();
+        assert_eq!(interrupted, (StatusCode::CONFLICT, "the drill was interrupted by a pause"));
+    }
+
+    #[tokio::test]
+    async fn branding_flags_change_only_what_they_name() {
+        let (state, _render_rx) = test_state("");
+        let Html(help) = help_page(State(state.clone())).await;
+        let Html(lite) = lite_page(State(state)).await;
+        assert!(help.contains("<title>Hyperpong help</title>"));
+        assert!(help.contains(r#"<a href="https://github.com/zvyn/rspong/">Fork me"#));
+        assert!(help.contains(r#"href="/favicon.svg""#));
+        assert!(lite.contains("<title>Hyperpong lite</title>"));
+
+        let args = "--title Pong&Co --ribbon-url https://example.com/?a=1&b=\"2\" --favicon x.png";
+        let (state, _render_rx) = test_state(args);
+        let Html(branded) = help_page(State(state.clone())).await;
+        assert!(branded.contains("<title>Pong&amp;Co help</title>"));
+        assert!(branded.contains(r#"<a href="https://example.com/?a=1&amp;b=&quot;2&quot;">"#));
+        assert!(branded.contains(r#"<link rel="icon" href="/favicon">"#));
+        let built_in_icon = r#"<link rel="icon" type="image/svg+xml" href="/favicon.svg") }}">"#;
+        let unbranded = branded
+            .replace("Pong&amp;Co", "Hyperpong")
+            .replace("example.com/?a=1&amp;b=&quot;2&quot;", "github.com/zvyn/rspong/")
+            .replace(r#"<link rel="icon" href="/favicon">"#, built_in_icon);
+        assert_eq!(unbranded, help);
+
+        let (state, _render_rx) = test_state("--hide-ribbon");
+        let Html(plain) = help_page(State(state)).await;
+        assert!(!plain.contains("forkongithub"));
+    }
+
+    #[test]
+    fn favicons_get_their_content_type() {
+        let path = std::env::temp_dir().join(format!("rspong-icon-{}.svg", random_token()));
+        std::fs::write(&path, "<svg/>").unwrap();
+        let (content_type, icon) = load_favicon(path.to_str().unwrap()).unwrap();
+        assert_eq!((content_type, &icon[..]), ("image/svg+xml", &b"<svg/>"[..]));
+        std::fs::remove_file(&path).unwrap();
+        assert!(load_favicon(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn moves_within_a_tick_resolve_as_configured() {
+        let moves = || [(50, Direction::Down), (50, Direction::Down), (100, Direction::Up)];
+        for (resolution, position, applied) in [
+            (MoveResolution::Sum, 400, [true; 3]),
+            (MoveResolution::Last, 300, [true; 3]),
+            (MoveResolution::First, 450, [true, false, false]),
+        ] {
+            let mut bat = Bat::new("w", "s", 400);
+            for ((offset, direction), applied) in moves().into_iter().zip(applied) {
+                assert_eq!(move_bat(&mut bat, offset, direction, resolution), applied);
+            }
+            assert_eq!(bat.position, position, "{resolution:?}");
+            assert_eq!(bat.velocity, position as i16 - 400, "{resolution:?}");
+        }
+    }
+
+    #[test]
+    fn bats_stay_on_the_board() {
+        let mut bat = Bat::new("w", "s", 30);
+        move_bat(&mut bat, 50, Direction::Up, MoveResolution::Sum);
+        assert_eq!(bat.position, 0);
+        move_bat(&mut bat, FIELD_SIZE, Direction::Down, MoveResolution::Sum);
+        assert_eq!(bat.position, FIELD_SIZE - bat.height);
+        // Taking back the earlier moves of the tick starts from 30 again.
+        move_bat(&mut bat, 20, Direction::Up, MoveResolution::Last);
+        assert_eq!((bat.position, bat.velocity), (10, -20));
+    }
+
+    /// A running survival game with the smallest bats and `returns` returns.
+    fn worn_out_game(marathon_after: Option<u16>, returns: u16) -> GameState {
+        let rules = Rules { marathon_after, ..Rules::default() };
+        let mut game = GameState::new(1, rules);
+        game.start();
+        for bat in game.left.bats.iter_mut().chain(&mut game.right.bats) {
+            bat.height = MIN_BAT_HEIGHT;
+        }
+        game.left.score = returns;
+        game
+    }
+
+    #[test]
+    fn games_are_exhausted_past_the_marathon_threshold() {
+        assert!(!worn_out_game(None, 500).is_exhausted());
+        assert!(!worn_out_game(Some(10), 10).is_exhausted());
+        assert!(worn_out_game(Some(10), 11).is_exhausted());
+        let mut game = worn_out_game(Some(10), 11);
+        game.right.bats[0].height = MIN_BAT_HEIGHT + 1;
+        assert!(!game.is_exhausted());
+    }
+
+    #[test]
+    fn exhausted_games_end_as_a_marathon() {
+        let mut game = worn_out_game(Some(10), 11);
+        game.tick = 9000;
+        let mut effects = Vec::new();
+        game.miss(Side::Left, &mut effects);
+        assert_eq!(game.phase(), Phase::MarathonComplete);
+        assert!(game.phase().is_over());
+        assert!(effects.contains(&Effect::Marathon) && effects.contains(&Effect::GameOver));
+        let marathon = game.marathon.as_ref().unwrap();
+        assert_eq!((marathon.returns, marathon.ticks), (11, 9000));
+        let mut game = worn_out_game(Some(10), 10);
+        game.miss(Side::Left, &mut Vec::new());
+        assert_eq!((game.phase(), game.marathon.is_none()), (Phase::Lost, true));
+    }
+
+    #[test]
+    fn rounded_tips_deflect_the_ball_away_from_the_center() {
+        let bat = Bat::new("w", "s", 400);
+        let bottom = 400 + bat.height;
+        let deflection = |y| bat.tip_deflection(y, BatShape::Rounded);
+        assert_eq!(deflection(400), -(TIP_RADIUS as i16));
+        assert_eq!(deflection(405), -5);
+        assert_eq!(deflection(400 + TIP_RADIUS), 0);
+        assert_eq!(deflection(400 + bat.height / 2), 0);
+        assert_eq!(deflection(bottom - 3), 7);
+        assert_eq!(deflection(bottom), TIP_RADIUS as i16);
+        for y in [400, 405, bottom] {
+            assert_eq!(bat.tip_deflection(y, BatShape::Rectangle), 0);
+        }
+        // Tiny bats are all tip, but the arcs don't overlap.
+        let tiny = Bat { height: 8, ..Bat::new("w", "s", 400) };
+        assert_eq!(tiny.tip_deflection(400, BatShape::Rounded), -4);
+        assert_eq!(tiny.tip_deflection(404, BatShape::Rounded), 0);
+    }
+
+    #[test]
+    fn rebounds_add_the_deflection_to_the_ball() {
+        let bat = Bat::new("w", "s", 400);
+        let ball = Ball { position: (bat_line(Side::Left), 400), velocity: (-10, 3) };
+        assert_eq!(rebound(&ball, &bat, BatShape::Rectangle), 3);
+        assert_eq!(rebound(&ball, &bat, BatShape::Rounded), 3 - TIP_RADIUS as i16);
+        let steep = Ball { velocity: (-10, -MAX_VERTICAL_SPEED), ..ball };
+        assert_eq!(rebound(&steep, &bat, BatShape::Rounded), -MAX_VERTICAL_SPEED);
+    }
+
+    #[test]
+    fn moving_tires_bats_out_until_they_rest() {
+        let rules = Rules { stamina: Some(Stamina::default()), ..Rules::default() };
+        let mut game = GameState::new(1, rules);
+        game.start();
+        let bat = |game: &GameState| game.left.bats[0].clone();
+        // Drained by 4 a tick, the bat is spent after 25 ticks of moving.
+        for tick in 0..25 {
+            assert!(!bat(&game).tired, "tired after {tick} ticks");
+            let pressed = if tick % 2 == 0 { "w" } else { "s" };
+            game.apply_input(key(pressed), StartMode::FirstInput);
+            game.step();
+        }
+        assert_eq!((bat(&game).stamina, bat(&game).tired), (0., true));
+        let before = bat(&game).position;
+        game.apply_input(key("w"), StartMode::FirstInput);
+        assert_eq!(before - bat(&game).position, KEY_STEP / 2);
+        game.step();
+        // Regaining 2 a tick, it's rested once it's back over 30.
+        for _ in 0..15 {
+            game.step();
+        }
+        assert_eq!((bat(&game).stamina, bat(&game).tired), (30., true));
+        game.step();
+        assert!(!bat(&game).tired);
+        let y = bat(&game).position;
+        game.apply_input(click(0.1, 0.9), StartMode::FirstInput);
+        assert_eq!(bat(&game).stamina, 32. - Stamina::default().click_cost);
+        assert_eq!(bat(&game).position, y + bat(&game).height / 2);
+    }
+
+    fn tied_game(sudden_death: bool) -> GameState {
+        let rules = Rules {
+            scoring: Scoring::Classic,
+            score_limit: 11,
+            win_by: 2,
+            sudden_death,
+            ..Rules::default()
+        };
+        let mut game = GameState::new(1, rules);
+        game.start();
+        (game.left.score, game.right.score) = (10, 9);
+        // Left misses, which ties the game at 10 all.
+        game.miss(Side::Left, &mut Vec::new());
+        game
+    }
+
+    #[test]
+    fn ties_at_match_point_go_to_sudden_death() {
+        let mut game = tied_game(true);
+        assert!(game.sudden_death);
+        assert_eq!(game.match_point(), MatchPointState::SuddenDeath);
+        let serve = game.serve_after_point(Side::Left);
+        assert_eq!(game.ball.velocity.0, serve.velocity.0 * 3 / 2);
+        assert_eq!(game.left.bats[0].height, BAT_HEIGHT / 2);
+        assert_eq!(game.right.bats[0].height, BAT_HEIGHT / 2);
+        // Without a two point lead, the next point wins.
+        game.miss(Side::Right, &mut Vec::new());
+        assert_eq!((game.left.score, game.right.score), (11, 10));
+        assert_eq!((game.winner, game.phase()), (Some(Side::Left), Phase::Won));
+        game.reset();
+        assert!(!game.sudden_death);
+    }
+
+    #[test]
+    fn ties_need_a_lead_without_sudden_death() {
+        let mut game = tied_game(false);
+        assert!(!game.sudden_death);
+        assert_eq!(game.left.bats[0].height, BAT_HEIGHT);
+        game.miss(Side::Right, &mut Vec::new());
+        assert_eq!((game.winner, game.phase()), (None, Phase::Running));
+    }
+
+    #[test]
+    fn every_broken_invariant_is_named() {
+        let mut game = running_game();
+        for _ in 0..200 {
+            game.step();
+        }
+        assert_eq!(game.violations(), Vec::<String>::new());
+        game.ball.position = (FIELD_SIZE + 1, 10);
+        game.split_ball = Some(Ball { position: (10, FIELD_SIZE + 1), ..Ball::default() });
+        game.left.bats[0].position = FIELD_SIZE + 5;
+        game.right.bats[0].height = MIN_BAT_HEIGHT - 1;
+        game.right.bats[0].stamina = -1.;
+        (game.winner, game.is_lost) = (Some(Side::Left), false);
+        game.drill = Some(Drill { side: Side::Left, count: 2, hits: 2, misses: 1 });
+        assert_eq!(
+            game.violations(),
+            [
+                "ball at (1001, 10) is off the board",
+                "split ball at (10, 1001) is off the board",
+                "Left bat 1 at 1005 is off the board",
+                "Right bat 1 is only 9 high",
+                "Right bat 1 has -1 stamina",
+                "the game has a winner but isn't over",
+                "the drill served 3 of 2 balls",
+            ]
+        );
+    }
+
+    #[test]
+    fn a_broken_invariant_is_dumped_once_until_it_is_fixed() {
+        let (state, _render_rx) = test_state("");
+        let mut game = running_game();
+        assert!(check_invariants(&state, &game).is_none());
+        game.winner = Some(Side::Right);
+        let dump = check_invariants(&state, &game).unwrap();
+        assert_eq!(dump.violations, ["the game has a winner but isn't over"]);
+        assert!(check_invariants(&state, &game).is_none());
+        game.winner = None;
+        assert!(check_invariants(&state, &game).is_none());
+        game.winner = Some(Side::Right);
+        assert!(check_invariants(&state, &game).is_some());
+    }
+
+    /// A running game with the ball at `position`, moving `velocity`.
+    fn ball_at(position: (u16, u16), velocity: (i16, i16)) -> GameState {
+        let mut game = running_game();
+        game.ball = Ball { position, velocity };
+        game
+    }
+
+    #[test]
+    fn collisions_list_what_the_balls_run_into() {
+        let wall = Collision::Wall { split: false };
+        let cases = [
+            ((500, 500), vec![wall]),
+            ((500, 0), vec![wall]),
+            ((bat_line(Side::Left), 450), vec![
+                Collision::Bat { side: Side::Left, bat: 0, offset: 50 },
+                wall,
+            ]),
+            ((0, 450), vec![Collision::Bat { side: Side::Left, bat: 0, offset: 50 }, wall]),
+            ((bat_line(Side::Left), 100), vec![Collision::Miss { side: Side::Left }, wall]),
+            ((bat_line(Side::Right), 600), vec![
+                Collision::Bat { side: Side::Right, bat: 0, offset: 200 },
+                wall,
+            ]),
+            ((FIELD_SIZE, 900), vec![Collision::Miss { side: Side::Right }, wall]),
+        ];
+        for (position, expected) in cases {
+            let game = ball_at(position, (-10, 5));
+            assert_eq!(game.detect_collisions(), expected, "ball at {position:?}");
+        }
+        let mut stacked = GameState::new(2, Rules::default());
+        stacked.ball.position = (bat_line(Side::Right), 210);
+        let second = Collision::Bat { side: Side::Right, bat: 1, offset: 10 };
+        assert_eq!(stacked.detect_collisions(), [second, wall]);
+    }
+
+    #[test]
+    fn split_balls_collide_in_their_own_halves() {
+        let mut game = running_game();
+        game.serve_practice();
+        let walls = [Collision::Wall { split: true }, Collision::Wall { split: false }];
+        assert_eq!(game.detect_collisions(), walls);
+        game.ball.position = (center_line(Side::Left), 500);
+        game.split_ball.as_mut().unwrap().position = (bat_line(Side::Right), 0);
+        let expected = [
+            Collision::CenterWall { side: Side::Left },
+            Collision::Miss { side: Side::Right },
+        ];
+        assert_eq!(game.detect_collisions(), [&expected[..], &walls].concat());
+        game.ball.position = (bat_line(Side::Left), 500);
+        game.split_ball.as_mut().unwrap().position = (center_line(Side::Right), 500);
+        let expected = [
+            Collision::Bat { side: Side::Left, bat: 0, offset: 100 },
+            Collision::CenterWall { side: Side::Right },
+        ];
+        assert_eq!(game.detect_collisions(), [&expected[..], &walls].concat());
+    }
+
+    #[test]
+    fn walls_bounce_the_ball_back_onto_the_board() {
+        let mut game = ball_at((500, 0), (-10, -5));
+        assert_eq!(game.resolve(vec![Collision::Wall { split: false }]), [Effect::Bounced]);
+        assert_eq!((game.ball.position, game.ball.velocity), ((500, 0), (-10, 5)));
+        let mut game = ball_at((500, LOWEST_BALL + 3), (-10, 5));
+        assert_eq!(game.resolve(vec![Collision::Wall { split: false }]), [Effect::Bounced]);
+        assert_eq!((game.ball.position, game.ball.velocity), ((500, LOWEST_BALL), (-10, -5)));
+        let mut game = ball_at((500, 500), (-10, 5));
+        assert_eq!(game.resolve(vec![Collision::Wall { split: false }]), []);
+        // Without a split ball there's nothing for its walls to bounce.
+        assert_eq!(game.resolve(vec![Collision::Wall { split: true }]), []);
+        assert_eq!(game.ball.velocity, (-10, 5));
+    }
+
+    #[test]
+    fn center_walls_send_the_ball_back_into_its_half() {
+        let mut game = running_game();
+        game.serve_practice();
+        let split_ball = game.split_ball.as_mut().unwrap();
+        *split_ball = Ball { position: (480, 300), velocity: (-10, 5) };
+        assert_eq!(game.resolve(vec![Collision::CenterWall { side: Side::Right }]), []);
+        let split_ball = game.split_ball.as_ref().unwrap();
+        assert_eq!((split_ball.position, split_ball.velocity), ((500, 300), (10, 5)));
+    }
+
+    #[test]
+    fn bats_return_the_ball_and_score_by_the_rules() {
+        let line = bat_line(Side::Left);
+        let hit = Collision::Bat { side: Side::Left, bat: 0, offset: 50 };
+        let mut game = ball_at((line - 4, 450), (-10, 5));
+        // Survival returns score and shrink the bats.
+        let effects = game.resolve(vec![hit]);
+        assert_eq!(effects, [Effect::BatsChanged(Side::Left), Effect::ScoreChanged]);
+        assert_eq!((game.ball.position, game.ball.velocity), ((line, 450), (10, 5)));
+        assert_eq!((game.left.score, game.rally), (1, 1));
+        assert!(game.left.bats[0].height < BAT_HEIGHT);
+
+        let rules = Rules { scoring: Scoring::Classic, ..Rules::default() };
+        let mut classic = GameState::new(1, rules);
+        classic.start();
+        classic.ball = Ball { position: (line, 450), velocity: (-10, 5) };
+        // Classic returns only count towards the rally, yet redraw the scoreboard.
+        assert_eq!(classic.resolve(vec![hit]), [Effect::ScoreChanged]);
+        assert_eq!((classic.left.score, classic.rally, classic.ball.velocity), (0, 1, (10, 5)));
+
+        let mut practice = running_game();
+        practice.serve_practice();
+        practice.ball = Ball { position: (line, 450), velocity: (-10, 5) };
+        assert_eq!(practice.resolve(vec![hit]), []);
+        assert_eq!((practice.left.score, practice.ball.velocity), (0, (10, 5)));
+    }
+
+    #[test]
+    fn misses_end_survival_score_classic_and_reserve_practice() {
+        let miss = Collision::Miss { side: Side::Left };
+        let mut game = ball_at((0, 100), (-10, 5));
+        assert_eq!(game.resolve(vec![miss]), [Effect::GameOver]);
+        assert_eq!(game.phase(), Phase::Lost);
+
+        let rules = Rules { scoring: Scoring::Classic, ..Rules::default() };
+        let mut classic = GameState::new(1, rules);
+        classic.start();
+        assert_eq!(classic.resolve(vec![miss]), [Effect::ScoreChanged]);
+        assert_eq!((classic.left.score, classic.right.score), (0, 1));
+        assert_eq!(classic.ball.position, Ball::serve(Side::Right).position);
+
+        let mut practice = running_game();
+        practice.serve_practice();
+        practice.ball.position = (0, 100);
+        assert_eq!(practice.resolve(vec![miss]), []);
+        let served = Ball::serve_practice(Side::Left);
+        assert_eq!(practice.ball.position, served.position);
+        assert_eq!(practice.phase(), Phase::Running);
+    }
+
+    #[tokio::test]
+    async fn only_finished_games_are_reset() {
+        let (state, _render_rx) = test_state("");
+        state.game.write().await.start();
+        reset_if_over(&state).await;
+        assert_eq!(state.game.read().await.phase(), Phase::Running);
+        state.game.write().await.is_lost = true;
+        reset_if_over(&state).await;
+        assert_eq!(state.game.read().await.phase(), Phase::Waiting);
+    }
+
+    #[tokio::test]
+    async fn finished_games_reset_on_their_own_after_the_delay() {
+        let (state, _render_rx) = test_state("--auto-reset 1");
+        let mut game = state.game.write().await;
+        game.start();
+        game.is_lost = true;
+        drop(game);
+        let game_loop = tokio::spawn(game_loop(state.clone()));
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert_eq!(state.game.read().await.phase(), Phase::Lost);
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert_eq!(state.game.read().await.phase(), Phase::Waiting);
+        game_loop.abort();
+    }
+
+    #[test]
+    fn substeps_add_up_to_the_velocity() {
+        for velocity in [(-10, 7), (13, -25), (1, 0)] {
+            let start = Ball { position: (500, 500), velocity };
+            for substeps in 1..=4 {
+                let mut ball = start.clone();
+                for substep in 0..substeps {
+                    ball.advance(substep, substeps);
+                }
+                let expected = ((500 + velocity.0) as u16, (500 + velocity.1) as u16);
+                assert_eq!(ball.position, expected, "{velocity:?} in {substeps} substeps");
+            }
+        }
+    }
+
+    #[test]
+    fn fast_balls_do_not_tunnel_through_the_bats() {
+        for speed in [10i16, 22, 37, 60, 200] {
+            for side in Side::ALL {
+                let velocity = match side {
+                    Side::Left => -speed,
+                    Side::Right => speed,
+                };
+                // Far enough from the bat line to cross it within the tick.
+                let distance = i32::from(speed) - 1;
+                let x = i32::from(bat_line(side)) - i32::from(velocity.signum()) * distance;
+                let mut game = ball_at((x as u16, 500), (velocity, 0));
+                game.step();
+                let ball = &game.ball;
+                assert_eq!(game.phase(), Phase::Running, "{side:?} missed at {speed}");
+                assert_eq!(ball.velocity.0, -velocity, "{side:?} at {speed}");
+                assert!(ball.position.0.abs_diff(bat_line(side)) <= BAT_STRIP, "{side:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn fast_balls_are_checked_where_they_cross_the_bat_line() {
+        // Level with the bat's bottom end when it crosses the bat line, but
+        // past it by the end of the tick.
+        let bottom = GameState::default().left.bats[0].position + BAT_HEIGHT;
+        let position = (bat_line(Side::Left) + 9, bottom - 20);
+        let mut game = ball_at(position, (-35, 25));
+        game.step();
+        assert_eq!((game.phase(), game.ball.velocity.0), (Phase::Running, 35));
+        let mut unlimited = ball_at(position, (-35, 25));
+        unlimited.rules.max_ball_step = None;
+        unlimited.step();
+        assert_eq!(unlimited.phase(), Phase::Lost);
+    }
+
+    #[test]
+    fn ready_games_start_once_both_sides_are_ready() {
+        let mut game = GameState::default();
+        let mut apply = |input| game.apply_input(input, StartMode::Ready).0;
+        let not_ready = Outcome::Ignored(Ignored::NotReady);
+        assert_eq!(apply(Input::Start), not_ready);
+        assert_eq!(apply(key(PAUSE_KEY)), not_ready);
+        assert_eq!(apply(click(0.2, 0.5)), Outcome::Ignored(Ignored::NotRunning));
+        assert_eq!(apply(Input::Ready { side: Side::Left }), Outcome::Changed);
+        assert_eq!(apply(Input::Ready { side: Side::Left }), Outcome::Changed);
+        assert_eq!(apply(Input::Start), not_ready);
+        assert_eq!(apply(Input::Ready { side: Side::Right }), Outcome::Started);
+        let already_started = Outcome::Ignored(Ignored::AlreadyStarted);
+        assert_eq!(apply(Input::Ready { side: Side::Right }), already_started);
+        // Once started, pausing and resuming work as usual.
+        assert_eq!(apply(key(PAUSE_KEY)), Outcome::Paused);
+        assert_eq!(apply(key(PAUSE_KEY)), Outcome::Started);
+        game.reset();
+        assert!(!game.left.ready && !game.right.ready);
+    }
+
+    #[tokio::test]
+    async fn readying_clears_a_finished_game_first() {
+        let (state, _render_rx) = test_state("--start-mode ready");
+        state.game.write().await.is_lost = true;
+        let query = |side| Query(ReadyQuery { side });
+        let Json(outcome) = ready(State(state.clone()), query(Side::Right)).await;
+        assert_eq!(outcome, Outcome::Changed);
+        let game = state.game.read().await;
+        assert_eq!(game.phase(), Phase::Waiting);
+        assert_eq!((game.left.ready, game.right.ready), (false, true));
+    }
+
+    #[test]
+    fn serves_go_where_the_serve_mode_says() {
+        let mut game = running_game();
+        let direction = |ball: Ball| ball.velocity.0.signum();
+        let [left, right] = Side::ALL.map(|side| direction(Ball::serve(side)));
+        assert_eq!(game.serve_mode, ServeMode::TowardConceder);
+        assert_eq!(direction(game.serve_after_point(Side::Left)), left);
+        assert_eq!(direction(game.serve_after_point(Side::Right)), right);
+        game.apply_input(Input::ServeMode { mode: ServeMode::Fixed }, StartMode::FirstInput);
+        assert_eq!(direction(game.serve_after_point(Side::Left)), right);
+        assert_eq!(direction(game.serve_after_point(Side::Right)), right);
+        game.apply_input(Input::ServeMode { mode: ServeMode::Random }, StartMode::FirstInput);
+        let serve = game.serve_after_point(Side::Left);
+        let again = game.serve_after_point(Side::Right);
+        assert_eq!((serve.position, serve.velocity), (again.position, again.velocity));
+    }
+
+    #[test]
+    fn the_serve_mode_outlasts_a_reset() {
+        let mut game = running_game();
+        game.apply_input(Input::ServeMode { mode: ServeMode::Fixed }, StartMode::FirstInput);
+        game.reset();
+        assert_eq!(game.serve_mode, ServeMode::Fixed);
+        let [logged] = &game.input_log[..] else {
+            panic!("{} inputs logged after the reset", game.input_log.len());
+        };
+        assert!(matches!(logged.input, Input::ServeMode { mode: ServeMode::Fixed }));
+    }
+
+    #[test]
+    fn bat_edges_return_the_ball_only_when_inclusive() {
+        let bat = Bat::new("w", "s", 400);
+        let bottom = 400 + bat.height;
+        for y in [400, bottom] {
+            assert!(bat.covers(y, BatEdges::Inclusive), "{y}");
+            assert!(!bat.covers(y, BatEdges::Exclusive), "{y}");
+        }
+        for (y, covered) in [(399, false), (401, true), (bottom - 1, true), (bottom + 1, false)] {
+            assert_eq!(bat.covers(y, BatEdges::Inclusive), covered, "{y}");
+            assert_eq!(bat.covers(y, BatEdges::Exclusive), covered, "{y}");
+        }
+        for (edges, returned) in [(BatEdges::Inclusive, true), (BatEdges::Exclusive, false)] {
+            let mut game = ball_at((bat_line(Side::Right), 600), (10, 0));
+            game.rules.bat_edges = edges;
+            let collisions = game.detect_collisions();
+            let hit = matches!(collisions[0], Collision::Bat { offset: 200, .. });
+            assert_eq!(hit, returned, "{edges:?}");
+        }
+    }
+
+    #[test]
+    fn a_lone_bat_starts_centered_unless_told_otherwise() {
+        let positions = |game: &GameState| {
+            let bats = game.left.bats.iter().chain(&game.right.bats);
+            bats.map(|bat| bat.position).collect::<Vec<_>>()
+        };
+        let mut game = running_game();
+        assert_eq!(positions(&game), [400, 400]);
+        game.apply_input(key("w"), StartMode::FirstInput);
+        game.reset();
+        assert_eq!(positions(&game), [400, 400]);
+        let rules = Rules { bat_start: Some(700), ..Rules::default() };
+        let mut game = GameState::new(1, rules);
+        assert_eq!(positions(&game), [700, 700]);
+        game.left.bats[0].position = 0;
+        game.reset();
+        assert_eq!(positions(&game), [700, 700]);
+        assert_eq!(positions(&GameState::new(2, rules)), [600, 200, 600, 200]);
+    }
+
+    #[test]
+    fn resized_bats_are_clamped_and_stay_on_the_board() {
+        let mut game = GameState::new(2, Rules::default());
+        let resize = |game: &mut GameState, side, height| {
+            game.apply_input(Input::BatHeight { side, height }, StartMode::FirstInput)
+        };
+        game.right.bats[0].position = 750;
+        let (outcome, renderables) = resize(&mut game, Side::Right, 400);
+        assert_eq!((outcome, renderables), (Outcome::Changed, vec![Renderable::Bat(Side::Right)]));
+        let bats: Vec<_> = game.right.bats.iter().map(|bat| (bat.position, bat.height)).collect();
+        assert_eq!(bats, [(600, 400), (200, 400)]);
+        assert!(game.left.bats.iter().all(|bat| bat.height == BAT_HEIGHT));
+        resize(&mut game, Side::Left, 0);
+        assert!(game.left.bats.iter().all(|bat| bat.height == MIN_BAT_HEIGHT));
+        resize(&mut game, Side::Left, u16::MAX);
+        assert!(game.left.bats.iter().all(|bat| bat.height == MAX_BAT_HEIGHT));
+        assert_eq!(game.input_log.len(), 3);
+        game.reset();
+        assert!(game.right.bats.iter().all(|bat| bat.height == BAT_HEIGHT));
+    }
+
+    #[test]
+    fn bats_measure_the_distance_past_their_ends() {
+        let bat = Bat::new("w", "s", 400);
+        assert_eq!([350, 400, 500, 600, 630].map(|y| bat.distance_to(y)), [50, 0, 0, 0, 30]);
+    }
+
+    #[test]
+    fn near_misses_are_saved_while_the_side_has_saves_left() {
+        let forgiveness = rules::Forgiveness { distance: 20, saves: 2 };
+        let mut game = ball_at((0, 620), (-10, 0));
+        game.rules.forgiveness = Some(forgiveness);
+        let mut effects = Vec::new();
+        assert!(game.lucky_save(Side::Left, &mut effects));
+        assert_eq!(effects, [Effect::LuckySave]);
+        assert_eq!(game.ball.position, (bat_line(Side::Left), 620));
+        assert_eq!(game.ball.velocity, (5, MAX_VERTICAL_SPEED));
+        assert_eq!((game.left.lucky_saves, game.rally), (1, 1));
+        game.ball = Ball { position: (FIELD_SIZE, 380), velocity: (10, 0) };
+        assert!(game.lucky_save(Side::Right, &mut effects));
+        assert_eq!(game.ball.velocity, (-5, -MAX_VERTICAL_SPEED));
+        game.ball.position.1 = 621;
+        assert!(!game.lucky_save(Side::Left, &mut effects));
+        game.ball.position.1 = 385;
+        assert!(game.lucky_save(Side::Left, &mut effects));
+        assert!(!game.lucky_save(Side::Left, &mut effects), "the saves ran out");
+        assert_eq!(game.left.lucky_saves, 2);
+        let mut game = ball_at((0, 620), (-10, 0));
+        game.rules.forgiveness = Some(forgiveness);
+        game.rules.competitive = true;
+        assert!(!game.lucky_save(Side::Left, &mut effects));
+    }
+
+    #[tokio::test]
+    async fn a_failed_render_task_is_restarted_and_redraws_everything() {
+        let (mut state, render_rx) = test_state("");
+        state.templates.add_template("marathon", "{{ no_such_function() }}").unwrap();
+        let mut updates = state.update_tx.subscribe();
+        let supervisor = tokio::spawn(supervise_render(state.clone(), render_rx));
+        state.renderer.send(Renderable::Marathon).await.unwrap();
+        let redrawn = tokio::time::timeout(Duration::from_secs(2), async {
+            while updates.recv().await.unwrap().event != "ball" {}
+        });
+        redrawn.await.expect("the ball is redrawn after the restart");
+        assert_eq!(state.watchdog.snapshot().render_restarts, 1);
+        supervisor.abort();
+    }
+
+    #[test]
+    fn moves_past_the_budget_are_held_back_until_the_tick_ends() {
+        let mut moves = MoveBudget::new();
+        let (start, step) = (moves.started, Duration::from_millis(32));
+        let limit = Some(2);
+        assert!(moves.admit(&Renderable::Bat(Side::Left), limit));
+        assert!(moves.admit(&Renderable::Scoreboard, limit), "only moves count");
+        assert!(moves.admit(&Renderable::Ball, limit));
+        assert_eq!(moves.due(step), None);
+        for _ in 0..3 {
+            assert!(!moves.admit(&Renderable::Ball, limit));
+        }
+        assert!(!moves.admit(&Renderable::Bat(Side::Right), limit));
+        assert!(moves.admit(&Renderable::Ball, None));
+        assert_eq!(moves.due(step), Some(start + step));
+        assert_eq!(moves.roll(0, start + step / 2, step), []);
+        let held_back = moves.roll(1, start + step / 2, step);
+        assert_eq!(held_back, [Renderable::Ball, Renderable::Bat(Side::Right)]);
+        // What was held back counts towards the new tick.
+        assert!(!moves.admit(&Renderable::Ball, limit));
+        // A game standing still ends its ticks after a step's time.
+        assert_eq!(moves.roll(1, start + step, step), []);
+        assert_eq!(moves.roll(1, start + step * 3 / 2, step), [Renderable::Ball]);
+        assert!(moves.admit(&Renderable::Ball, limit));
+    }
+
+    #[tokio::test]
+    async fn rebound_sides_take_presses_of_their_old_keys_for_a_while() {
+        let (state, _render_rx) = test_state("");
+        let rebind = |side, set| rebind_keys(State(state.clone()), Form(KeysForm { side, set }));
+        assert!(rebind(Side::Left, KeySet::Arrows).await.is_ok());
+        let game = state.game.read().await.clone();
+        assert_eq!(game.left.bats[0].up_key, "ArrowUp");
+        let pressed = |key: &str| rebound_key(&state, &game, key.to_string());
+        assert_eq!([pressed("w"), pressed("s")], ["ArrowUp", "ArrowDown"]);
+        assert_eq!([pressed("ArrowUp"), pressed("o"), pressed("x")], ["ArrowUp", "o", "x"]);
+        state.rebound.lock().unwrap().as_mut().unwrap().until = clock::now();
+        assert_eq!(pressed("w"), "w", "the grace period is over");
+        let conflict = rebind(Side::Right, KeySet::Arrows).await;
+        assert_eq!(conflict.unwrap_err().0, StatusCode::CONFLICT);
+        assert_eq!(state.game.read().await.keys.right, KeySet::Default);
+    }
+
+    #[test]
+    fn intercepts_fold_the_bounces_off_the_walls() {
+        let intercept = |position, velocity, x| intercept_y(&Ball { position, velocity }, x);
+        assert_eq!(intercept((500, 500), (10, 5), 900), Some(700));
+        assert_eq!(intercept((500, 900), (10, 5), 900), Some(880), "off the bottom");
+        assert_eq!(intercept((500, 100), (-10, -5), 100), Some(100), "off the top");
+        assert_eq!(intercept((100, 100), (10, -20), 900), Some(480), "off both");
+        assert_eq!(intercept((500, 500), (-10, 5), 900), None, "moving away");
+        assert_eq!(intercept((500, 500), (0, 5), 900), None);
+        assert_eq!(intercept((900, 500), (0, 5), 900), None);
+    }
+
+    #[tokio::test]
+    async fn moves_into_the_wall_send_no_bat() {
+        let (state, render_rx) = test_state("");
+        state.game.write().await.start();
+        state.game.write().await.left.bats[0].position = 0;
+        render_bat(&state, Side::Left).await;
+        let mut update_rx = state.update_tx.subscribe();
+        let rendering = tokio::spawn(supervise_render(state.clone(), render_rx));
+        let press = |key: &str| KeyPress {
+            last_key: key.to_string(),
+            sent_at: None,
+            debug_input: None,
+            controller: None,
+            page: None,
+        };
+        keypress(State(state.clone()), Form(press("w"))).await;
+        keypress(State(state.clone()), Form(press("s"))).await;
+        let update = timeout(Duration::from_secs(1), update_rx.recv()).await.unwrap().unwrap();
+        rendering.abort();
+        // The move down is the first bat sent, the one up left it where it was.
+        assert_eq!(update.event, "bat_left");
+        assert!(update_rx.try_recv().is_err());
+        assert_ne!(state.game.read().await.left.bats[0].position, 0);
+    }
+
+    #[test]
+    fn balls_render_as_they_did_from_the_whole_game() {
+        let (state, _render_rx) = test_state("");
+        let whole_game = |game: &GameState| {
+            let tmpl = state.templates.get_template("ball").unwrap();
+            tmpl.render(context! {
+                game => game,
+                ball_color => game.ball.color(),
+                split_ball_color => game.split_ball.as_ref().map(Ball::color),
+            })
+            .unwrap()
+        };
+        let mut game = running_game();
+        let served = concat!(
+            r#"<div class=ball style="left: 23.0%; top: 42.0%; "#,
+            r#"background-color: hsl(113, 100%, 50%);"></div>"#,
+        );
+        assert_eq!(ball_fragment(&state, &game), served);
+        assert_eq!(whole_game(&game), served);
+        game.apply_input(Input::Practice { enabled: true }, StartMode::FirstInput);
+        for _ in 0..100 {
+            game.step();
+            assert_eq!(ball_fragment(&state, &game), whole_game(&game));
+        }
+        assert!(ball_fragment(&state, &game).contains("split_ball"));
+    }
 }
@@ -0,0 +1,69 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Counters for the broadcast channel feeding the SSE subscribers.
+#[derive(Default)]
+pub struct ChannelMetrics {
+    lag_events: AtomicU64,
+    skipped_events: AtomicU64,
+    queue_high_water: AtomicUsize,
+    collapsed_events: AtomicU64,
+}
+
+#[derive(Clone, Copy, Serialize)]
+pub struct ChannelStats {
+    /// How often a subscriber fell behind and got `Lagged`.
+    pub lag_events: u64,
+    /// Events lost by lagging subscribers in total.
+    pub skipped_events: u64,
+    /// Most events ever queued in the channel at once.
+    pub queue_high_water: usize,
+    /// Bat and ball updates left out for a later state of their tick, see
+    /// `--max-moves-per-tick`.
+    pub collapsed_events: u64,
+}
+
+impl ChannelMetrics {
+    pub fn record_lag(&self, skipped: u64) {
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+        self.skipped_events.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    pub fn record_queue_len(&self, len: usize) {
+        self.queue_high_water.fetch_max(len, Ordering::Relaxed);
+    }
+
+    pub fn record_collapsed(&self) {
+        self.collapsed_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ChannelStats {
+        ChannelStats {
+            lag_events: self.lag_events.load(Ordering::Relaxed),
+            skipped_events: self.skipped_events.load(Ordering::Relaxed),
+            queue_high_water: self.queue_high_water.load(Ordering::Relaxed),
+            collapsed_events: self.collapsed_events.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lags_add_up_and_the_queue_keeps_its_high_water_mark() {
+        let metrics = ChannelMetrics::default();
+        metrics.record_lag(5);
+        metrics.record_lag(2);
+        for len in [3, 40, 7] {
+            metrics.record_queue_len(len);
+        }
+        metrics.record_collapsed();
+        let stats = metrics.snapshot();
+        assert_eq!(stats.lag_events, 2);
+        assert_eq!(stats.skipped_events, 7);
+        assert_eq!(stats.queue_high_water, 40);
+        assert_eq!(stats.collapsed_events, 1);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::rules::{BatShape, Forgiveness, Rules, Scoring, Stamina};
+use serde::Serialize;
+
+/// A named variant of the game, which `POST /mode` switches to.
+#[derive(Serialize)]
+pub struct Mode {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Turns rules without any variant into this one.
+    #[serde(skip)]
+    apply: fn(&mut Rules),
+}
+
+/// Every mode `POST /mode` knows, the first one being the default game.
+pub const MODES: &[Mode] = &[
+    Mode {
+        name: "survival",
+        description: "Every return scores and shrinks the bats, the first miss ends the game",
+        apply: |_| {},
+    },
+    Mode {
+        name: "classic",
+        description: "A miss scores for the other side, the first to 11 wins",
+        apply: |rules| rules.scoring = Scoring::Classic,
+    },
+    Mode {
+        name: "tournament",
+        description: "Classic to 11 with a two point lead, without assists or lucky saves",
+        apply: |rules| {
+            rules.scoring = Scoring::Classic;
+            rules.win_by = 2;
+            rules.competitive = true;
+        },
+    },
+    Mode {
+        name: "sudden-death",
+        description: "Classic to 11, a tie at match point is decided by the next point",
+        apply: |rules| {
+            rules.scoring = Scoring::Classic;
+            rules.sudden_death = true;
+        },
+    },
+    Mode {
+        name: "endurance",
+        description: "Classic to 11 with bats that tire from moving",
+        apply: |rules| {
+            rules.scoring = Scoring::Classic;
+            rules.stamina = Some(Stamina::default());
+        },
+    },
+    Mode {
+        name: "beginner",
+        description: "Classic to 5 with rounded bats and three lucky saves per side",
+        apply: |rules| {
+            rules.scoring = Scoring::Classic;
+            rules.score_limit = 5;
+            rules.bat_shape = BatShape::Rounded;
+            rules.forgiveness = Some(Forgiveness::default());
+        },
+    },
+];
+
+impl Mode {
+    pub fn find(name: &str) -> Result<&'static Self, String> {
+        MODES.iter().find(|mode| mode.name == name).ok_or_else(|| {
+            let names: Vec<&str> = MODES.iter().map(|mode| mode.name).collect();
+            format!("unknown mode {name:?}, expected one of {}", names.join(", "))
+        })
+    }
+
+    /// The rules of this mode on a server started with `base`. What makes a
+    /// variant is the mode's, the physics and controls stay the server's.
+    pub fn rules(&self, base: &Rules) -> Rules {
+        let plain = Rules::default();
+        let mut rules = Rules {
+            scoring: plain.scoring,
+            score_limit: plain.score_limit,
+            win_by: plain.win_by,
+            competitive: plain.competitive,
+            marathon_after: plain.marathon_after,
+            bat_shape: plain.bat_shape,
+            stamina: plain.stamina,
+            sudden_death: plain.sudden_death,
+            forgiveness: plain.forgiveness,
+            ..*base
+        };
+        (self.apply)(&mut rules);
+        rules
+    }
+}
@@ -0,0 +1,499 @@
+use crate::clock;
+use crate::load::QualityShift;
+use axum::response::sse::Event;
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+
+/// Events queued for one subscriber before older ones are dropped.
+const CAPACITY: usize = 32;
+/// Events that only move the ball, which a client can do without for a while.
+const DISPOSABLE_EVENTS: [&str; 2] = ["ball", "correct"];
+/// Characters of a subscriber's name kept, see `--player-names`.
+const MAX_NAME_LEN: usize = 20;
+/// Subscribers listed by name, the others are only counted.
+const MAX_LISTED: usize = 10;
+/// One in this many ball events reaches spectators on `Quality::Reduced`.
+const REDUCED_BALL_EVERY: u64 = 3;
+/// Pages count as players for this long after their last input.
+const PLAYER_IDLE: Duration = Duration::from_secs(60);
+
+/// A broadcast event with its name, which `Event` doesn't let us read back.
+#[derive(Clone)]
+pub struct Update {
+    pub event: &'static str,
+    pub frame: Event,
+    /// Bytes of the name and data, near enough to what goes over the wire.
+    pub size: usize,
+}
+
+/// How much of the ball a subscriber gets, lowered for spectators while the
+/// room sends more than `--bandwidth-budget` allows. Players always get it all.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quality {
+    #[default]
+    Full,
+    /// One in `REDUCED_BALL_EVERY` ball events.
+    Reduced,
+    /// No ball events, the ball only snaps into place with the corrections
+    /// of `--correction-interval`.
+    Keyframes,
+}
+
+impl Quality {
+    fn shifted(self, shift: QualityShift) -> Self {
+        match (shift, self) {
+            (QualityShift::Lower, Self::Full) => Self::Reduced,
+            (QualityShift::Lower, _) => Self::Keyframes,
+            (QualityShift::Raise, Self::Keyframes) => Self::Reduced,
+            (QualityShift::Raise, _) => Self::Full,
+        }
+    }
+
+    /// Whether the `seen`th ball event on this quality is sent.
+    fn sends_ball(self, seen: u64) -> bool {
+        match self {
+            Self::Full => true,
+            Self::Reduced => seen.is_multiple_of(REDUCED_BALL_EVERY),
+            Self::Keyframes => false,
+        }
+    }
+}
+
+fn is_disposable(event: &str) -> bool {
+    DISPOSABLE_EVENTS.contains(&event)
+}
+
+/// The `quality_changed` event.
+#[derive(Serialize)]
+struct QualityChanged {
+    quality: Quality,
+}
+
+/// The events waiting to be sent to one SSE subscriber.
+///
+/// Every broadcast event replaces what the last one of the same name drew, so
+/// once the queue is full an event can take the place of a queued one of the
+/// same name. Failing that, ball frames make way for other events, and the
+/// newest ball frames dropped that way are sent as keyframes once the
+/// subscriber caught up. The scoreboard and lifecycle events are always kept.
+#[derive(Default)]
+struct Outbox {
+    queue: VecDeque<Update>,
+    keyframes: Vec<Update>,
+    /// Set when the broadcast channel overran the pump, which ends the stream.
+    lagged: Option<u64>,
+    dropped: u64,
+    quality: Quality,
+    /// Ball events broadcast since the subscriber connected.
+    balls_seen: u64,
+    /// Ball events left out for the quality.
+    thinned: u64,
+}
+
+impl Outbox {
+    fn push(&mut self, update: Update) {
+        if update.event == "ball" {
+            self.balls_seen += 1;
+            if !self.quality.sends_ball(self.balls_seen) {
+                self.thinned += 1;
+                return;
+            }
+        }
+        if self.queue.len() >= CAPACITY {
+            let superseded = self.queue.iter().position(|queued| queued.event == update.event);
+            let disposable = self.queue.iter().position(|queued| is_disposable(queued.event));
+            if let Some(superseded) = superseded {
+                self.queue.remove(superseded);
+                self.dropped += 1;
+            } else if is_disposable(update.event) {
+                self.keep_keyframe(update);
+                self.dropped += 1;
+                return;
+            } else if let Some(disposable) = disposable {
+                let shed = self.queue.remove(disposable).expect("position is in the queue");
+                if !self.queue.iter().any(|queued| queued.event == shed.event) {
+                    self.keep_keyframe(shed);
+                }
+                self.dropped += 1;
+            }
+            // Otherwise the queue grows past its capacity, as nothing in it
+            // may be dropped.
+        }
+        self.keyframes.retain(|keyframe| keyframe.event != update.event);
+        self.queue.push_back(update);
+    }
+
+    fn keep_keyframe(&mut self, update: Update) {
+        self.keyframes.retain(|keyframe| keyframe.event != update.event);
+        self.keyframes.push(update);
+    }
+
+    fn pop(&mut self) -> Option<Result<Update, BroadcastStreamRecvError>> {
+        if let Some(update) = self.queue.pop_front().or_else(|| self.keyframes.pop()) {
+            return Some(Ok(update));
+        }
+        self.lagged.take().map(|skipped| Err(BroadcastStreamRecvError::Lagged(skipped)))
+    }
+}
+
+struct Subscriber {
+    name: Option<String>,
+    /// The token of the page, which its inputs come with.
+    page: Option<String>,
+    outbox: Mutex<Outbox>,
+    ready: Notify,
+    delivered: AtomicU64,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberStats {
+    pub id: u64,
+    pub queued: usize,
+    pub delivered: u64,
+    /// Events dropped for newer or more important ones.
+    pub dropped: u64,
+    pub quality: Quality,
+    /// Whether the page sent inputs lately, which keeps it on `Quality::Full`.
+    pub player: bool,
+    /// Ball events left out for the quality.
+    pub thinned: u64,
+}
+
+/// The subscribers listed on the scoreboard with `--player-names`, by name or
+/// else by id.
+#[derive(Serialize)]
+pub struct Names {
+    pub names: Vec<String>,
+    /// Subscribers past the listed ones.
+    pub more: usize,
+}
+
+/// The outboxes of the open SSE connections.
+#[derive(Clone, Default)]
+pub struct Subscribers {
+    subscribers: Arc<Mutex<BTreeMap<u64, Arc<Subscriber>>>>,
+    last_id: Arc<AtomicU64>,
+    /// Notified whenever a subscriber is gone.
+    left: Arc<Notify>,
+    /// When the pages by token last sent an input.
+    inputs: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Bytes streamed to all subscribers so far.
+    sent: Arc<AtomicU64>,
+}
+
+/// Stops a subscriber's pump and forgets it once its stream is dropped.
+struct Unsubscribe {
+    id: u64,
+    subscribers: Subscribers,
+    pump: JoinHandle<()>,
+}
+
+impl Drop for Unsubscribe {
+    fn drop(&mut self) {
+        self.pump.abort();
+        self.subscribers.subscribers.lock().unwrap().remove(&self.id);
+        self.subscribers.left.notify_one();
+    }
+}
+
+impl Subscribers {
+    /// Queue `updates` for one subscriber as they're broadcast, so that a slow
+    /// client can't overrun the broadcast channel, and stream them from there.
+    pub fn subscribe(
+        &self,
+        mut updates: broadcast::Receiver<Update>,
+        name: Option<&str>,
+        page: Option<String>,
+    ) -> impl Stream<Item = Result<Event, BroadcastStreamRecvError>> {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let name = name
+            .map(|name| name.trim().chars().take(MAX_NAME_LEN).collect::<String>())
+            .filter(|name| !name.is_empty());
+        let subscriber = Arc::new(Subscriber {
+            name,
+            page,
+            outbox: Mutex::new(Outbox::default()),
+            ready: Notify::new(),
+            delivered: AtomicU64::new(0),
+        });
+        self.subscribers.lock().unwrap().insert(id, subscriber.clone());
+        let pump = tokio::spawn({
+            let subscriber = subscriber.clone();
+            async move {
+                loop {
+                    let update = updates.recv().await;
+                    let mut outbox = subscriber.outbox.lock().unwrap();
+                    match update {
+                        Ok(update) => outbox.push(update),
+                        Err(RecvError::Lagged(skipped)) => outbox.lagged = Some(skipped),
+                        Err(RecvError::Closed) => return,
+                    }
+                    let lagged = outbox.lagged.is_some();
+                    drop(outbox);
+                    subscriber.ready.notify_one();
+                    if lagged {
+                        return;
+                    }
+                }
+            }
+        });
+        let unsubscribe = Unsubscribe {
+            id,
+            subscribers: self.clone(),
+            pump,
+        };
+        stream::unfold((subscriber, unsubscribe), |(subscriber, unsubscribe)| async move {
+            loop {
+                let next = subscriber.outbox.lock().unwrap().pop();
+                if let Some(item) = next {
+                    subscriber.delivered.fetch_add(1, Ordering::Relaxed);
+                    let item = item.map(|update| {
+                        let sent = &unsubscribe.subscribers.sent;
+                        sent.fetch_add(update.size as u64, Ordering::Relaxed);
+                        update.frame
+                    });
+                    return Some((item, (subscriber, unsubscribe)));
+                }
+                subscriber.ready.notified().await;
+            }
+        })
+    }
+
+    /// Wait until a subscriber is gone, or was since the last call.
+    pub async fn left(&self) {
+        self.left.notified().await;
+    }
+
+    /// The names of the subscribers in the order they connected, `#` and
+    /// their id for those without one.
+    pub fn names(&self) -> Names {
+        let subscribers = self.subscribers.lock().unwrap();
+        let names = subscribers
+            .iter()
+            .take(MAX_LISTED)
+            .map(|(id, subscriber)| subscriber.name.clone().unwrap_or_else(|| format!("#{id}")))
+            .collect();
+        Names {
+            names,
+            more: subscribers.len().saturating_sub(MAX_LISTED),
+        }
+    }
+
+    /// Note an input of the page with token `page`, making it a player.
+    pub fn played(&self, page: &str) {
+        self.inputs.lock().unwrap().insert(page.to_string(), clock::now());
+    }
+
+    /// Bytes streamed to all subscribers so far.
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// Put the players on `Quality::Full` and shift the spectators' quality,
+    /// telling every subscriber whose quality changed with a
+    /// `quality_changed` event.
+    pub fn shift_quality(&self, shift: Option<QualityShift>) {
+        let players = self.players();
+        for subscriber in self.subscribers.lock().unwrap().values() {
+            let is_player = subscriber.page.as_ref().is_some_and(|page| players.contains_key(page));
+            let mut outbox = subscriber.outbox.lock().unwrap();
+            let quality = match shift {
+                _ if is_player => Quality::Full,
+                Some(shift) => outbox.quality.shifted(shift),
+                None => outbox.quality,
+            };
+            if quality == outbox.quality {
+                continue;
+            }
+            outbox.quality = quality;
+            let data =
+                serde_json::to_string(&QualityChanged { quality }).expect("quality serializes");
+            outbox.push(Update {
+                event: "quality_changed",
+                size: "quality_changed".len() + data.len(),
+                frame: Event::default().event("quality_changed").data(data),
+            });
+            drop(outbox);
+            subscriber.ready.notify_one();
+        }
+    }
+
+    /// The pages that sent an input lately, forgetting the others.
+    fn players(&self) -> HashMap<String, Instant> {
+        let mut inputs = self.inputs.lock().unwrap();
+        let now = clock::now();
+        inputs.retain(|_, at| now - *at < PLAYER_IDLE);
+        inputs.clone()
+    }
+
+    pub fn snapshot(&self) -> Vec<SubscriberStats> {
+        let players = self.players();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, subscriber)| {
+                let outbox = subscriber.outbox.lock().unwrap();
+                SubscriberStats {
+                    id: *id,
+                    queued: outbox.queue.len() + outbox.keyframes.len(),
+                    delivered: subscriber.delivered.load(Ordering::Relaxed),
+                    dropped: outbox.dropped,
+                    quality: outbox.quality,
+                    player: subscriber.page.as_ref().is_some_and(|page| players.contains_key(page)),
+                    thinned: outbox.thinned,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `event` update told apart from others of its name by `size`.
+    fn update(event: &'static str, size: usize) -> Update {
+        Update {
+            event,
+            frame: Event::default().event(event),
+            size,
+        }
+    }
+
+    /// A differently named event for every `n`.
+    fn nth_event(n: usize) -> &'static str {
+        Box::leak(format!("event-{n}").into_boxed_str())
+    }
+
+    fn drain(outbox: &mut Outbox) -> Vec<(&'static str, usize)> {
+        std::iter::from_fn(|| outbox.pop())
+            .map(|update| update.map(|update| (update.event, update.size)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn events_are_sent_in_order_until_the_outbox_is_full() {
+        let mut outbox = Outbox::default();
+        for n in 0..CAPACITY {
+            outbox.push(update(if n % 2 == 0 { "ball" } else { "score" }, n));
+        }
+        let sizes: Vec<_> = drain(&mut outbox).into_iter().map(|(_, size)| size).collect();
+        assert_eq!(sizes, (0..CAPACITY).collect::<Vec<_>>());
+        assert_eq!(outbox.dropped, 0);
+    }
+
+    #[test]
+    fn full_outboxes_replace_an_event_of_the_same_name() {
+        let mut outbox = Outbox::default();
+        outbox.push(update("score", 0));
+        for n in 1..CAPACITY {
+            outbox.push(update(nth_event(n), n));
+        }
+        outbox.push(update("score", 100));
+        let drained = drain(&mut outbox);
+        assert_eq!(drained.len(), CAPACITY);
+        assert_eq!(drained[0], ("event-1", 1));
+        assert_eq!(drained[CAPACITY - 1], ("score", 100));
+        assert_eq!(outbox.dropped, 1);
+    }
+
+    #[test]
+    fn ball_frames_make_way_and_come_back_as_keyframes() {
+        let mut outbox = Outbox::default();
+        outbox.push(update("ball", 0));
+        for n in 1..CAPACITY {
+            outbox.push(update(nth_event(n), n));
+        }
+        // The only queued ball frame makes way and is kept as a keyframe.
+        outbox.push(update("score", 100));
+        // Newer ball frames replace the keyframe instead of queueing.
+        outbox.push(update("ball", 101));
+        outbox.push(update("ball", 102));
+        let drained = drain(&mut outbox);
+        assert_eq!(drained.len(), CAPACITY + 1);
+        assert_eq!(drained[0], ("event-1", 1));
+        assert_eq!(drained[CAPACITY - 1], ("score", 100));
+        assert_eq!(drained[CAPACITY], ("ball", 102));
+        assert_eq!(outbox.dropped, 3);
+    }
+
+    #[test]
+    fn other_events_are_never_dropped() {
+        let mut outbox = Outbox::default();
+        for n in 0..CAPACITY + 5 {
+            outbox.push(update(nth_event(n), n));
+        }
+        assert_eq!(drain(&mut outbox).len(), CAPACITY + 5);
+        assert_eq!(outbox.dropped, 0);
+    }
+
+    #[test]
+    fn a_lag_ends_the_stream_after_what_was_queued() {
+        let mut outbox = Outbox::default();
+        outbox.push(update("score", 0));
+        outbox.lagged = Some(7);
+        assert_eq!(outbox.pop().unwrap().unwrap().event, "score");
+        let Some(Err(BroadcastStreamRecvError::Lagged(7))) = outbox.pop() else {
+            panic!("the lag isn't reported");
+        };
+        assert!(outbox.pop().is_none());
+    }
+
+    #[test]
+    fn lower_qualities_thin_out_the_ball() {
+        let mut outbox = Outbox { quality: Quality::Reduced, ..Outbox::default() };
+        for n in 0..9 {
+            outbox.push(update("ball", n));
+        }
+        assert_eq!(drain(&mut outbox), [("ball", 2), ("ball", 5), ("ball", 8)]);
+        outbox.quality = Quality::Keyframes;
+        outbox.push(update("ball", 9));
+        outbox.push(update("correct", 10));
+        assert_eq!(drain(&mut outbox), [("correct", 10)]);
+        assert_eq!(outbox.thinned, 7);
+    }
+
+    #[test]
+    fn qualities_shift_one_level_at_a_time() {
+        let lower = |quality: Quality| quality.shifted(QualityShift::Lower);
+        let raise = |quality: Quality| quality.shifted(QualityShift::Raise);
+        assert_eq!(lower(Quality::Full), Quality::Reduced);
+        assert_eq!(lower(Quality::Reduced), Quality::Keyframes);
+        assert_eq!(lower(Quality::Keyframes), Quality::Keyframes);
+        assert_eq!(raise(Quality::Keyframes), Quality::Reduced);
+        assert_eq!(raise(Quality::Reduced), Quality::Full);
+        assert_eq!(raise(Quality::Full), Quality::Full);
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_listed_by_name_until_they_leave() {
+        let subscribers = Subscribers::default();
+        let (updates, _) = broadcast::channel(CAPACITY);
+        let long_name = "A name much longer than twenty characters";
+        let mut streams: Vec<_> = ["  Alice ", "", long_name]
+            .into_iter()
+            .map(|name| Box::pin(subscribers.subscribe(updates.subscribe(), Some(name), None)))
+            .collect();
+        let names = subscribers.names();
+        assert_eq!(names.names, ["Alice", "#2", "A name much longer t"]);
+        assert_eq!(names.more, 0);
+        drop(streams.remove(0));
+        tokio::time::timeout(Duration::from_secs(1), subscribers.left()).await.unwrap();
+        assert_eq!(subscribers.names().names, ["#2", "A name much longer t"]);
+        for _ in 0..10 {
+            streams.push(Box::pin(subscribers.subscribe(updates.subscribe(), None, None)));
+        }
+        let names = subscribers.names();
+        assert_eq!((names.names.len(), names.names[9].as_str(), names.more), (10, "#11", 2));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::config::StartMode;
+use crate::rules::Rules;
+use crate::{GameState, LoggedInput, Phase};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Bumped whenever the document layout changes; older versions stay loadable.
+pub const VERSION: u32 = 1;
+
+/// A game's inputs and settings, enough to re-simulate it deterministically.
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    pub settings: RecordedSettings,
+    pub inputs: Vec<LoggedInput>,
+    /// The state when the recording was exported, to compare a replay against.
+    pub final_state: GameState,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct RecordedSettings {
+    pub paddles: usize,
+    pub rules: Rules,
+    pub start_mode: StartMode,
+}
+
+impl Recording {
+    pub fn new(game: &GameState, start_mode: StartMode) -> Self {
+        Self {
+            version: VERSION,
+            settings: RecordedSettings {
+                paddles: game.left.bats.len(),
+                rules: game.rules,
+                start_mode,
+            },
+            inputs: game.input_log.clone(),
+            final_state: game.clone(),
+        }
+    }
+
+    /// Read the recording at `path`.
+    pub fn read(path: &str) -> Result<Self, String> {
+        std::fs::read_to_string(path)
+            .map_err(|err| format!("can't read {path}: {err}"))
+            .and_then(|json| Self::load(&json))
+    }
+
+    pub fn load(json: &str) -> Result<Self, String> {
+        let document: Value =
+            serde_json::from_str(json).map_err(|err| format!("invalid recording: {err}"))?;
+        match document.get("version").and_then(Value::as_u64) {
+            Some(1) => serde_json::from_value(document)
+                .map_err(|err| format!("invalid version 1 recording: {err}")),
+            Some(version) => Err(format!(
+                "unsupported recording version {version}, this build reads up to {VERSION}"
+            )),
+            None => Err("recording has no version".to_string()),
+        }
+    }
+
+    /// Re-run the recorded inputs on a fresh game up to the recorded final tick,
+    /// calling `on_tick` after every step.
+    pub fn simulate(&self, mut on_tick: impl FnMut(&GameState)) -> Result<GameState, String> {
+        let mut playback = Playback::new(self);
+        while playback.step()? {
+            on_tick(playback.game());
+        }
+        Ok(playback.game)
+    }
+}
+
+/// A recording being re-run on a fresh game, one physics step at a time.
+pub struct Playback<'a> {
+    recording: &'a Recording,
+    game: GameState,
+    next_input: usize,
+}
+
+impl<'a> Playback<'a> {
+    pub fn new(recording: &'a Recording) -> Self {
+        Self {
+            recording,
+            game: GameState::new(recording.settings.paddles, recording.settings.rules),
+            next_input: 0,
+        }
+    }
+
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    /// Apply the inputs recorded for the current step and do the step,
+    /// returning `false` instead once the recorded final step is reached.
+    pub fn step(&mut self) -> Result<bool, String> {
+        let (recording, game) = (self.recording, &mut self.game);
+        while let Some(logged) = recording
+            .inputs
+            .get(self.next_input)
+            .filter(|logged| (logged.tick, logged.substep) == (game.tick, game.substep))
+        {
+            game.apply_input(logged.input.clone(), recording.settings.start_mode);
+            self.next_input += 1;
+        }
+        let final_state = &recording.final_state;
+        if (game.tick, game.substep) >= (final_state.tick, final_state.substep) {
+            return Ok(false);
+        }
+        if game.phase() != Phase::Running {
+            return Err(format!(
+                "game stopped at tick {} but the recording goes on until tick {}",
+                game.tick, final_state.tick
+            ));
+        }
+        game.step();
+        Ok(true)
+    }
+}
+
+/// Describe every field that differs between two JSON documents, by path.
+pub fn diff(path: &str, expected: &Value, actual: &Value) -> Vec<String> {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .flat_map(|(key, value)| {
+                diff(
+                    &format!("{path}.{key}"),
+                    value,
+                    actual.get(key).unwrap_or(&Value::Null),
+                )
+            })
+            .collect(),
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            expected
+                .iter()
+                .zip(actual)
+                .enumerate()
+                .flat_map(|(index, (expected, actual))| {
+                    diff(&format!("{path}[{index}]"), expected, actual)
+                })
+                .collect()
+        }
+        _ if expected == actual => Vec::new(),
+        _ => vec![format!("{path}: recorded {expected}, replayed {actual}")],
+    }
+}
+
+/// Entry point of `rspong replay-file`, returns the process exit code.
+pub fn replay_file(path: &str, trace: bool) -> i32 {
+    let loaded = std::fs::read_to_string(path)
+        .map_err(|err| format!("can't read {path}: {err}"))
+        .and_then(|json| Ok((Recording::load(&json)?, json)));
+    let (recording, json) = match loaded {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("{err}");
+            return 2;
+        }
+    };
+    let replayed = recording.simulate(|game| {
+        if trace {
+            println!("{}", serde_json::to_string(game).expect("game serializes"));
+        }
+    });
+    let replayed = match replayed {
+        Ok(replayed) => replayed,
+        Err(err) => {
+            eprintln!("replay failed: {err}");
+            return 1;
+        }
+    };
+    // Compared as exported, so that what an older build didn't keep track of
+    // yet isn't taken for a difference.
+    let document: Value = serde_json::from_str(&json).expect("a loaded recording is JSON");
+    let differences = diff(
+        "final_state",
+        &document["final_state"],
+        &serde_json::to_value(&replayed).expect("game serializes"),
+    );
+    if differences.is_empty() {
+        println!("replay matches the recorded final state at tick {}", replayed.tick);
+        0
+    } else {
+        for difference in differences {
+            println!("{difference}");
+        }
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Input;
+    use serde_json::json;
+
+    /// A game stepped for 100 ticks with a few presses in between.
+    fn played() -> GameState {
+        let mut game = GameState::default();
+        let press = |game: &mut GameState, key: &str| {
+            game.apply_input(Input::Key { key: key.to_string() }, StartMode::FirstInput);
+        };
+        press(&mut game, "p");
+        while game.phase() == Phase::Running && game.tick < 100 {
+            if game.tick % 10 == 0 {
+                let key = if game.tick % 20 == 0 { "w" } else { "l" };
+                press(&mut game, key);
+            }
+            game.step();
+        }
+        game
+    }
+
+    #[test]
+    fn a_recording_replays_to_its_final_state() {
+        let game = played();
+        let json = serde_json::to_string(&Recording::new(&game, StartMode::FirstInput)).unwrap();
+        let recording = Recording::load(&json).unwrap();
+        assert_eq!(recording.inputs.len(), game.input_log.len());
+        let mut ticks = 0;
+        let replayed = recording.simulate(|_| ticks += 1).unwrap();
+        assert_eq!((ticks, game.tick), (100, 100));
+        let (expected, actual) = (serde_json::to_value(&game), serde_json::to_value(&replayed));
+        assert_eq!(diff("final_state", &expected.unwrap(), &actual.unwrap()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn recordings_need_a_known_version() {
+        let error = |json| Recording::load(json).err().unwrap();
+        assert!(error(r#"{"version": 2}"#).contains("version 2"));
+        assert!(error("{}").contains("no version"));
+        assert!(error("[").starts_with("invalid recording"));
+    }
+
+    #[test]
+    fn differences_are_listed_by_path() {
+        let expected = json!({"tick": 3, "left": {"bats": [{"position": 400}]}});
+        let actual = json!({"tick": 3, "left": {"bats": [{"position": 450}]}});
+        assert_eq!(
+            diff("final_state", &expected, &actual),
+            ["final_state.left.bats[0].position: recorded 400, replayed 450"]
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crate::config::TICK_HZ;
+use crate::hooks::GameEvent;
+use crate::{broadcast, show_banner, AppState, PointSample, Side, FIELD_SIZE};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::sync::Mutex as AsyncMutex;
+
+/// Games won with fewer points don't count for the fastest game, so that
+/// short variants don't beat the full game.
+const FASTEST_GAME_POINTS: u16 = 11;
+/// How long the banner announcing new records is up, in seconds.
+const BANNER_SECONDS: u32 = 5;
+
+/// Something a game can set an all-time record in.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordKind {
+    /// The most returns in one point.
+    LongestRally,
+    /// The fewest ticks to win a game to `FASTEST_GAME_POINTS`.
+    FastestGame,
+    /// The most points the winner was behind by.
+    BiggestComeback,
+    /// The fastest a ball went, in field units per second.
+    TopBallSpeed,
+}
+
+impl RecordKind {
+    fn title(self) -> &'static str {
+        match self {
+            Self::LongestRally => "Longest rally",
+            Self::FastestGame => "Fastest game",
+            Self::BiggestComeback => "Biggest comeback",
+            Self::TopBallSpeed => "Fastest ball",
+        }
+    }
+
+    fn describe(self, value: u64) -> String {
+        match self {
+            Self::LongestRally => format!("{value} returns"),
+            Self::FastestGame => format!("{:.1} seconds", value as f64 / f64::from(TICK_HZ)),
+            Self::BiggestComeback => format!("from {value} points behind"),
+            Self::TopBallSpeed => format!(
+                "{:.2} boards per second",
+                value as f64 / f64::from(FIELD_SIZE)
+            ),
+        }
+    }
+
+    /// Whether `value` breaks a record of `held`. A tie keeps the earlier holder.
+    fn beats(self, value: u64, held: u64) -> bool {
+        match self {
+            Self::FastestGame => value < held,
+            _ => value > held,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub value: u64,
+    /// The side that won the game, for records of the winner.
+    pub holder: Option<Side>,
+    pub game_id: String,
+    /// Unix time in milliseconds at the end of the game.
+    pub set_at: u64,
+}
+
+/// A record as `/records` shows it.
+#[derive(Serialize)]
+pub struct RecordLine {
+    pub title: &'static str,
+    pub value: String,
+    pub holder: Option<Side>,
+}
+
+/// The `new_record` event.
+#[derive(Serialize)]
+struct NewRecord<'a> {
+    record: RecordKind,
+    #[serde(flatten)]
+    held: &'a Record,
+}
+
+/// The best game of every `RecordKind` the server saw, kept in the
+/// `--records` file if there is one.
+#[derive(Clone, Default)]
+pub struct Records {
+    path: Option<String>,
+    held: Arc<Mutex<BTreeMap<RecordKind, Record>>>,
+    /// Held while the file is written, as the games of every room save to it.
+    saving: Arc<AsyncMutex<()>>,
+}
+
+impl Records {
+    /// The records in the file at `path`, none if it doesn't exist yet.
+    pub fn load(path: Option<String>) -> Result<Self, String> {
+        let mut held = BTreeMap::new();
+        if let Some(path) = &path {
+            match std::fs::read_to_string(path) {
+                Ok(json) => {
+                    held = serde_json::from_str(&json)
+                        .map_err(|err| format!("invalid records {path}: {err}"))?;
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(format!("can't read records {path}: {err}")),
+            }
+        }
+        Ok(Self {
+            path,
+            held: Arc::new(Mutex::new(held)),
+            saving: Arc::default(),
+        })
+    }
+
+    pub fn held(&self) -> BTreeMap<RecordKind, Record> {
+        self.held.lock().unwrap().clone()
+    }
+
+    pub fn lines(&self) -> Vec<RecordLine> {
+        let held = self.held.lock().unwrap();
+        held.iter()
+            .map(|(kind, record)| RecordLine {
+                title: kind.title(),
+                value: kind.describe(record.value),
+                holder: record.holder,
+            })
+            .collect()
+    }
+
+    /// Keep the candidates that break a record, returning them.
+    fn update(&self, candidates: Vec<(RecordKind, Record)>) -> Vec<(RecordKind, Record)> {
+        let mut held = self.held.lock().unwrap();
+        let broken: Vec<_> = candidates
+            .into_iter()
+            .filter(|(kind, record)| {
+                held.get(kind).is_none_or(|held| kind.beats(record.value, held.value))
+            })
+            .collect();
+        for (kind, record) in &broken {
+            held.insert(*kind, record.clone());
+        }
+        broken
+    }
+
+    /// Write the records to the `--records` file, if there is one. Saves take
+    /// turns, and each writes the records as they are when its turn comes, so
+    /// that an earlier save never overwrites a later one.
+    async fn save(&self) -> Result<(), String> {
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let _saving = self.saving.lock().await;
+        let held = self.held();
+        tokio::task::spawn_blocking(move || save(&path, &held))
+            .await
+            .map_err(|err| format!("saving the records failed: {err}"))?
+    }
+}
+
+/// Write `held` to `path` by way of a temporary file, so that the records
+/// file is never left half written. Blocks, so run it with `spawn_blocking`.
+fn save(path: &str, held: &BTreeMap<RecordKind, Record>) -> Result<(), String> {
+    let temporary = format!("{path}.{}.tmp", crate::random_token());
+    let json = serde_json::to_vec_pretty(held).expect("records serialize");
+    std::fs::write(&temporary, json)
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .map_err(|err| format!("can't write records {path}: {err}"))
+}
+
+/// What the game that ended with the given winner, score, timeline and top
+/// speed would set records with, if it beats the ones held.
+fn candidates(
+    winner: Option<Side>,
+    score: (u16, u16),
+    timeline: &[PointSample],
+    top_speed: f64,
+) -> Vec<(RecordKind, u64, Option<Side>)> {
+    let mut candidates = Vec::new();
+    if let Some(rally) = timeline.iter().map(|sample| sample.rally).max().filter(|&rally| rally > 0) {
+        candidates.push((RecordKind::LongestRally, rally.into(), None));
+    }
+    if let Some(winner) = winner {
+        let points = match winner {
+            Side::Left => score.0,
+            Side::Right => score.1,
+        };
+        if let Some(last) = timeline.last().filter(|_| points >= FASTEST_GAME_POINTS) {
+            candidates.push((RecordKind::FastestGame, last.tick, Some(winner)));
+        }
+        let (mut won, mut lost, mut deficit) = (0u16, 0u16, 0u16);
+        for sample in timeline {
+            match sample.scorer {
+                Some(side) if side == winner => won += 1,
+                Some(_) => lost += 1,
+                None => {}
+            }
+            deficit = deficit.max(lost.saturating_sub(won));
+        }
+        if deficit > 0 {
+            candidates.push((RecordKind::BiggestComeback, deficit.into(), Some(winner)));
+        }
+    }
+    let speed = (top_speed * f64::from(TICK_HZ)).round() as u64;
+    if speed > 0 {
+        candidates.push((RecordKind::TopBallSpeed, speed, None));
+    }
+    candidates
+}
+
+/// Check every game that ends against the records, saving and announcing
+/// the ones it broke.
+pub async fn record(state: AppState, mut events: Receiver<GameEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(GameEvent::GameOver {
+                winner,
+                left,
+                right,
+                game_id,
+                timeline,
+                top_speed,
+                assisted,
+                ..
+            }) => {
+                if !assisted.is_empty() && !state.config.assisted_records {
+                    continue;
+                }
+                let set_at = crate::now_ms() as u64;
+                let candidates = candidates(winner, (left, right), &timeline, top_speed)
+                    .into_iter()
+                    .map(|(kind, value, holder)| {
+                        (kind, Record { value, holder, game_id: game_id.clone(), set_at })
+                    })
+                    .collect();
+                let broken = state.records.update(candidates);
+                if broken.is_empty() {
+                    continue;
+                }
+                announce(&state, &broken);
+                if let Err(err) = state.records.save().await {
+                    eprintln!("warning: {err}");
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Send a `new_record` event for each of `broken` and put them on a banner.
+fn announce(state: &AppState, broken: &[(RecordKind, Record)]) {
+    let mut lines = Vec::new();
+    for (kind, record) in broken {
+        let event = NewRecord {
+            record: *kind,
+            held: record,
+        };
+        broadcast(
+            state,
+            "new_record",
+            serde_json::to_string(&event).expect("record serializes"),
+        );
+        let holder = match record.holder {
+            Some(Side::Left) => " by the left side",
+            Some(Side::Right) => " by the right side",
+            None => "",
+        };
+        let title = kind.title().to_lowercase();
+        lines.push(format!("{title}, {}{holder}", kind.describe(record.value)));
+    }
+    let noun = if lines.len() == 1 { "record" } else { "records" };
+    show_banner(state, &format!("New {noun}: {}", lines.join("; ")), Some(BANNER_SECONDS));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(tick: u64, scorer: Side, rally: u32) -> PointSample {
+        PointSample { tick, scorer: Some(scorer), rally, heights: (200, 200) }
+    }
+
+    fn record(value: u64) -> Record {
+        Record { value, holder: None, game_id: "game".to_string(), set_at: 0 }
+    }
+
+    #[test]
+    fn comebacks_rallies_and_full_games_are_candidates() {
+        let mut timeline: Vec<_> = (0..3).map(|n| point(n * 100, Side::Right, 2)).collect();
+        timeline.extend((3..14).map(|n| point(n * 100, Side::Left, if n == 5 { 9 } else { 1 })));
+        let won = candidates(Some(Side::Left), (11, 3), &timeline, 10.);
+        let expected = [
+            (RecordKind::LongestRally, 9, None),
+            (RecordKind::FastestGame, 1300, Some(Side::Left)),
+            (RecordKind::BiggestComeback, 3, Some(Side::Left)),
+            (RecordKind::TopBallSpeed, 10 * u64::from(TICK_HZ), None),
+        ];
+        assert_eq!(won, expected);
+        let short = candidates(Some(Side::Right), (0, 5), &timeline[..5], 0.);
+        assert_eq!(short, [(RecordKind::LongestRally, 2, None)], "too short and never behind");
+        assert_eq!(candidates(None, (0, 0), &[], 0.), []);
+    }
+
+    #[test]
+    fn records_are_broken_by_better_values_only() {
+        let records = Records::default();
+        let broken = records.update(vec![
+            (RecordKind::LongestRally, record(5)),
+            (RecordKind::FastestGame, record(1000)),
+        ]);
+        assert_eq!(broken.len(), 2);
+        let broken = records.update(vec![
+            (RecordKind::LongestRally, record(5)),
+            (RecordKind::FastestGame, record(900)),
+            (RecordKind::TopBallSpeed, record(1)),
+        ]);
+        let broken: Vec<_> = broken.iter().map(|(kind, record)| (*kind, record.value)).collect();
+        assert_eq!(broken, [(RecordKind::FastestGame, 900), (RecordKind::TopBallSpeed, 1)]);
+        assert_eq!(records.held()[&RecordKind::LongestRally].value, 5);
+        let broken = records.update(vec![(RecordKind::LongestRally, record(6))]);
+        assert_eq!(broken.len(), 1);
+        let lines = records.lines();
+        assert_eq!((lines[0].title, lines[0].value.as_str()), ("Longest rally", "6 returns"));
+        assert_eq!(lines[1].value, format!("{:.1} seconds", 900. / f64::from(TICK_HZ)));
+    }
+
+    #[tokio::test]
+    async fn records_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("rspong-records-{}.json", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let records = Records::load(Some(path.clone())).unwrap();
+        assert!(records.held().is_empty(), "no file yet");
+        records.update(vec![(RecordKind::BiggestComeback, record(4))]);
+        records.save().await.unwrap();
+        let reloaded = Records::load(Some(path.clone())).unwrap().held();
+        assert_eq!(reloaded[&RecordKind::BiggestComeback].value, 4);
+        std::fs::write(&path, "[").unwrap();
+        assert!(Records::load(Some(path.clone())).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn saves_of_rooms_at_once_keep_every_record() {
+        let dir = std::env::temp_dir().join(format!("rspong-saves-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("records.json").to_str().unwrap().to_string();
+        let records = Records::load(Some(path.clone())).unwrap();
+        let saves: Vec<_> = (1..=20)
+            .map(|rally| {
+                let records = records.clone();
+                tokio::spawn(async move {
+                    records.update(vec![(RecordKind::LongestRally, record(rally))]);
+                    records.save().await
+                })
+            })
+            .collect();
+        for save in saves {
+            save.await.unwrap().unwrap();
+        }
+        let saved = Records::load(Some(path)).unwrap().held();
+        assert_eq!(saved[&RecordKind::LongestRally].value, 20);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1, "no temporary files left");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use crate::config::TICK_HZ;
+use crate::{Input, LoggedInput, Side};
+
+/// Intervals between presses whose spread is measured at a time.
+const WINDOW: usize = 12;
+/// The coefficient of variation of the intervals in a window below which
+/// they're too even for a human hand.
+const MAX_INHUMAN_CV: f64 = 0.08;
+/// How long the presses have to stay that even, in ticks, so that a few
+/// lucky ones don't count.
+const SUSTAINED_TICKS: f64 = 10. * TICK_HZ as f64;
+/// Presses of the same key at most this many ticks apart are the repeats of
+/// a held key, which keyboards send just as evenly. Slower presses count one
+/// by one, however even.
+const HELD_KEY_TICKS: f64 = 1.;
+
+/// A key press or click of one side.
+#[derive(Clone, Copy, Debug)]
+pub struct Press<'a> {
+    /// The tick, with the substep as a fraction of it.
+    pub at: f64,
+    /// The key, `None` for a click.
+    pub key: Option<&'a str>,
+}
+
+/// The presses in `inputs` of each side, the left one's first. `side_of_key`
+/// tells the keys apart, clicks go to the half of the board they're on.
+pub fn presses<'a>(
+    inputs: &'a [LoggedInput],
+    substeps: u8,
+    side_of_key: impl Fn(&str) -> Option<Side>,
+) -> [Vec<Press<'a>>; 2] {
+    let mut presses = [Vec::new(), Vec::new()];
+    for logged in inputs {
+        let (side, key) = match &logged.input {
+            Input::Key { key } | Input::HeldKey { key, .. } => {
+                (side_of_key(key), Some(key.as_str()))
+            }
+            Input::Click { x, .. } => {
+                (Some(if *x < 0.5 { Side::Left } else { Side::Right }), None)
+            }
+            _ => continue,
+        };
+        let Some(side) = side else {
+            continue;
+        };
+        let at = logged.tick as f64 + f64::from(logged.substep) / f64::from(substeps.max(1));
+        let index = match side {
+            Side::Left => 0,
+            Side::Right => 1,
+        };
+        presses[index].push(Press { at, key });
+    }
+    presses
+}
+
+/// Whether one side's `presses` came too evenly spaced for a human, window
+/// after window, for `SUSTAINED_TICKS`. The repeats of a held key count as
+/// one press.
+pub fn is_inhuman(presses: &[Press]) -> bool {
+    let mut kept: Vec<Press> = Vec::new();
+    let mut last: Option<Press> = None;
+    for &press in presses {
+        let held = last
+            .is_some_and(|last| last.key == press.key && press.at - last.at <= HELD_KEY_TICKS);
+        last = Some(press);
+        if !held {
+            kept.push(press);
+        }
+    }
+    let intervals: Vec<f64> = kept.windows(2).map(|pair| pair[1].at - pair[0].at).collect();
+    let mut even_since: Option<f64> = None;
+    for (start, window) in intervals.windows(WINDOW).enumerate() {
+        if !is_even(window) {
+            even_since = None;
+            continue;
+        }
+        let since = *even_since.get_or_insert(kept[start].at);
+        if kept[start + WINDOW].at - since >= SUSTAINED_TICKS {
+            return true;
+        }
+    }
+    false
+}
+
+fn is_even(intervals: &[f64]) -> bool {
+    let count = intervals.len() as f64;
+    let mean = intervals.iter().sum::<f64>() / count;
+    if mean <= 0. {
+        return false;
+    }
+    let variance = intervals.iter().map(|interval| (interval - mean).powi(2)).sum::<f64>() / count;
+    variance.sqrt() / mean < MAX_INHUMAN_CV
+}
+
+/// The sides whose presses in `inputs` look scripted, see `is_inhuman`.
+pub fn flagged(
+    inputs: &[LoggedInput],
+    substeps: u8,
+    side_of_key: impl Fn(&str) -> Option<Side>,
+) -> Vec<Side> {
+    let presses = presses(inputs, substeps, side_of_key);
+    Side::ALL
+        .into_iter()
+        .zip(presses)
+        .filter(|(_, presses)| is_inhuman(presses))
+        .map(|(side, _)| side)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Presses of alternating keys after the given intervals in ticks.
+    fn trace(intervals: impl IntoIterator<Item = f64>) -> Vec<Press<'static>> {
+        let mut at = 0.;
+        intervals
+            .into_iter()
+            .enumerate()
+            .map(|(index, interval)| {
+                at += interval;
+                let key = if index % 2 == 0 { "w" } else { "s" };
+                Press { at, key: Some(key) }
+            })
+            .collect()
+    }
+
+    /// The same key pressed after each interval, with repeats every tick
+    /// while it's held for `held` ticks.
+    fn held_trace(intervals: &[f64], held: usize) -> Vec<Press<'static>> {
+        let mut at = 0.;
+        let mut presses = Vec::new();
+        for &interval in intervals {
+            at += interval;
+            presses.extend((0..held).map(|repeat| Press {
+                at: at + repeat as f64,
+                key: Some("w"),
+            }));
+            at += held as f64;
+        }
+        presses
+    }
+
+    /// Intervals around `mean` ticks, off by up to a third either way.
+    fn jittered(mean: f64, count: usize) -> Vec<f64> {
+        let mut seed: u32 = 0x9e37_79b9;
+        (0..count)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                mean * (1. + (f64::from(seed % 1000) / 1000. - 0.5) * 2. / 3.)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_human_rhythm_is_not_flagged() {
+        assert!(!is_inhuman(&trace(jittered(6., 200))));
+        assert!(!is_inhuman(&held_trace(&jittered(10., 100), 4)));
+    }
+
+    #[test]
+    fn a_held_key_is_not_flagged() {
+        assert!(!is_inhuman(&held_trace(&[0.], 30 * 60)));
+    }
+
+    #[test]
+    fn a_bot_pressing_on_a_timer_is_flagged() {
+        assert!(is_inhuman(&trace([6.; 200])));
+        // The same key 96 ms apart, which used to be folded as a held key.
+        let same_key: Vec<Press> = trace([3.; 200])
+            .into_iter()
+            .map(|press| Press { key: Some("w"), ..press })
+            .collect();
+        assert!(is_inhuman(&same_key));
+    }
+
+    #[test]
+    fn a_bot_holding_for_even_spells_is_flagged() {
+        assert!(is_inhuman(&held_trace(&[8.; 100], 4)));
+    }
+
+    #[test]
+    fn even_presses_for_less_than_ten_seconds_are_not_flagged() {
+        assert!(!is_inhuman(&trace([6.; 40])));
+    }
+}
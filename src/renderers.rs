@@ -0,0 +1,263 @@
+use crate::config::RendererKind;
+use crate::{GameState, Phase, Renderable, Side};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Updates a renderer may fall behind by, further ones are dropped for it.
+const QUEUE_LEN: usize = 64;
+
+/// The game as a renderer gets it along with an update, at the time of the
+/// update.
+#[derive(Serialize)]
+pub struct GameSnapshot {
+    pub tick: u64,
+    pub phase: Phase,
+    pub ball: (u16, u16),
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_ball: Option<(u16, u16)>,
+    /// The top and height of each bat of the left side, then the right one's.
+    pub bats: [Vec<(u16, u16)>; 2],
+    pub score: (u16, u16),
+}
+
+impl GameSnapshot {
+    pub fn new(game: &GameState) -> Self {
+        let bats = |side: Side| {
+            let team = game.team(side);
+            team.bats.iter().map(|bat| (bat.position, bat.height)).collect()
+        };
+        Self {
+            tick: game.tick,
+            phase: game.phase(),
+            ball: game.ball.position,
+            split_ball: game.split_ball.as_ref().map(|ball| ball.position),
+            bats: [bats(Side::Left), bats(Side::Right)],
+            score: (game.left.score, game.right.score),
+        }
+    }
+}
+
+/// Draws the game somewhere besides the pages, e.g. on a terminal or an LED
+/// matrix. It gets every update the pages get, in the same order, on a
+/// thread of its own, so it may block.
+pub trait Renderer: Send + 'static {
+    fn render(&mut self, renderable: Renderable, snapshot: &GameSnapshot);
+}
+
+struct Output {
+    kind: RendererKind,
+    queue: mpsc::Sender<(Renderable, Arc<GameSnapshot>)>,
+    dropped: AtomicU64,
+}
+
+/// How a renderer of `--renderer` keeps up.
+#[derive(Serialize)]
+pub struct RendererStats {
+    pub renderer: RendererKind,
+    /// Updates it fell too far behind to get.
+    pub dropped: u64,
+}
+
+/// The renderers of `--renderer`, which the render task hands every update
+/// it draws for the pages.
+#[derive(Clone, Default)]
+pub struct Renderers {
+    outputs: Arc<Vec<Output>>,
+}
+
+impl Renderers {
+    /// Start a thread for each of `kinds`, drawing from its own queue.
+    pub fn start(kinds: &[RendererKind]) -> Self {
+        let outputs = kinds
+            .iter()
+            .map(|&kind| {
+                let (queue, mut updates) = mpsc::channel::<(_, Arc<_>)>(QUEUE_LEN);
+                let mut renderer = build(kind);
+                std::thread::spawn(move || {
+                    while let Some((renderable, snapshot)) = updates.blocking_recv() {
+                        renderer.render(renderable, &snapshot);
+                    }
+                });
+                Output {
+                    kind,
+                    queue,
+                    dropped: AtomicU64::new(0),
+                }
+            })
+            .collect();
+        Self {
+            outputs: Arc::new(outputs),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Queue `renderable` for every renderer, dropping it for the ones that
+    /// are too far behind rather than waiting for them.
+    pub fn send(&self, renderable: Renderable, snapshot: GameSnapshot) {
+        let snapshot = Arc::new(snapshot);
+        for output in self.outputs.iter() {
+            // Closed only if the renderer panicked, which its thread reported.
+            if output.queue.try_send((renderable, snapshot.clone())).is_err() {
+                output.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Vec<RendererStats> {
+        self.outputs
+            .iter()
+            .map(|output| RendererStats {
+                renderer: output.kind,
+                dropped: output.dropped.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+fn build(kind: RendererKind) -> Box<dyn Renderer> {
+    match kind {
+        #[cfg(feature = "log-renderer")]
+        RendererKind::Log => Box::new(LogRenderer),
+        #[cfg(feature = "ascii-renderer")]
+        RendererKind::Ascii => Box::new(AsciiRenderer::default()),
+    }
+}
+
+/// Writes every update with the game it shows as a JSON line to stderr.
+#[cfg(feature = "log-renderer")]
+struct LogRenderer;
+
+#[cfg(feature = "log-renderer")]
+impl Renderer for LogRenderer {
+    fn render(&mut self, renderable: Renderable, snapshot: &GameSnapshot) {
+        eprintln!("{}", Self::line(renderable, snapshot));
+    }
+}
+
+#[cfg(feature = "log-renderer")]
+impl LogRenderer {
+    fn line(renderable: Renderable, snapshot: &GameSnapshot) -> String {
+        let snapshot = serde_json::to_string(snapshot).expect("snapshot serializes");
+        format!("render {renderable:?} {snapshot}")
+    }
+}
+
+/// Draws the board on stdout with characters, redrawing it in place, e.g.
+/// for a terminal on the host.
+#[cfg(feature = "ascii-renderer")]
+#[derive(Default)]
+struct AsciiRenderer {
+    /// The tick last drawn, as every tick brings several updates.
+    drawn: Option<u64>,
+}
+
+#[cfg(feature = "ascii-renderer")]
+impl AsciiRenderer {
+    const COLUMNS: usize = 60;
+    const ROWS: usize = 20;
+
+    fn cell(position: (u16, u16)) -> (usize, usize) {
+        let scale = |value: u16, cells: usize| {
+            (usize::from(value) * cells / usize::from(crate::FIELD_SIZE)).min(cells - 1)
+        };
+        (scale(position.0, Self::COLUMNS), scale(position.1, Self::ROWS))
+    }
+
+    /// The board of `snapshot`, drawn over whatever the terminal shows.
+    fn frame(snapshot: &GameSnapshot) -> String {
+        let mut board = vec![vec![' '; Self::COLUMNS]; Self::ROWS];
+        for (side, bats) in Side::ALL.into_iter().zip(&snapshot.bats) {
+            let column = match side {
+                Side::Left => 0,
+                Side::Right => Self::COLUMNS - 1,
+            };
+            for &(top, height) in bats {
+                let (_, first) = Self::cell((0, top));
+                let (_, last) = Self::cell((0, top.saturating_add(height).saturating_sub(1)));
+                for row in &mut board[first..=last] {
+                    row[column] = '|';
+                }
+            }
+        }
+        for ball in [Some(snapshot.ball), snapshot.split_ball].into_iter().flatten() {
+            let (column, row) = Self::cell(ball);
+            board[row][column] = 'o';
+        }
+        let mut frame = format!(
+            "\x1b[H\x1b[2J{} : {} ({:?})\n",
+            snapshot.score.0, snapshot.score.1, snapshot.phase
+        );
+        frame.push_str(&"-".repeat(Self::COLUMNS));
+        frame.push('\n');
+        for row in board {
+            frame.extend(row);
+            frame.push('\n');
+        }
+        frame.push_str(&"-".repeat(Self::COLUMNS));
+        frame.push('\n');
+        frame
+    }
+}
+
+#[cfg(feature = "ascii-renderer")]
+impl Renderer for AsciiRenderer {
+    fn render(&mut self, _: Renderable, snapshot: &GameSnapshot) {
+        use std::io::Write;
+
+        if self.drawn == Some(snapshot.tick) {
+            return;
+        }
+        self.drawn = Some(snapshot.tick);
+        // Nothing to do about a closed terminal but to go on.
+        let _ = std::io::stdout().lock().write_all(Self::frame(snapshot).as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn snapshot() -> GameSnapshot {
+        let mut game = GameState::default();
+        game.ball.position = (500, 100);
+        game.right.score = 3;
+        GameSnapshot::new(&game)
+    }
+
+    #[test]
+    fn snapshots_leave_out_a_ball_that_isnt_split() {
+        let mut snapshot = serde_json::to_value(snapshot()).unwrap();
+        snapshot.as_object_mut().unwrap().remove("phase");
+        let bats = [[[400, 200]], [[400, 200]]];
+        let expected = json!({ "tick": 0, "ball": [500, 100], "bats": bats, "score": [0, 3] });
+        assert_eq!(snapshot, expected);
+    }
+
+    #[cfg(feature = "log-renderer")]
+    #[test]
+    fn the_log_renderer_writes_a_line_per_update() {
+        let line = LogRenderer::line(Renderable::Bat(Side::Left), &snapshot());
+        assert!(line.starts_with("render Bat(Left) {\"tick\":0,"), "{line}");
+        assert!(!line.contains('\n'));
+    }
+
+    #[cfg(feature = "ascii-renderer")]
+    #[test]
+    fn the_ascii_renderer_draws_the_board_in_cells() {
+        let frame = AsciiRenderer::frame(&snapshot());
+        let lines: Vec<_> = frame.lines().collect();
+        assert!(lines[0].ends_with("0 : 3 (Waiting)"), "{:?}", lines[0]);
+        assert_eq!(lines.len(), AsciiRenderer::ROWS + 3);
+        let row = |index: usize| lines[index + 2];
+        assert_eq!(row(2), format!("{}o{}", " ".repeat(30), " ".repeat(29)));
+        let bats = format!("|{}|", " ".repeat(58));
+        let empty = " ".repeat(60);
+        assert_eq!([row(7), row(8), row(11), row(12)], [&empty, &bats, &bats, &empty]);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::recording::Recording;
+use crate::{GameState, Side};
+
+const MAGIC: &str = "rpong";
+/// Bumped whenever the line layout changes.
+pub const VERSION: u32 = 1;
+/// The most paddles per side `--paddles` allows.
+const MAX_PADDLES: usize = 2;
+
+/// What the board showed on one tick. The second ball of practice isn't kept.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pub tick: u64,
+    pub ball: (u16, u16),
+    /// Position and height of every bat, the left ones first.
+    pub bats: Vec<(u16, u16)>,
+    /// The left and the right score.
+    pub score: (u16, u16),
+}
+
+impl Frame {
+    pub fn of(game: &GameState) -> Self {
+        Self {
+            tick: game.tick,
+            ball: game.ball.position,
+            bats: Side::ALL
+                .into_iter()
+                .flat_map(|side| &game.team(side).bats)
+                .map(|bat| (bat.position, bat.height))
+                .collect(),
+            score: (game.left.score, game.right.score),
+        }
+    }
+
+    /// Show this frame on `game`, which has as many bats.
+    pub fn apply(&self, game: &mut GameState) {
+        game.tick = self.tick;
+        game.ball.position = self.ball;
+        let bats = game.left.bats.iter_mut().chain(&mut game.right.bats);
+        for (bat, &(position, height)) in bats.zip(&self.bats) {
+            bat.position = position;
+            bat.height = height;
+        }
+        (game.left.score, game.right.score) = self.score;
+    }
+
+    fn values(&self) -> Vec<i64> {
+        let mut values = vec![self.tick as i64, self.ball.0.into(), self.ball.1.into()];
+        for &(position, height) in &self.bats {
+            values.extend([i64::from(position), i64::from(height)]);
+        }
+        values.extend([i64::from(self.score.0), i64::from(self.score.1)]);
+        values
+    }
+
+    fn from_values(values: &[i64]) -> Result<Self, String> {
+        let u16_at = |index: usize| {
+            u16::try_from(values[index])
+                .map_err(|_| format!("value {} is out of range: {}", index + 1, values[index]))
+        };
+        let bats = (3..values.len() - 2)
+            .step_by(2)
+            .map(|index| Ok((u16_at(index)?, u16_at(index + 1)?)))
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            tick: u64::try_from(values[0]).map_err(|_| format!("negative tick {}", values[0]))?,
+            ball: (u16_at(1)?, u16_at(2)?),
+            bats,
+            score: (u16_at(values.len() - 2)?, u16_at(values.len() - 1)?),
+        })
+    }
+}
+
+/// Values on a line of a replay with `paddles` bats per side.
+fn fields(paddles: usize) -> usize {
+    3 + 4 * paddles + 2
+}
+
+/// The frames of a game, one per tick. Unlike a `Recording`, which is
+/// re-simulated, a replay plays back the same on any build.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Replay {
+    pub paddles: usize,
+    pub frames: Vec<Frame>,
+}
+
+impl Replay {
+    /// Re-simulate `recording`, keeping a frame of every tick.
+    pub fn record(recording: &Recording) -> Result<Self, String> {
+        let settings = &recording.settings;
+        let mut frames = vec![Frame::of(&GameState::new(settings.paddles, settings.rules))];
+        recording.simulate(|game| {
+            if game.substep == 0 {
+                frames.push(Frame::of(game));
+            }
+        })?;
+        Ok(Self {
+            paddles: settings.paddles,
+            frames,
+        })
+    }
+
+    /// A running game to show the frames on.
+    pub fn board(&self) -> GameState {
+        let mut game = GameState::new(self.paddles, Default::default());
+        game.is_started = true;
+        game.is_running = true;
+        game
+    }
+}
+
+/// Write `replay` in the `.rpong` format. Its first line is
+/// `rpong <version> <paddles>`, every other line is one frame: the tick, the
+/// ball's x and y, the position and height of every bat (the left ones
+/// first) and the left and right score, each as its difference from the line
+/// before (from zero on the first one), separated by spaces. Trailing zeros
+/// are left out, so a tick where only the ball moved is three short numbers
+/// and one where nothing did is just `1`.
+pub fn encode(replay: &Replay) -> String {
+    let mut encoded = format!("{MAGIC} {VERSION} {}\n", replay.paddles);
+    let mut previous = vec![0; fields(replay.paddles)];
+    for frame in &replay.frames {
+        let values = frame.values();
+        let mut deltas: Vec<i64> =
+            values.iter().zip(&previous).map(|(value, previous)| value - previous).collect();
+        while deltas.last() == Some(&0) {
+            deltas.pop();
+        }
+        let deltas: Vec<String> = deltas.iter().map(i64::to_string).collect();
+        encoded.push_str(&deltas.join(" "));
+        encoded.push('\n');
+        previous = values;
+    }
+    encoded
+}
+
+/// Read a replay written by `encode`.
+pub fn decode(encoded: &str) -> Result<Replay, String> {
+    let mut lines = encoded.lines();
+    let header: Vec<&str> = lines.next().unwrap_or_default().split(' ').collect();
+    let paddles = match header[..] {
+        [MAGIC, version, paddles] => {
+            if version != VERSION.to_string() {
+                return Err(format!(
+                    "unsupported replay version {version}, this build reads {VERSION}"
+                ));
+            }
+            paddles
+                .parse()
+                .ok()
+                .filter(|paddles| (1..=MAX_PADDLES).contains(paddles))
+                .ok_or_else(|| format!("invalid number of paddles {paddles}"))?
+        }
+        _ => return Err("not an rpong replay".to_string()),
+    };
+    let mut values = vec![0i64; fields(paddles)];
+    let mut frames: Vec<Frame> = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line_number = index + 2;
+        let deltas = line
+            .split_whitespace()
+            .map(str::parse::<i64>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("line {line_number}: {err}"))?;
+        if deltas.len() > values.len() {
+            return Err(format!(
+                "line {line_number}: {} values, but a frame has {}",
+                deltas.len(),
+                values.len()
+            ));
+        }
+        for (value, delta) in values.iter_mut().zip(deltas) {
+            *value = value
+                .checked_add(delta)
+                .ok_or_else(|| format!("line {line_number}: value out of range"))?;
+        }
+        let frame =
+            Frame::from_values(&values).map_err(|err| format!("line {line_number}: {err}"))?;
+        if let Some(last) = frames.last().filter(|last| last.tick >= frame.tick) {
+            return Err(format!(
+                "line {line_number}: tick {} doesn't come after tick {}",
+                frame.tick, last.tick
+            ));
+        }
+        frames.push(frame);
+    }
+    Ok(Replay { paddles, frames })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StartMode;
+    use crate::{Input, Phase};
+
+    fn frame(tick: u64, ball: (u16, u16), bats: &[(u16, u16)], score: (u16, u16)) -> Frame {
+        Frame { tick, ball, bats: bats.to_vec(), score }
+    }
+
+    #[test]
+    fn replays_decode_to_what_was_encoded() {
+        let replay = Replay {
+            paddles: 2,
+            frames: vec![
+                frame(0, (500, 500), &[(600, 200), (200, 200), (600, 200), (200, 200)], (0, 0)),
+                frame(1, (490, 505), &[(600, 200), (200, 200), (600, 200), (200, 200)], (0, 0)),
+                frame(2, (480, 510), &[(550, 200), (200, 200), (600, 200), (200, 200)], (0, 0)),
+                frame(5, (0, 0), &[(550, 180), (200, 180), (600, 200), (200, 200)], (0, 3)),
+            ],
+        };
+        let encoded = encode(&replay);
+        assert_eq!(
+            encoded,
+            "rpong 1 2\n0 500 500 600 200 200 200 600 200 200 200\n1 -10 5\n1 -10 5 -50\n\
+             3 -480 -510 0 -20 0 -20 0 0 0 0 0 3\n"
+        );
+        assert_eq!(decode(&encoded), Ok(replay));
+    }
+
+    #[test]
+    fn a_recorded_game_replays_frame_by_frame() {
+        let mut game = GameState::default();
+        game.apply_input(Input::Key { key: "p".to_string() }, StartMode::FirstInput);
+        while game.phase() == Phase::Running && game.tick < 300 {
+            game.step();
+        }
+        let replay = Replay::record(&Recording::new(&game, StartMode::FirstInput)).unwrap();
+        assert_eq!(replay.frames.len() as u64, game.tick + 1);
+        assert_eq!(replay.frames.last(), Some(&Frame::of(&game)));
+        assert_eq!(decode(&encode(&replay)), Ok(replay.clone()));
+        let mut board = replay.board();
+        replay.frames[1].apply(&mut board);
+        assert_eq!(Frame::of(&board), replay.frames[1]);
+    }
+
+    #[test]
+    fn broken_replays_say_what_is_wrong() {
+        let error = |encoded: &str| decode(encoded).unwrap_err();
+        assert_eq!(error("png 1 1\n"), "not an rpong replay");
+        assert_eq!(error("rpong 2 1\n"), "unsupported replay version 2, this build reads 1");
+        assert_eq!(error("rpong 1 3\n"), "invalid number of paddles 3");
+        assert!(error("rpong 1 1\n1 x\n").starts_with("line 2: "));
+        let too_long = error("rpong 1 1\n1 2 3 4 5 6 7 8 9 10\n");
+        assert_eq!(too_long, "line 2: 10 values, but a frame has 9");
+        assert_eq!(error("rpong 1 1\n1 -2\n"), "line 2: value 2 is out of range: -2");
+        assert_eq!(error("rpong 1 1\n-1\n"), "line 2: negative tick -1");
+        assert_eq!(error("rpong 1 1\n3\n-1\n"), "line 3: tick 2 doesn't come after tick 3");
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{clock, game_routes, random_token, spawn_room_tasks, AppState};
+use axum::extract::{Path, Request, State};
+use axum::http::{StatusCode, Uri};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Router;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+/// Rooms nobody watched for this long are closed.
+const EMPTY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How often rooms are checked for watchers.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub type RoomId = String;
+
+/// A room of `POST /rooms`, with a game of its own under `/room/{id}`.
+pub struct RoomState {
+    state: AppState,
+    /// Every game route, on the room's state.
+    router: Router,
+    /// The room's game loop, render task and the like, aborted when the room
+    /// is closed and this is dropped.
+    _tasks: JoinSet<()>,
+    /// Since when nobody watches, `None` while somebody does.
+    empty_since: Option<Instant>,
+}
+
+/// The rooms besides the default one at `/`.
+#[derive(Clone, Default)]
+pub struct Rooms {
+    rooms: Arc<RwLock<HashMap<RoomId, RoomState>>>,
+}
+
+impl Rooms {
+    /// Open a room with a new game, sharing the server-wide parts of `state`,
+    /// or `None` if `--max-rooms` are open already.
+    fn open(&self, state: &AppState) -> Option<RoomId> {
+        let mut rooms = self.rooms.write().unwrap();
+        if rooms.len() >= state.config.max_rooms {
+            return None;
+        }
+        let id = random_token();
+        let (render_tx, render_rx) = mpsc::channel(50);
+        let state = state.room(&id, render_tx);
+        let room = RoomState {
+            router: game_routes(&state.config).with_state(state.clone()),
+            _tasks: spawn_room_tasks(&state, render_rx),
+            state,
+            empty_since: Some(clock::now()),
+        };
+        rooms.insert(id.clone(), room);
+        Some(id)
+    }
+
+    fn router(&self, id: &str) -> Option<Router> {
+        self.rooms.read().unwrap().get(id).map(|room| room.router.clone())
+    }
+
+    /// Close the rooms nobody watched for `EMPTY_TIMEOUT` by `now`.
+    fn close_empty(&self, now: Instant) {
+        self.rooms.write().unwrap().retain(|id, room| {
+            if room.state.update_tx.receiver_count() > 0 {
+                room.empty_since = None;
+                return true;
+            }
+            let empty_since = *room.empty_since.get_or_insert(now);
+            let keep = now - empty_since < EMPTY_TIMEOUT;
+            if !keep {
+                eprintln!("closing room {id}, nobody watched it for {EMPTY_TIMEOUT:?}");
+            }
+            keep
+        });
+    }
+}
+
+/// Close the rooms left without watchers, every `CHECK_INTERVAL`.
+pub async fn close_empty(rooms: Rooms) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        rooms.close_empty(clock::now());
+    }
+}
+
+/// Open a room and send the page to it.
+pub async fn create(
+    State(state): State<AppState>,
+) -> Result<Redirect, (StatusCode, &'static str)> {
+    let id = state
+        .rooms
+        .open(&state)
+        .ok_or((StatusCode::SERVICE_UNAVAILABLE, "too many rooms are open"))?;
+    Ok(Redirect::to(&format!("/room/{id}")))
+}
+
+#[derive(Deserialize)]
+pub struct RoomPath {
+    id: RoomId,
+    /// The game route in the room, the board if empty.
+    #[serde(default)]
+    rest: String,
+}
+
+/// Handle a request under `/room/{id}` with the route after it on the
+/// room's game.
+pub async fn dispatch(
+    State(state): State<AppState>,
+    Path(path): Path<RoomPath>,
+    mut request: Request,
+) -> Response {
+    let Some(router) = state.rooms.router(&path.id) else {
+        return (StatusCode::NOT_FOUND, "no such room").into_response();
+    };
+    let query = request.uri().query().map(|query| format!("?{query}")).unwrap_or_default();
+    let Ok(uri) = format!("/{}{query}", path.rest).parse::<Uri>() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    *request.uri_mut() = uri;
+    match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_initial_state, Config, Records};
+
+    #[tokio::test]
+    async fn rooms_nobody_watches_are_closed() {
+        let config = Config::from_args(["--max-rooms", "2"].map(String::from)).unwrap();
+        let (render_tx, _render_rx) = mpsc::channel(1);
+        let state = get_initial_state(render_tx, config, Records::load(None).unwrap());
+        let watched = state.rooms.open(&state).unwrap();
+        let empty = state.rooms.open(&state).unwrap();
+        assert_eq!(state.rooms.open(&state), None);
+        let watcher = state.rooms.rooms.read().unwrap()[&watched].state.update_tx.subscribe();
+        let open = |id: &str| state.rooms.rooms.read().unwrap().contains_key(id);
+
+        let now = clock::now();
+        state.rooms.close_empty(now + EMPTY_TIMEOUT - Duration::from_secs(1));
+        assert!(open(&watched) && open(&empty));
+        state.rooms.close_empty(now + EMPTY_TIMEOUT);
+        assert!(open(&watched) && !open(&empty));
+
+        // The timeout starts over once the last watcher leaves.
+        drop(watcher);
+        state.rooms.close_empty(now + EMPTY_TIMEOUT);
+        state.rooms.close_empty(now + EMPTY_TIMEOUT * 2 - Duration::from_secs(1));
+        assert!(open(&watched));
+        state.rooms.close_empty(now + EMPTY_TIMEOUT * 2);
+        assert!(!open(&watched));
+        assert!(state.rooms.open(&state).is_some());
+    }
+}
@@ -0,0 +1,621 @@
+use crate::Side;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How points are scored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Scoring {
+    /// Every return scores and shrinks the bats, the first miss ends the game.
+    Survival,
+    /// A miss scores for the other side, first to the score limit wins.
+    Classic,
+}
+
+impl std::str::FromStr for Scoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "survival" => Ok(Self::Survival),
+            "classic" => Ok(Self::Classic),
+            _ => Err(format!(
+                "invalid scoring {s:?}, expected \"survival\" or \"classic\""
+            )),
+        }
+    }
+}
+
+/// What happens when a bat gets several moves within one tick.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MoveResolution {
+    /// Apply them all, clamped to the board.
+    #[default]
+    Sum,
+    /// Only the last one counts, undoing the earlier ones.
+    Last,
+    /// Only the first one counts, dropping the later ones.
+    First,
+}
+
+impl std::str::FromStr for MoveResolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(Self::Sum),
+            "last" => Ok(Self::Last),
+            "first" => Ok(Self::First),
+            _ => Err(format!(
+                "invalid move resolution {s:?}, expected \"sum\", \"last\" or \"first\""
+            )),
+        }
+    }
+}
+
+/// The outline of the bats, which decides how their tips return the ball.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BatShape {
+    /// Every part of the bat returns the ball alike.
+    #[default]
+    Rectangle,
+    /// The ends are arcs which deflect the ball away from the bat's center.
+    Rounded,
+}
+
+impl std::str::FromStr for BatShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rectangle" => Ok(Self::Rectangle),
+            "rounded" => Ok(Self::Rounded),
+            _ => Err(format!(
+                "invalid bat shape {s:?}, expected \"rectangle\" or \"rounded\""
+            )),
+        }
+    }
+}
+
+/// Whether a ball exactly level with the top or bottom edge of a bat is returned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BatEdges {
+    /// The edges are part of the bat.
+    Inclusive,
+    /// Only a ball strictly between the edges is returned.
+    Exclusive,
+}
+
+impl std::str::FromStr for BatEdges {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "inclusive" => Ok(Self::Inclusive),
+            "exclusive" => Ok(Self::Exclusive),
+            _ => Err(format!(
+                "invalid bat edges {s:?}, expected \"inclusive\" or \"exclusive\""
+            )),
+        }
+    }
+}
+
+/// Which way the ball is served after a point.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ServeMode {
+    /// Always towards the right, like the first serve of a game.
+    Fixed,
+    /// Towards either side, up or down, as rolled from the game so far.
+    Random,
+    /// Towards the side that lost the point.
+    #[default]
+    TowardConceder,
+}
+
+/// How fast moving a bat tires it out, in percent of a full bar.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Stamina {
+    /// Spent per tick in which the bat moved.
+    pub drain: f32,
+    /// Recovered per tick in which the bat stood still.
+    pub regen: f32,
+    /// Spent per click, which moves the bat by half its height at once.
+    pub click_cost: f32,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self {
+            drain: 4.,
+            regen: 2.,
+            click_cost: 20.,
+        }
+    }
+}
+
+/// Misses by a hair that are turned into weak returns instead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Forgiveness {
+    /// How far past the end of a bat a ball may go and still be saved.
+    pub distance: u16,
+    /// Saves per side and game.
+    pub saves: u16,
+}
+
+impl Default for Forgiveness {
+    fn default() -> Self {
+        Self {
+            distance: 20,
+            saves: 3,
+        }
+    }
+}
+
+/// A built-in set of keys for the bats of a side.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySet {
+    /// W and S on the left, O and L on the right.
+    #[default]
+    Default,
+    /// The arrow keys, clear of browser and screen reader shortcuts.
+    Arrows,
+    /// I and K, for the right hand on the home row.
+    Ijkl,
+}
+
+impl KeySet {
+    const ALL: [Self; 3] = [Self::Default, Self::Arrows, Self::Ijkl];
+
+    /// The up and down keys of each of `side`'s stacked bats.
+    pub fn keys(self, side: Side) -> [(&'static str, &'static str); 2] {
+        match (self, side) {
+            (Self::Default, Side::Left) => [("w", "s"), ("e", "d")],
+            (Self::Default, Side::Right) => [("o", "l"), ("i", "k")],
+            (Self::Arrows, _) => [("ArrowUp", "ArrowDown"), ("PageUp", "PageDown")],
+            (Self::Ijkl, _) => [("i", "k"), ("u", "j")],
+        }
+    }
+
+    /// Every key any set binds for `paddles` bats per side, for pages to send
+    /// whichever set is bound.
+    pub fn every_key(paddles: usize) -> Vec<&'static str> {
+        let mut keys = Vec::new();
+        for set in Self::ALL {
+            for side in Side::ALL {
+                for (up, down) in &set.keys(side)[..paddles] {
+                    for key in [*up, *down] {
+                        if !keys.contains(&key) {
+                            keys.push(key);
+                        }
+                    }
+                }
+            }
+        }
+        keys
+    }
+}
+
+impl std::str::FromStr for KeySet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "arrows" => Ok(Self::Arrows),
+            "ijkl" => Ok(Self::Ijkl),
+            _ => Err(format!(
+                "invalid key set {s:?}, expected \"default\", \"arrows\" or \"ijkl\""
+            )),
+        }
+    }
+}
+
+/// The key sets the sides play with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Bindings {
+    pub left: KeySet,
+    pub right: KeySet,
+}
+
+impl Bindings {
+    pub fn of(&self, side: Side) -> KeySet {
+        match side {
+            Side::Left => self.left,
+            Side::Right => self.right,
+        }
+    }
+
+    pub fn set(&mut self, side: Side, set: KeySet) {
+        match side {
+            Side::Left => self.left = set,
+            Side::Right => self.right = set,
+        }
+    }
+
+    /// Whether both sides would have a key in common with `paddles` bats each.
+    pub fn overlap(&self, paddles: usize) -> bool {
+        let keys = |side: Side| {
+            self.of(side).keys(side)[..paddles]
+                .iter()
+                .flat_map(|(up, down)| [*up, *down])
+                .collect::<Vec<_>>()
+        };
+        let right = keys(Side::Right);
+        keys(Side::Left).iter().any(|key| right.contains(key))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Rules {
+    pub scoring: Scoring,
+    /// Points needed to win in classic scoring.
+    pub score_limit: u16,
+    /// Lead needed to win in classic scoring, 2 for the "win by two" rule.
+    pub win_by: u8,
+    /// Disables accessibility assists.
+    pub competitive: bool,
+    /// Distance from a side's bats within which its assist acts on the ball.
+    pub assist_range: u16,
+    #[serde(default)]
+    pub same_tick_moves: MoveResolution,
+    /// In survival scoring, once both sides' bats are at their minimum and
+    /// the combined score is above this, the next miss completes a marathon.
+    #[serde(default)]
+    pub marathon_after: Option<u16>,
+    #[serde(default)]
+    pub bat_shape: BatShape,
+    /// Bats tire from moving, `None` for infinite stamina.
+    #[serde(default)]
+    pub stamina: Option<Stamina>,
+    /// In classic scoring, a tie at match point goes to sudden death: the
+    /// next point wins, served faster to shrunk bats.
+    #[serde(default)]
+    pub sudden_death: bool,
+    /// Physics steps per tick, each moving the ball a share of its velocity
+    /// and checking it for collisions.
+    #[serde(default = "one_substep")]
+    pub substeps: u8,
+    /// The farthest the ball moves horizontally before it's checked for
+    /// collisions, `None` for once per physics step however fast it is.
+    /// Missing from recordings made before it existed, which replay without.
+    #[serde(default)]
+    pub max_ball_step: Option<u16>,
+    /// Recordings made before it existed replay with exclusive edges.
+    #[serde(default = "exclusive_edges")]
+    pub bat_edges: BatEdges,
+    /// Where the top of a lone bat on each side starts, `None` for centered.
+    /// Recordings made before it existed start it at 600, below the center.
+    #[serde(default = "legacy_bat_start")]
+    pub bat_start: Option<u16>,
+    /// Saves misses close to a bat, `None` for never. Off in competitive games.
+    #[serde(default)]
+    pub forgiveness: Option<Forgiveness>,
+    /// Ticks the bats the server plays take to notice where the ball goes.
+    #[serde(default)]
+    pub ai_reaction: u16,
+}
+
+/// The width of the strip along each side wall the bats move in.
+pub const BAT_STRIP: u16 = 10;
+
+fn one_substep() -> u8 {
+    1
+}
+
+fn exclusive_edges() -> BatEdges {
+    BatEdges::Exclusive
+}
+
+fn legacy_bat_start() -> Option<u16> {
+    Some(600)
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            scoring: Scoring::Survival,
+            score_limit: 11,
+            win_by: 1,
+            competitive: false,
+            assist_range: 200,
+            same_tick_moves: MoveResolution::Sum,
+            marathon_after: None,
+            bat_shape: BatShape::Rectangle,
+            stamina: None,
+            sudden_death: false,
+            substeps: 1,
+            max_ball_step: Some(BAT_STRIP),
+            bat_edges: BatEdges::Inclusive,
+            bat_start: None,
+            forgiveness: None,
+            ai_reaction: 8,
+        }
+    }
+}
+
+/// How close a classic match is to being decided.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(tag = "kind", content = "side", rename_all = "snake_case")]
+pub enum MatchPointState {
+    None,
+    /// The side wins with its next point.
+    MatchPoint(Side),
+    /// Tied with both sides at the limit or beyond, a lead is needed to win.
+    Deuce,
+    /// The side is in the lead after deuce and wins with its next point.
+    Advantage(Side),
+    /// Tied and whoever scores next wins.
+    DecidingPoint,
+    /// Tied at match point with `Rules::sudden_death`, the next point wins.
+    SuddenDeath,
+}
+
+impl Rules {
+    /// The rule deciding what hits and misses score under `scoring`.
+    pub fn scoring_rule(&self) -> Box<dyn ScoringRule> {
+        match self.scoring {
+            Scoring::Survival => Box::new(SurvivalScoring),
+            Scoring::Classic => Box::new(ClassicScoring {
+                score_limit: self.score_limit,
+                win_by: self.win_by,
+            }),
+        }
+    }
+
+    pub fn winner(&self, left: u16, right: u16) -> Option<Side> {
+        self.scoring_rule().winner(left, right)
+    }
+
+    pub fn match_point(&self, left: u16, right: u16) -> MatchPointState {
+        self.scoring_rule().match_point(left, right)
+    }
+}
+
+/// What a hit or a miss does to the game, as decided by a `ScoringRule`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Point {
+    /// The side whose score goes up, if any.
+    pub scorer: Option<Side>,
+    /// Whether the scorer's bats shrink with it.
+    pub shrinks_bats: bool,
+    pub next: Next,
+}
+
+/// How play goes on after a `Point`, unless it made a side win.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Next {
+    /// The ball stays in play.
+    Rally,
+    /// The ball is served again to the side that missed.
+    Serve,
+    /// The game ends, with a winner or without.
+    GameOver,
+}
+
+/// A ruleset, deciding the points scored by hits and misses and who wins.
+pub trait ScoringRule {
+    /// The ball was returned by a bat on `side`.
+    fn on_hit(&self, side: Side) -> Point;
+    /// The ball got past the bats on `side`.
+    fn on_miss(&self, side: Side) -> Point;
+    /// The side that won with the given scores, if any.
+    fn winner(&self, left: u16, right: u16) -> Option<Side>;
+    /// How close the given scores are to deciding the game.
+    fn match_point(&self, _left: u16, _right: u16) -> MatchPointState {
+        MatchPointState::None
+    }
+}
+
+/// Every return scores and shrinks the bats, the first miss ends the game.
+pub struct SurvivalScoring;
+
+impl ScoringRule for SurvivalScoring {
+    fn on_hit(&self, side: Side) -> Point {
+        Point {
+            scorer: Some(side),
+            shrinks_bats: true,
+            next: Next::Rally,
+        }
+    }
+
+    fn on_miss(&self, _side: Side) -> Point {
+        Point {
+            scorer: None,
+            shrinks_bats: false,
+            next: Next::GameOver,
+        }
+    }
+
+    fn winner(&self, _left: u16, _right: u16) -> Option<Side> {
+        None
+    }
+}
+
+/// How far `score` got towards `score_limit`, from 0 to 1. Scores past it,
+/// which winning by two can take, are as far as it.
+pub fn progress(score: u16, score_limit: u16) -> f64 {
+    (f64::from(score) / f64::from(score_limit.max(1))).min(1.)
+}
+
+/// A miss scores for the other side, the first to `score_limit` with a lead
+/// of `win_by` wins.
+pub struct ClassicScoring {
+    pub score_limit: u16,
+    pub win_by: u8,
+}
+
+impl ScoringRule for ClassicScoring {
+    fn on_hit(&self, _side: Side) -> Point {
+        Point {
+            scorer: None,
+            shrinks_bats: false,
+            next: Next::Rally,
+        }
+    }
+
+    fn on_miss(&self, side: Side) -> Point {
+        Point {
+            scorer: Some(side.other()),
+            shrinks_bats: false,
+            next: Next::Serve,
+        }
+    }
+
+    fn winner(&self, left: u16, right: u16) -> Option<Side> {
+        let wins = |score: u16, other: u16| {
+            score >= self.score_limit && score.saturating_sub(other) >= u16::from(self.win_by)
+        };
+        if wins(left, right) {
+            Some(Side::Left)
+        } else if wins(right, left) {
+            Some(Side::Right)
+        } else {
+            None
+        }
+    }
+
+    fn match_point(&self, left: u16, right: u16) -> MatchPointState {
+        if self.winner(left, right).is_some() {
+            return MatchPointState::None;
+        }
+        let left_wins_next = self.winner(left + 1, right).is_some();
+        let right_wins_next = self.winner(left, right + 1).is_some();
+        let past_limit = self.win_by > 1
+            && left + 1 >= self.score_limit
+            && right + 1 >= self.score_limit;
+        match (left_wins_next, right_wins_next) {
+            (true, true) => MatchPointState::DecidingPoint,
+            (true, false) if past_limit => MatchPointState::Advantage(Side::Left),
+            (false, true) if past_limit => MatchPointState::Advantage(Side::Right),
+            (true, false) => MatchPointState::MatchPoint(Side::Left),
+            (false, true) => MatchPointState::MatchPoint(Side::Right),
+            (false, false) if past_limit && left == right => MatchPointState::Deuce,
+            (false, false) => MatchPointState::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classic(score_limit: u16, win_by: u8) -> ClassicScoring {
+        ClassicScoring { score_limit, win_by }
+    }
+
+    #[test]
+    fn classic_games_are_won_at_the_limit_with_the_lead() {
+        let rule = classic(11, 1);
+        assert_eq!(rule.winner(11, 10), Some(Side::Left));
+        assert_eq!(rule.winner(3, 11), Some(Side::Right));
+        assert_eq!(rule.winner(10, 10), None);
+        let rule = classic(11, 2);
+        assert_eq!(rule.winner(11, 10), None);
+        assert_eq!(rule.winner(12, 10), Some(Side::Left));
+        assert_eq!(rule.winner(14, 16), Some(Side::Right));
+    }
+
+    #[test]
+    fn match_points_lead_to_deuce_and_advantage_when_winning_by_two() {
+        let rule = classic(11, 2);
+        assert_eq!(rule.match_point(10, 5), MatchPointState::MatchPoint(Side::Left));
+        assert_eq!(rule.match_point(5, 10), MatchPointState::MatchPoint(Side::Right));
+        assert_eq!(rule.match_point(10, 10), MatchPointState::Deuce);
+        assert_eq!(rule.match_point(11, 10), MatchPointState::Advantage(Side::Left));
+        assert_eq!(rule.match_point(12, 13), MatchPointState::Advantage(Side::Right));
+        assert_eq!(rule.match_point(9, 9), MatchPointState::None);
+        assert_eq!(rule.match_point(13, 11), MatchPointState::None);
+    }
+
+    #[test]
+    fn a_tie_before_the_limit_is_a_deciding_point_when_winning_by_one() {
+        let rule = classic(11, 1);
+        assert_eq!(rule.match_point(10, 10), MatchPointState::DecidingPoint);
+        assert_eq!(rule.match_point(10, 9), MatchPointState::MatchPoint(Side::Left));
+        assert_eq!(rule.match_point(11, 9), MatchPointState::None);
+    }
+
+    #[test]
+    fn survival_scores_returns_and_ends_on_a_miss() {
+        for side in Side::ALL {
+            let hit = Point { scorer: Some(side), shrinks_bats: true, next: Next::Rally };
+            assert_eq!(SurvivalScoring.on_hit(side), hit);
+            let miss = Point { scorer: None, shrinks_bats: false, next: Next::GameOver };
+            assert_eq!(SurvivalScoring.on_miss(side), miss);
+        }
+        assert_eq!(SurvivalScoring.winner(500, 0), None);
+        assert_eq!(SurvivalScoring.match_point(500, 500), MatchPointState::None);
+    }
+
+    #[test]
+    fn classic_scores_misses_for_the_other_side() {
+        let rule = classic(11, 2);
+        for side in Side::ALL {
+            let hit = Point { scorer: None, shrinks_bats: false, next: Next::Rally };
+            assert_eq!(rule.on_hit(side), hit);
+            let miss = Point { scorer: Some(side.other()), shrinks_bats: false, next: Next::Serve };
+            assert_eq!(rule.on_miss(side), miss);
+        }
+    }
+
+    #[test]
+    fn rules_pick_the_scoring_rule() {
+        let survival = Rules::default();
+        assert_eq!(survival.scoring_rule().on_miss(Side::Left).next, Next::GameOver);
+        assert_eq!(survival.winner(11, 0), None);
+        let classic = Rules { scoring: Scoring::Classic, score_limit: 3, ..Rules::default() };
+        assert_eq!(classic.scoring_rule().on_miss(Side::Left).next, Next::Serve);
+        assert_eq!(classic.winner(3, 0), Some(Side::Left));
+    }
+
+    #[test]
+    fn rules_recorded_before_the_bat_edges_replay_with_exclusive_edges() {
+        assert_eq!(Rules::default().bat_edges, BatEdges::Inclusive);
+        let mut recorded = serde_json::to_value(Rules::default()).unwrap();
+        recorded.as_object_mut().unwrap().remove("bat_edges");
+        let rules: Rules = serde_json::from_value(recorded).unwrap();
+        assert_eq!(rules.bat_edges, BatEdges::Exclusive);
+        assert_eq!("exclusive".parse(), Ok(BatEdges::Exclusive));
+        assert!("both".parse::<BatEdges>().is_err());
+    }
+
+    #[test]
+    fn rules_recorded_before_the_bat_start_start_the_lone_bat_low() {
+        assert_eq!(Rules::default().bat_start, None);
+        let mut recorded = serde_json::to_value(Rules::default()).unwrap();
+        recorded.as_object_mut().unwrap().remove("bat_start");
+        let rules: Rules = serde_json::from_value(recorded).unwrap();
+        assert_eq!(rules.bat_start, Some(600));
+    }
+
+    #[test]
+    fn score_bars_fill_up_to_the_limit() {
+        assert_eq!(progress(0, 4), 0.);
+        assert_eq!(progress(1, 4), 0.25);
+        assert_eq!(progress(4, 4), 1.);
+        assert_eq!(progress(6, 4), 1., "won by two past the limit");
+        assert_eq!(progress(3, 0), 1.);
+    }
+
+    #[test]
+    fn key_sets_overlap_when_the_sides_share_a_key() {
+        let bindings = |left, right| Bindings { left, right };
+        assert!(!Bindings::default().overlap(2));
+        let ijkl = bindings(KeySet::Ijkl, KeySet::Default);
+        assert!(!ijkl.overlap(1));
+        assert!(ijkl.overlap(2), "the right side's second bat is on i and k");
+        assert!(bindings(KeySet::Arrows, KeySet::Arrows).overlap(1));
+        assert_eq!(
+            KeySet::every_key(1),
+            ["w", "s", "o", "l", "ArrowUp", "ArrowDown", "i", "k"]
+        );
+        assert_eq!(KeySet::every_key(2).len(), 14);
+        assert_eq!("arrows".parse(), Ok(KeySet::Arrows));
+        assert!("wasd".parse::<KeySet>().is_err());
+    }
+}
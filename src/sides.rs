@@ -0,0 +1,124 @@
+use crate::{clock, Side};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A side is free for another page to claim after its page sent no input for
+/// this long, e.g. because it was closed without releasing it.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The pages playing each side with `--claim-sides`, by the token they were
+/// rendered with, the left side's first.
+#[derive(Clone, Default)]
+pub struct SideClaims {
+    holders: Arc<Mutex<[Option<Holder>; 2]>>,
+}
+
+struct Holder {
+    token: String,
+    last_input: Instant,
+}
+
+fn is_held(holder: &Option<Holder>, now: Instant) -> bool {
+    holder.as_ref().is_some_and(|holder| now - holder.last_input < IDLE_TIMEOUT)
+}
+
+fn is_held_by(holder: &Option<Holder>, token: &str) -> bool {
+    holder.as_ref().is_some_and(|holder| holder.token == token)
+}
+
+impl SideClaims {
+    /// Give the page with `token` the first side that's free and not among
+    /// `bot_sides`, `None` if both are taken.
+    pub fn claim(&self, token: &str, bot_sides: &[Side]) -> Option<Side> {
+        let mut holders = self.holders.lock().unwrap();
+        let now = clock::now();
+        let (side, holder) = Side::ALL
+            .into_iter()
+            .zip(holders.iter_mut())
+            .find(|(side, holder)| !is_held(holder, now) && !bot_sides.contains(side))?;
+        *holder = Some(Holder {
+            token: token.to_string(),
+            last_input: now,
+        });
+        Some(side)
+    }
+
+    /// The side the page with `token` plays, noting the input so that no
+    /// other page takes it over. Idle pages keep their side until one does.
+    pub fn side_of(&self, token: &str) -> Option<Side> {
+        let mut holders = self.holders.lock().unwrap();
+        let now = clock::now();
+        let (side, holder) = Side::ALL
+            .into_iter()
+            .zip(holders.iter_mut())
+            .find(|(_, holder)| is_held_by(holder, token))?;
+        holder.as_mut()?.last_input = now;
+        Some(side)
+    }
+
+    /// Give up the side of the page with `token`, returning `false` if it
+    /// had none.
+    pub fn release(&self, token: &str) -> bool {
+        let mut holders = self.holders.lock().unwrap();
+        let Some(holder) = holders.iter_mut().find(|holder| is_held_by(holder, token)) else {
+            return false;
+        };
+        *holder = None;
+        true
+    }
+
+    /// The sides a page plays.
+    pub fn claimed(&self) -> Vec<Side> {
+        let holders = self.holders.lock().unwrap();
+        Side::ALL
+            .into_iter()
+            .zip(holders.iter())
+            .filter(|(_, holder)| holder.is_some())
+            .map(|(side, _)| side)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_get_the_free_sides_in_turn() {
+        let claims = SideClaims::default();
+        assert_eq!(claims.claim("a", &[]), Some(Side::Left));
+        assert_eq!(claims.claim("b", &[]), Some(Side::Right));
+        assert_eq!(claims.claim("c", &[]), None);
+        assert!(claims.release("a"));
+        assert_eq!(claims.claim("c", &[]), Some(Side::Left));
+        assert_eq!(claims.side_of("c"), Some(Side::Left));
+        assert_eq!(claims.side_of("a"), None);
+    }
+
+    #[test]
+    fn pages_skip_the_sides_of_bots() {
+        let claims = SideClaims::default();
+        assert_eq!(claims.claim("a", &[Side::Left]), Some(Side::Right));
+        assert_eq!(claims.claim("b", &[Side::Left]), None);
+        assert_eq!(claims.claimed(), vec![Side::Right]);
+    }
+
+    #[test]
+    fn idle_pages_keep_their_side_until_another_page_claims_it() {
+        let claims = SideClaims::default();
+        assert_eq!(claims.claim("a", &[]), Some(Side::Left));
+        // As if the clock moved on past the timeout since the last input.
+        let idle = |claims: &SideClaims| {
+            let mut holders = claims.holders.lock().unwrap();
+            let holder = holders[0].as_mut().unwrap();
+            holder.last_input -= IDLE_TIMEOUT + Duration::from_secs(1);
+        };
+        idle(&claims);
+        assert_eq!(claims.side_of("a"), Some(Side::Left));
+        assert_eq!(claims.claimed(), vec![Side::Left]);
+        idle(&claims);
+        assert_eq!(claims.claim("b", &[]), Some(Side::Left));
+        assert_eq!(claims.side_of("a"), None);
+        assert_eq!(claims.side_of("b"), Some(Side::Left));
+    }
+}
@@ -0,0 +1,77 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the watchdog looks at the game loop.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Steps the game loop may miss before it counts as stuck.
+pub const MISSED_STEPS: u32 = 3;
+
+/// The game loop's heartbeat and what the watchdog did about it stopping.
+pub struct Watchdog {
+    started_at: Instant,
+    /// Milliseconds after `started_at` of the last physics step.
+    last_step: AtomicU64,
+    stalls: AtomicU64,
+    resets: AtomicU64,
+    render_restarts: AtomicU64,
+}
+
+#[derive(Clone, Copy, Serialize)]
+pub struct WatchdogStats {
+    /// How often the game should have been stepping but wasn't.
+    pub stalls: u64,
+    /// Stalls that nudging the game loop didn't end, so the game was reset.
+    pub resets: u64,
+    /// How often the render task failed and was restarted.
+    pub render_restarts: u64,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            last_step: AtomicU64::new(0),
+            stalls: AtomicU64::new(0),
+            resets: AtomicU64::new(0),
+            render_restarts: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Watchdog {
+    /// Called by the game loop after every step.
+    pub fn beat(&self) {
+        self.last_step.store(self.elapsed_ms(), Ordering::Relaxed);
+    }
+
+    /// Time since the last step.
+    pub fn since_beat(&self) -> Duration {
+        let since = self.elapsed_ms().saturating_sub(self.last_step.load(Ordering::Relaxed));
+        Duration::from_millis(since)
+    }
+
+    pub fn record_stall(&self) {
+        self.stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reset(&self) {
+        self.resets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_render_restart(&self) {
+        self.render_restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> WatchdogStats {
+        WatchdogStats {
+            stalls: self.stalls.load(Ordering::Relaxed),
+            resets: self.resets.load(Ordering::Relaxed),
+            render_restarts: self.render_restarts.load(Ordering::Relaxed),
+        }
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+}
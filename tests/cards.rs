@@ -0,0 +1,32 @@
+mod common;
+
+use common::Server;
+use std::time::Duration;
+
+#[test]
+fn a_card_is_drawn_once_and_then_served_from_the_card_dir() {
+    let dir = std::env::temp_dir().join(format!("rspong-card-test-{}", std::process::id()));
+    let server = Server::start(&["--card-dir", dir.to_str().unwrap()]);
+    let id = common::game_id(&server.get("/").text());
+    server.watch();
+    server.post("/keypress", "last_key=p");
+    // Nobody moves the bats, so the first miss ends the survival game.
+    server.wait_for(&format!("/result/{id}"), Duration::from_secs(20));
+
+    let drawn = server.get(&format!("/result/{id}/card.png"));
+    assert_eq!(drawn.status, 200);
+    assert_eq!(drawn.header("content-type"), Some("image/png"));
+    assert_eq!(
+        drawn.header("cache-control"),
+        Some("public, max-age=31536000, immutable")
+    );
+    assert!(drawn.body.starts_with(b"\x89PNG\r\n\x1a\n"));
+    let card = dir.join(format!("{id}.png"));
+    assert_eq!(std::fs::read(&card).unwrap(), drawn.body);
+
+    std::fs::write(&card, b"from the cache").unwrap();
+    let cached = server.get(&format!("/result/{id}/card.png"));
+    assert_eq!(cached.body, b"from the cache");
+    assert_eq!(server.get("/result/nope/card.png").status, 404);
+    std::fs::remove_dir_all(dir).unwrap();
+}
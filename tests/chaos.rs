@@ -0,0 +1,56 @@
+//! Needs `--features chaos`, for `POST /__chaos`.
+#![cfg(feature = "chaos")]
+
+mod common;
+
+use common::Server;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The parts of the board events swap in, which a keyframe redraws.
+const BOARD_EVENTS: [&str; 3] = ["bat_left", "bat_right", "ball"];
+
+fn poll(server: &Server, since: u64) -> Value {
+    serde_json::from_slice(&server.get(&format!("/poll?since={since}")).body).unwrap()
+}
+
+#[test]
+fn clients_missing_events_converge_on_the_final_board_via_keyframes() {
+    let server = Server::start(&["--scoring", "classic", "--score-limit", "3"]);
+    let chaos = server.post("/__chaos", "seed=250&broadcast_drop_percent=10");
+    assert_eq!(chaos.status, 200);
+    let id = common::game_id(&server.get("/").text());
+    let events = server.subscribe();
+    server.post("/keypress", "last_key=p");
+    // Nobody moves the bats, so every serve is a point until the game is won.
+    server.wait_for(&format!("/result/{id}"), Duration::from_secs(60));
+    std::thread::sleep(Duration::from_millis(500));
+
+    let received: Vec<_> = events.try_iter().collect();
+    let ids: Vec<u64> = received.iter().filter_map(|event| event.id).collect();
+    let gap = ids.windows(2).find(|pair| pair[1] != pair[0] + 1);
+    let seen_before_gap = gap.expect("a tenth of the events was dropped")[0];
+    let mut board = HashMap::new();
+    for event in &received {
+        board.insert(event.event.clone(), event.data.clone());
+    }
+    // Like a page catching up, ask for what came after the last event it has
+    // in order. The log moved on since, so that's a keyframe of the board.
+    let catch_up = poll(&server, seen_before_gap);
+    assert_eq!(catch_up["keyframe"], true, "{catch_up}");
+    for event in catch_up["events"].as_array().unwrap() {
+        let data = event["data"].as_str().unwrap().to_string();
+        board.insert(event["event"].as_str().unwrap().to_string(), data);
+    }
+
+    // A server that restarted has nothing to catch up from either.
+    let current = poll(&server, u64::MAX);
+    assert_eq!(current["keyframe"], true);
+    for event in current["events"].as_array().unwrap() {
+        let name = event["event"].as_str().unwrap();
+        if BOARD_EVENTS.contains(&name) {
+            assert_eq!(board[name], event["data"].as_str().unwrap(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Runs the server on a free port for the integration tests, with a small
+//! HTTP client, since the crate has no library to call into.
+// Every test uses a different part of it.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The server, stopped when this is dropped.
+pub struct Server {
+    child: Child,
+    pub address: String,
+}
+
+/// An event of `/game-sse`.
+pub struct SseEvent {
+    pub id: Option<u64>,
+    pub event: String,
+    pub data: String,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+impl Server {
+    /// Start the server with `args` and wait until it listens.
+    pub fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rspong"))
+            .args(["--listen", "127.0.0.1:0"])
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("server starts");
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let address = loop {
+            let mut line = String::new();
+            assert_ne!(stdout.read_line(&mut line).unwrap(), 0, "server exited");
+            if let Some(address) = line.trim_end().strip_prefix("Listening on http://") {
+                break address.to_string();
+            }
+        };
+        // Keep reading, so that the server never blocks on a full pipe.
+        thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+        Self { child, address }
+    }
+
+    pub fn get(&self, path: &str) -> Response {
+        self.request("GET", path, "")
+    }
+
+    pub fn post(&self, path: &str, form: &str) -> Response {
+        self.request("POST", path, form)
+    }
+
+    pub fn request(&self, method: &str, path: &str, form: &str) -> Response {
+        let mut stream = self.send(method, path, form);
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        parse(&response)
+    }
+
+    /// Send a request and hand over the connection, e.g. to read an event
+    /// stream as it goes.
+    pub fn send(&self, method: &str, path: &str, form: &str) -> TcpStream {
+        self.send_with(method, path, "", form)
+    }
+
+    /// Like `post`, with the bearer token of `--admin-token`.
+    pub fn post_as_admin(&self, path: &str, token: &str, form: &str) -> Response {
+        let authorization = format!("Authorization: Bearer {token}\r\n");
+        let mut stream = self.send_with("POST", path, &authorization, form);
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        parse(&response)
+    }
+
+    /// Send a request with `headers`, each line ending in `\r\n`.
+    fn send_with(&self, method: &str, path: &str, headers: &str, form: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n{headers}\
+             Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{form}",
+            self.address,
+            form.len()
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        stream
+    }
+
+    /// Open `/game-sse` and read it away on a thread, so that the game steps.
+    pub fn watch(&self) {
+        self.watch_at("/game-sse");
+    }
+
+    /// Like `watch`, with the event stream at `path`, e.g. a room's.
+    pub fn watch_at(&self, path: &str) {
+        let mut stream = self.send("GET", path, "");
+        thread::spawn(move || std::io::copy(&mut stream, &mut std::io::sink()));
+    }
+
+    /// Open `/game-sse` and hand over its events as they arrive, read on a
+    /// thread until the server goes away.
+    pub fn subscribe(&self) -> mpsc::Receiver<SseEvent> {
+        self.subscribe_at("/game-sse")
+    }
+
+    /// Like `subscribe`, with the event stream at `path`, e.g. a room's.
+    pub fn subscribe_at(&self, path: &str) -> mpsc::Receiver<SseEvent> {
+        let mut stream = BufReader::new(self.send("GET", path, ""));
+        let (events_tx, events) = mpsc::channel();
+        thread::spawn(move || {
+            let mut line = String::new();
+            // Skip the head, up to the empty line.
+            while stream.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let mut body = Vec::new();
+            loop {
+                line.clear();
+                if stream.read_line(&mut line).unwrap_or(0) == 0 {
+                    return;
+                }
+                let size = usize::from_str_radix(line.trim(), 16).unwrap();
+                let mut chunk = vec![0; size + 2];
+                if size == 0 || stream.read_exact(&mut chunk).is_err() {
+                    return;
+                }
+                body.extend(&chunk[..size]);
+                while let Some(end) = body.windows(2).position(|window| window == b"\n\n") {
+                    let block = String::from_utf8_lossy(&body[..end]).into_owned();
+                    body.drain(..end + 2);
+                    if let Some(event) = parse_event(&block) {
+                        if events_tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        events
+    }
+
+    /// Wait for `path` to answer with a 200 and return that response.
+    pub fn wait_for(&self, path: &str, timeout: Duration) -> Response {
+        let until = Instant::now() + timeout;
+        loop {
+            let response = self.get(path);
+            if response.status == 200 {
+                return response;
+            }
+            assert!(Instant::now() < until, "{path} still answers {}", response.status);
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn parse(response: &[u8]) -> Response {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .expect("response has a head");
+    let head = String::from_utf8_lossy(&response[..end]);
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(": "))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    let mut body = response[end + 4..].to_vec();
+    let chunked = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("transfer-encoding") && value == "chunked"
+    });
+    if chunked {
+        body = unchunk(&body);
+    }
+    Response {
+        status,
+        headers,
+        body,
+    }
+}
+
+/// The event of an SSE block, `None` for a comment such as a keep-alive.
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = SseEvent {
+        id: None,
+        event: String::new(),
+        data: String::new(),
+    };
+    let mut data = Vec::new();
+    for line in block.lines() {
+        let (field, value) = line.split_once(':')?;
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "id" => event.id = value.parse().ok(),
+            "event" => event.event = value.to_string(),
+            "data" => data.push(value),
+            _ => {}
+        }
+    }
+    event.data = data.join("\n");
+    (!event.event.is_empty()).then_some(event)
+}
+
+fn unchunk(mut chunked: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = chunked.windows(2).position(|window| window == b"\r\n").unwrap();
+        let size = std::str::from_utf8(&chunked[..line_end]).unwrap();
+        let size = usize::from_str_radix(size.trim(), 16).unwrap();
+        if size == 0 {
+            return body;
+        }
+        let start = line_end + 2;
+        body.extend(&chunked[start..start + size]);
+        chunked = &chunked[start + size + 2..];
+    }
+}
+
+/// The id of the game on the board, from the page's `data-game-id`.
+pub fn game_id(page: &str) -> String {
+    let start = page.find("data-game-id=\"").expect("page has a game id") + 14;
+    let length = page[start..].find('"').unwrap();
+    page[start..start + length].to_string()
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{Server, SseEvent};
+use serde_json::Value;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Open a room, returning the path it's under.
+fn open(server: &Server) -> String {
+    let created = server.post("/rooms", "");
+    assert_eq!(created.status, 303);
+    created.header("location").unwrap().to_string()
+}
+
+fn json(server: &Server, path: &str) -> Value {
+    serde_json::from_slice(&server.get(path).body).unwrap()
+}
+
+#[test]
+fn rooms_play_their_own_game() {
+    let server = Server::start(&["--max-rooms", "2"]);
+    let (playing, idle) = (open(&server), open(&server));
+    assert_ne!(playing, idle);
+    let full = server.post("/rooms", "");
+    assert_eq!((full.status, full.text().as_str()), (503, "too many rooms are open"));
+    assert_eq!(server.get("/room/nope/state").status, 404);
+
+    let idle_before = json(&server, &format!("{idle}/state"));
+    let board_before = json(&server, "/state");
+    let idle_events = server.subscribe_at(&format!("{idle}/game-sse"));
+    let playing_events = server.subscribe_at(&format!("{playing}/game-sse"));
+    server.post(&format!("{playing}/keypress"), "last_key=p");
+    std::thread::sleep(Duration::from_millis(300));
+    server.post(&format!("{playing}/keypress"), "last_key=w");
+    std::thread::sleep(Duration::from_millis(500));
+
+    let moved = json(&server, &format!("{playing}/state"));
+    assert_ne!(moved["left"], idle_before["left"]);
+    assert_eq!(json(&server, &format!("{idle}/state")), idle_before);
+    assert_eq!(json(&server, "/state")["left"], board_before["left"]);
+    let sent = |events: &mpsc::Receiver<SseEvent>| -> Vec<String> {
+        events.try_iter().map(|event| event.event).collect()
+    };
+    assert!(sent(&playing_events).contains(&"bat_left".to_string()));
+    let idle_sent = sent(&idle_events);
+    assert!(!idle_sent.iter().any(|event| event == "bat_left" || event == "ball"));
+
+    // The query goes along to the room's route: an id ahead of its log needs a
+    // keyframe, while no id is the start of it.
+    assert_eq!(json(&server, &format!("{idle}/poll?since={}", u64::MAX))["keyframe"], true);
+    assert_eq!(json(&server, &format!("{idle}/poll"))["keyframe"], false);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use common::Server;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+/// Events an outbox holds, see `src/outbox.rs`.
+const CAPACITY: u64 = 32;
+
+#[test]
+fn a_stalled_subscriber_sheds_ball_frames_and_catches_up() {
+    let args = ["--debug", "--admin-token", "t", "--scoring", "classic", "--score-limit", "99"];
+    let server = Server::start(&args);
+    let mut stalled = server.send("GET", "/game-sse", "");
+    server.post("/keypress", "last_key=p");
+    // Announcements fill the socket's buffers, as the subscriber doesn't read.
+    let announcement = format!("message={}", "x".repeat(64 * 1024));
+    let shedding = (0..400).find_map(|_| {
+        let posted = server.post_as_admin("/admin/announce", "t", &announcement);
+        assert_eq!(posted.status, 200);
+        let connections: serde_json::Value =
+            serde_json::from_slice(&server.get("/debug/connections").body).unwrap();
+        let connection = connections[0].clone();
+        (connection["dropped"].as_u64().unwrap() > 0).then_some(connection)
+    });
+    let connection = shedding.expect("the outbox never had to drop anything");
+    assert!(connection["queued"].as_u64().unwrap() <= CAPACITY + 2, "{connection}");
+    assert_eq!(server.get("/state").status, 200);
+
+    // Once it reads again, the backlog goes through and the ball moves on.
+    stalled.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let until = Instant::now() + Duration::from_secs(20);
+    let mut received = Vec::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        assert!(Instant::now() < until, "no ball after the announcements");
+        let read = stalled.read(&mut buffer).expect("the stream is still open");
+        assert_ne!(read, 0, "the stream ended");
+        received.extend(&buffer[..read]);
+        let text = String::from_utf8_lossy(&received);
+        let announced = text.rfind("event: announcement");
+        if announced.is_some_and(|at| text[at..].contains("event: ball")) {
+            break;
+        }
+        if let Some(at) = announced {
+            received.drain(..at);
+        }
+    }
+}